@@ -0,0 +1,224 @@
+//! Normalization of artist names, grouping the variants of an artist (e.g. `Björk`, `BJORK`, and
+//! `Björk feat. X`) in per-artist summaries
+
+use crate::DateEntry;
+
+/// Markers of featured artists, following the primary artist (matched ignoring ASCII case)
+const FEATURING: &[&str] = &[
+    " feat. ",
+    " feat ",
+    " ft. ",
+    " ft ",
+    " featuring ",
+    " (feat",
+    " [feat",
+    " (ft.",
+    " [ft.",
+    " (featuring",
+    " [featuring",
+];
+
+/// Lowercase letters with diacritics, and their plain equivalent
+const FOLDED: &[(&str, &str)] = &[
+    ("àáâãäåāăą", "a"),
+    ("æ", "ae"),
+    ("çćĉċč", "c"),
+    ("ďđð", "d"),
+    ("èéêëēĕėęě", "e"),
+    ("ĝğġģ", "g"),
+    ("ĥħ", "h"),
+    ("ìíîïĩīĭįı", "i"),
+    ("ĵ", "j"),
+    ("ķ", "k"),
+    ("ĺļľŀł", "l"),
+    ("ñńņňŉ", "n"),
+    ("òóôõöøōŏő", "o"),
+    ("œ", "oe"),
+    ("ŕŗř", "r"),
+    ("śŝşš", "s"),
+    ("ß", "ss"),
+    ("ţťŧ", "t"),
+    ("þ", "th"),
+    ("ùúûüũūŭůűų", "u"),
+    ("ŵ", "w"),
+    ("ýÿŷ", "y"),
+    ("źżž", "z"),
+];
+
+/// Returns the primary artist of the `name`, without any featured artists
+pub fn primary(name: &str) -> &str {
+    let padded = format!("{} ", name.to_ascii_lowercase());
+    let end = FEATURING
+        .iter()
+        .filter_map(|marker| padded.find(marker))
+        .min()
+        .unwrap_or(name.len());
+    name[..end].trim()
+}
+
+/// Returns the key grouping the variants of the artist `name`: the [`primary`] artist in
+/// lowercase, without diacritics, and with whitespace collapsed
+pub fn normalize(name: &str) -> String {
+    let mut folded = String::new();
+    for c in primary(name).chars().flat_map(char::to_lowercase) {
+        match FOLDED.iter().find(|(accented, _)| accented.contains(c)) {
+            Some((_, plain)) => folded.push_str(plain),
+            None => folded.push(c),
+        }
+    }
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Counts the `entries` per artist (grouped by [`normalize`]), most frequent first
+///
+/// Each artist is named by its most frequent spelling (of the [`primary`] artist).
+pub fn artist_counts(entries: &[DateEntry]) -> Vec<(String, usize)> {
+    group_by_artist(entries, |entry| &entry.artist)
+        .into_iter()
+        .map(|(artist, entries)| (artist, entries.len()))
+        .collect()
+}
+
+/// Tracks of an artist, per album
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArtistAlbums {
+    pub artist: String,
+    pub count: usize,
+    /// Album names (empty for singletons) with their track counts, most tracks first
+    pub albums: Vec<(String, usize)>,
+}
+/// Counts the `entries` per album artist (grouped by [`normalize`]) and album, most tracks first
+pub fn album_breakdown(entries: &[DateEntry]) -> Vec<ArtistAlbums> {
+    group_by_artist(entries, |entry| entry.album_key().0)
+        .into_iter()
+        .map(|(artist, entries)| {
+            let mut albums: Vec<(String, usize)> = vec![];
+            for entry in &entries {
+                match albums
+                    .iter_mut()
+                    .find(|(existing, _)| *existing == entry.album)
+                {
+                    Some((_, count)) => *count += 1,
+                    None => albums.push((entry.album.clone(), 1)),
+                }
+            }
+            albums.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+            ArtistAlbums {
+                artist,
+                count: entries.len(),
+                albums,
+            }
+        })
+        .collect()
+}
+
+/// Groups the `entries` by the normalized artist (see [`normalize`]) of each, most entries first
+///
+/// Each group is named by its most frequent spelling (of the [`primary`] artist).
+fn group_by_artist(
+    entries: &[DateEntry],
+    artist_of: impl Fn(&DateEntry) -> &str,
+) -> Vec<(String, Vec<&DateEntry>)> {
+    #[derive(Default)]
+    struct Group<'a> {
+        spellings: Vec<(&'a str, usize)>,
+        entries: Vec<&'a DateEntry>,
+    }
+    let mut groups: Vec<Group> = vec![];
+    let mut indices = std::collections::HashMap::new();
+    for entry in entries {
+        let spelling = primary(artist_of(entry));
+        let index = *indices.entry(normalize(spelling)).or_insert_with(|| {
+            groups.push(Group::default());
+            groups.len() - 1
+        });
+        let Group { spellings, entries } = &mut groups[index];
+        entries.push(entry);
+        match spellings
+            .iter_mut()
+            .find(|(existing, _)| *existing == spelling)
+        {
+            Some((_, count)) => *count += 1,
+            None => spellings.push((spelling, 1)),
+        }
+    }
+    // most frequent first, ties in order of appearance (newest first)
+    groups.sort_by_key(|group| std::cmp::Reverse(group.entries.len()));
+    groups
+        .into_iter()
+        .map(|group| {
+            let Group {
+                mut spellings,
+                entries,
+            } = group;
+            spellings.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+            let name = spellings.first().map_or("", |&(spelling, _)| spelling);
+            (name.to_owned(), entries)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_variants() {
+        assert_eq!(primary("Björk feat. Thom Yorke"), "Björk");
+        assert_eq!(primary("Daft Punk (FEAT. Pharrell)"), "Daft Punk");
+        assert_eq!(primary("Ft. Lauderdale"), "Ft. Lauderdale");
+        assert_eq!(normalize("BJÖRK"), "bjork");
+        assert_eq!(normalize("  Sigur  Rós ft Jónsi"), "sigur ros");
+        assert_eq!(normalize("Mötley Crüe"), normalize("motley crue"));
+
+        let entries: Vec<_> = [
+            "Björk",
+            "Beyoncé",
+            "Bjork feat. Thom Yorke",
+            "BJÖRK",
+            "Björk",
+        ]
+        .iter()
+        .map(|artist| {
+            DateEntry::try_from(format!("2024-03-01 10:00:00 {artist} - Album - Title"))
+                .expect("valid entry in test case")
+        })
+        .collect();
+        assert_eq!(
+            artist_counts(&entries),
+            [("Björk".to_owned(), 4), ("Beyoncé".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn breakdown_per_album() {
+        let entries: Vec<_> = [
+            "Sigur Rós - Takk - Glósóli",
+            "Sigur Ros - () - Untitled 1",
+            "Sigur Rós - Takk - Hoppípolla",
+            "Air - Moon Safari - La femme d'argent",
+        ]
+        .iter()
+        .map(|line| {
+            DateEntry::try_from(format!("2024-03-01 10:00:00 {line}"))
+                .expect("valid entry in test case")
+        })
+        .collect();
+
+        assert_eq!(
+            album_breakdown(&entries),
+            [
+                ArtistAlbums {
+                    artist: "Sigur Rós".to_owned(),
+                    count: 3,
+                    albums: vec![("Takk".to_owned(), 2), ("()".to_owned(), 1)],
+                },
+                ArtistAlbums {
+                    artist: "Air".to_owned(),
+                    count: 1,
+                    albums: vec![("Moon Safari".to_owned(), 1)],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::Timestamp;
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+/// Change of a value in the state file, appended to the audit log
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    pub file: PathBuf,
+    pub key: String,
+    /// Previous value at the key (the latest of a history), if any
+    pub old: Option<serde_json::Value>,
+    pub new: String,
+    /// Count of items after the new cutoff
+    pub final_count: Option<usize>,
+}
+impl AuditEntry {
+    /// Describes the change made at the `timestamp` (UTC) by the `user` on the `host`
+    pub fn to_json(
+        &self,
+        timestamp: Timestamp,
+        user: Option<&str>,
+        host: Option<&str>,
+    ) -> serde_json::Value {
+        let Self {
+            file,
+            key,
+            old,
+            new,
+            final_count,
+        } = self;
+        serde_json::json!({
+            "timestamp_utc": timestamp.to_string(),
+            "user": user,
+            "host": host,
+            "file": file.display().to_string(),
+            "key": key,
+            "old": old,
+            "new": new,
+            "final_count": final_count,
+        })
+    }
+}
+
+/// Appends a JSON line for each of the `entries` to the audit log at `path`, with the current
+/// time, user, and host
+///
+/// The lines are appended in a single write, to keep the lines of concurrent runs intact.
+pub fn append(path: &Path, entries: &[AuditEntry]) -> anyhow::Result<()> {
+    let timestamp = Timestamp::now();
+    let user = user();
+    let host = hostname();
+    let lines: Vec<_> = entries
+        .iter()
+        .map(|entry| entry.to_json(timestamp, user.as_deref(), host.as_deref()))
+        .collect();
+    append_lines(path, &lines)
+}
+
+/// Appends a JSON line recording the `event` (e.g. `shutdown` of a long-running mode) to the
+/// audit log at `path`, with the current time, user, and host
+pub fn append_event(path: &Path, event: &str) -> anyhow::Result<()> {
+    let line = serde_json::json!({
+        "timestamp_utc": Timestamp::now().to_string(),
+        "user": user(),
+        "host": hostname(),
+        "event": event,
+    });
+    append_lines(path, &[line])
+}
+
+fn append_lines(path: &Path, lines: &[serde_json::Value]) -> anyhow::Result<()> {
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(&serde_json::to_string(line)?);
+        text.push('\n');
+    }
+    let mut file = std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+fn user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .ok()
+}
+
+fn hostname() -> Option<String> {
+    let hostname = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())?;
+    let hostname = hostname.trim();
+    (!hostname.is_empty()).then(|| hostname.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_lines() {
+        let path = std::env::temp_dir().join(format!("audit_test_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let entry = AuditEntry {
+            file: PathBuf::from("cutoffs.json"),
+            key: "recent".to_owned(),
+            old: Some(serde_json::json!("2024-01-01")),
+            new: "2024-03-01".to_owned(),
+            final_count: Some(42),
+        };
+
+        append(&path, std::slice::from_ref(&entry)).expect("append first");
+        append(&path, std::slice::from_ref(&entry)).expect("append second");
+        append_event(&path, "shutdown").expect("append event");
+
+        let contents = std::fs::read_to_string(&path).expect("read audit log");
+        std::fs::remove_file(&path).expect("remove audit log");
+        assert_eq!(contents.lines().count(), 3);
+        let event: serde_json::Value =
+            serde_json::from_str(contents.lines().last().expect("event line")).expect("JSON");
+        assert_eq!(event["event"], "shutdown");
+
+        let timestamp = "2024-03-01 10:00:00".parse().expect("valid timestamp");
+        let line = entry.to_json(timestamp, Some("dj"), None);
+        assert_eq!(
+            serde_json::to_string(&line).expect("serialize"),
+            r#"{"timestamp_utc":"2024-03-01 10:00:00","user":"dj","host":null,"file":"cutoffs.json","key":"recent","old":"2024-01-01","new":"2024-03-01","final_count":42}"#
+        );
+    }
+}
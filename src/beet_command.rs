@@ -1,19 +1,226 @@
-use crate::DateEntry;
+use crate::{
+    count_cache::CountCache, date::Timestamp, parse_lines, Date, DateEntry, ItemId, ParsedEntries,
+};
 use anyhow::Context as _;
-use std::io::BufRead as _;
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    io::BufRead as _,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-pub struct BeetCommand<'a> {
+/// Executes the subprocesses constructed by [`BeetCommand`]
+///
+/// Allows substituting the real process execution (e.g. for asserting the exact invocations in tests)
+pub trait ProcessRunner {
+    fn output(&self, command: &mut std::process::Command) -> std::io::Result<std::process::Output>;
+    /// Runs the command as [`Self::output`], keeping only the first `max_lines` lines of stdout
+    ///
+    /// Implementations may stop the process once the lines are read (e.g. to avoid waiting for
+    /// the output of a large library), which is not reported as a failure.
+    fn output_head(
+        &self,
+        command: &mut std::process::Command,
+        max_lines: usize,
+    ) -> std::io::Result<std::process::Output> {
+        let mut output = self.output(command)?;
+        let head_len = output
+            .stdout
+            .split_inclusive(|&byte| byte == b'\n')
+            .take(max_lines)
+            .map(<[u8]>::len)
+            .sum();
+        output.stdout.truncate(head_len);
+        Ok(output)
+    }
+}
+
+/// Subcommands of `beet` not modifying the library
+pub const READ_ONLY_COMMANDS: &[&str] = &["list", "ls", "stats", "info", "fields", "version"];
+
+/// Runs subprocesses on the host system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemRunner;
+impl ProcessRunner for SystemRunner {
+    fn output(&self, command: &mut std::process::Command) -> std::io::Result<std::process::Output> {
+        command.output()
+    }
+    fn output_head(
+        &self,
+        command: &mut std::process::Command,
+        max_lines: usize,
+    ) -> std::io::Result<std::process::Output> {
+        use std::io::Read as _;
+        use std::process::Stdio;
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stderr = child.stderr.take().expect("piped stderr");
+        // read concurrently, to avoid blocking the process on a full stderr pipe
+        let stderr_reader = std::thread::spawn(move || {
+            let mut buffer = vec![];
+            stderr.read_to_end(&mut buffer).map(|_| buffer)
+        });
+
+        let mut stdout = std::io::BufReader::new(child.stdout.take().expect("piped stdout"));
+        let mut head = vec![];
+        let mut line_count = 0;
+        let mut progress = crate::progress::Progress::start("fetch");
+        while line_count < max_lines && stdout.read_until(b'\n', &mut head)? > 0 {
+            line_count += 1;
+            progress.update(line_count);
+        }
+        progress.finish(line_count);
+        let status = match child.try_wait()? {
+            Some(status) => status,
+            None if line_count == max_lines => {
+                // remaining output is not needed
+                child.kill()?;
+                child.wait()?;
+                std::process::ExitStatus::default()
+            }
+            None => child.wait()?,
+        };
+        drop(stdout);
+        let stderr = stderr_reader.join().expect("stderr reader panicked")?;
+        Ok(std::process::Output {
+            status,
+            stdout: head,
+            stderr,
+        })
+    }
+}
+
+/// Runs subprocesses on the host system in their own process group, so a terminal interrupt
+/// (Ctrl-C) reaches only this process, e.g. to finish an in-progress query before shutting down
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcessGroupRunner;
+#[cfg(unix)]
+impl ProcessGroupRunner {
+    /// Spawns the command in its own process group, with stdout and stderr piped
+    pub fn spawn(command: &mut std::process::Command) -> std::io::Result<std::process::Child> {
+        use std::os::unix::process::CommandExt as _;
+        use std::process::Stdio;
+
+        command
+            .process_group(0)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+}
+#[cfg(unix)]
+impl ProcessRunner for ProcessGroupRunner {
+    fn output(&self, command: &mut std::process::Command) -> std::io::Result<std::process::Output> {
+        use std::os::unix::process::CommandExt as _;
+        SystemRunner.output(command.process_group(0))
+    }
+    fn output_head(
+        &self,
+        command: &mut std::process::Command,
+        max_lines: usize,
+    ) -> std::io::Result<std::process::Output> {
+        use std::os::unix::process::CommandExt as _;
+        SystemRunner.output_head(command.process_group(0), max_lines)
+    }
+}
+
+/// Runs subprocesses as [`ProcessGroupRunner`], killing the process group (with any processes
+/// started by `beet`) once the timeout elapses, failing with [`std::io::ErrorKind::TimedOut`]
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug)]
+pub struct TimeoutRunner {
+    timeout: std::time::Duration,
+}
+#[cfg(unix)]
+impl TimeoutRunner {
+    pub fn new(timeout: std::time::Duration) -> Self {
+        Self { timeout }
+    }
+}
+#[cfg(unix)]
+impl ProcessRunner for TimeoutRunner {
+    fn output(&self, command: &mut std::process::Command) -> std::io::Result<std::process::Output> {
+        use std::io::Read;
+
+        let mut child = ProcessGroupRunner::spawn(command)?;
+        // read concurrently, to avoid blocking the process on a full pipe
+        let read_all = |mut pipe: Box<dyn Read + Send>| {
+            std::thread::spawn(move || {
+                let mut buffer = vec![];
+                pipe.read_to_end(&mut buffer).map(|_| buffer)
+            })
+        };
+        let stdout = read_all(Box::new(child.stdout.take().expect("piped stdout")));
+        let stderr = read_all(Box::new(child.stderr.take().expect("piped stderr")));
+        let deadline = std::time::Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if std::time::Instant::now() >= deadline {
+                let process_group = libc::pid_t::try_from(child.id()).expect("valid pid");
+                // SAFETY: signals only the process group of the child, which is not reaped
+                unsafe { libc::kill(-process_group, libc::SIGKILL) };
+                child.wait()?;
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("killed after the timeout of {:?}", self.timeout),
+                ));
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        let join = |reader: std::thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+            reader.join().expect("pipe reader panicked")
+        };
+        Ok(std::process::Output {
+            status,
+            stdout: join(stdout)?,
+            stderr: join(stderr)?,
+        })
+    }
+}
+
+pub struct BeetCommand<'a, R = SystemRunner> {
+    /// Executes the constructed commands
+    runner: R,
     /// Path to the `beet` command from the package `beets`
     beet_command: std::path::PathBuf,
+    /// Arguments preceding each subcommand (e.g. `-m beets` for `python3 -m beets`)
+    command_args: Vec<String>,
     /// List of argument tokens that were originally comma-separated
     ///
     /// Example:
     ///  - desired args "arg1" "arg2," "arg3" "arg4"
     ///  - argument TIMELESS_ARGS="arg1\narg2,\narg3\narg4"
     ///  - leads to this representation: &[ &["arg1", "arg2"], &["arg3", "arg4"] ]
-    timeless_filter_sets: Vec<Vec<&'a str>>,
+    timeless_filter_sets: Vec<Vec<Cow<'a, str>>>,
     /// truncates results to the specified entry count
     max_entries: usize,
+    /// Date-like field for sorting, display, and range queries (e.g. `added`, `mtime`)
+    date_field: String,
+    /// Additional fields fetched with each entry (e.g. `play_count` from a plugin)
+    extra_fields: Vec<String>,
+    /// Set once a fetch failed with the extra fields, to fetch without them from then on
+    extra_fields_failed: Arc<AtomicBool>,
+    /// Earliest and latest dates of the fetched (and counted) items, if bounded
+    date_bounds: (Option<Date>, Option<Date>),
+    /// Counts of previous identical count queries, if caching
+    count_cache: Option<Arc<CountCache>>,
+    /// Minimum rating of the items, if limited by [`Self::with_min_rating`]
+    min_rating: Option<f64>,
+    /// Library database read directly for the unfiltered fetch and count queries, if enabled
+    #[cfg(feature = "db")]
+    library_db: Option<Arc<crate::db::LibraryDb>>,
 }
 impl BeetCommand<'_> {
     pub fn new(
@@ -21,117 +228,1311 @@ impl BeetCommand<'_> {
         timeless_args: &str,
         max_entries: usize,
     ) -> BeetCommand<'_> {
-        let timeless_filter_sets: Vec<Vec<_>> = timeless_args
-            .split(',')
-            .filter_map(|filter_set| {
-                let elems: Vec<_> = filter_set.lines().collect();
-                if elems.is_empty() {
-                    None
-                } else {
-                    Some(elems)
-                }
-            })
+        let timeless_filter_sets = parse_filter_sets(timeless_args)
+            .into_iter()
+            .map(|filter_set| filter_set.into_iter().map(Cow::Borrowed).collect())
             .collect();
 
         BeetCommand {
+            runner: SystemRunner,
             beet_command,
+            command_args: vec![],
             timeless_filter_sets,
             max_entries,
+            date_field: DEFAULT_DATE_FIELD.to_owned(),
+            extra_fields: vec![],
+            extra_fields_failed: Arc::default(),
+            date_bounds: (None, None),
+            count_cache: None,
+            min_rating: None,
+            #[cfg(feature = "db")]
+            library_db: None,
         }
     }
 }
-impl BeetCommand<'_> {
-    fn new_list_command(&self, extra_filter: Option<&str>) -> std::process::Command {
-        let mut command = std::process::Command::new(&self.beet_command);
-        command.arg("list");
+/// Splits the arguments into comma-separated filter sets of newline-separated atoms
+fn parse_filter_sets(args: &str) -> Vec<Vec<&str>> {
+    args.split(',')
+        .filter_map(|filter_set| {
+            let elems: Vec<_> = filter_set.lines().collect();
+            if elems.is_empty() {
+                None
+            } else {
+                Some(elems)
+            }
+        })
+        .collect()
+}
 
-        // add timeless_args with extra_filter (if any) added to each clause
-        let mut prev_arg = None;
-        for filter_set in &self.timeless_filter_sets {
-            if let Some(prev_arg) = prev_arg {
-                // filter_set will follow, append comma to last arg
-                command.arg(&format!("{prev_arg},"));
+/// Converts a filter file (one atom per line, with blank lines between the filter sets) to the
+/// format of the timeless args, ignoring lines starting with `#`
+pub fn filter_file_args(text: &str) -> String {
+    let mut filter_sets = vec![vec![]];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            if filter_sets
+                .last()
+                .is_some_and(|set: &Vec<_>| !set.is_empty())
+            {
+                filter_sets.push(vec![]);
             }
+        } else if !line.starts_with('#') {
+            filter_sets.last_mut().expect("nonempty").push(line);
+        }
+    }
+    filter_sets
+        .iter()
+        .filter(|filter_set| !filter_set.is_empty())
+        .map(|filter_set| filter_set.join("\n"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-            let (filter_set, last): (&[&str], &str) = if let Some(extra_filter) = extra_filter {
-                (filter_set, extra_filter)
-            } else {
-                let (last, rest) = filter_set.split_last().expect("nonempty filter set");
-                (rest, last)
+/// Suspicious atom in the timeless args, from [`lint_filter_args`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterLint {
+    /// Number of the filter set (from 1)
+    pub filter_set: usize,
+    pub atom: String,
+    pub problem: &'static str,
+}
+impl std::fmt::Display for FilterLint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            filter_set,
+            atom,
+            problem,
+        } = self;
+        write!(f, "filter set {filter_set}, atom {atom:?}: {problem}")
+    }
+}
+
+/// Checks the timeless args for atoms that are likely mistakes (e.g. empty or with stray commas)
+pub fn lint_filter_args(timeless_args: &str) -> Vec<FilterLint> {
+    let filter_sets: Vec<_> = timeless_args.split(',').collect();
+    let mut lints = vec![];
+    for (index, filter_set) in filter_sets.iter().enumerate() {
+        let mut lint = |atom: &str, problem| {
+            lints.push(FilterLint {
+                filter_set: index + 1,
+                atom: atom.to_owned(),
+                problem,
+            });
+        };
+        if filter_set.is_empty() {
+            if filter_sets.len() > 1 {
+                lint("", "empty filter set (stray comma), ignored");
+            }
+            continue;
+        }
+        for (line, atom) in filter_set.lines().enumerate() {
+            // the line break after a comma (as in the documented format) is an empty atom
+            let after_comma = index > 0 && line == 0;
+            if atom.trim().is_empty() {
+                if !after_comma {
+                    lint(atom, "empty atom, passed to beet as an empty argument");
+                }
+                continue;
+            }
+            if atom.trim() != atom {
+                lint(atom, "leading or trailing whitespace, part of the query");
+            }
+            let query = atom.trim().trim_start_matches(['^', '-']);
+            let Some((field, value)) = query.split_once(':') else {
+                continue;
             };
-            for filter_arg in filter_set {
-                command.arg(filter_arg);
+            let value = value.strip_prefix(':').unwrap_or(value);
+            if field.is_empty() {
+                lint(atom, "empty field before the colon");
+            } else if field.contains(char::is_whitespace) {
+                lint(atom, "whitespace in the field name");
+            }
+            if value.contains(':') {
+                lint(
+                    atom,
+                    "unquoted colon in the value, beets splits the field at the first colon only",
+                );
+            }
+        }
+    }
+    lints
+}
+
+/// Negates the query atom, e.g. `genre:classical` to `^genre:classical` (and back)
+pub fn negate_atom(atom: &str) -> String {
+    match atom.strip_prefix('^').or_else(|| atom.strip_prefix('-')) {
+        Some(negated) if !negated.is_empty() => negated.to_owned(),
+        _ => format!("^{atom}"),
+    }
+}
+
+/// Query atom for the items rated at least `min_rating`
+fn rating_atom(min_rating: f64) -> String {
+    format!("rating:{min_rating}..")
+}
+
+/// Returns the query atoms excluding the items with the `ids`, e.g. `^id:12 ^id:15`
+pub fn exclude_ids_query(ids: impl IntoIterator<Item = ItemId>) -> String {
+    ids.into_iter()
+        .map(|id| negate_atom(&format!("id:{id}")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the filter sets matching the items that match none of the `filter_sets`
+///
+/// Beets queries are an OR of filter sets, each an AND of atoms. Excluding every filter set
+/// requires any one negated atom from each set, so the result has one filter set per combination.
+pub fn negate_filter_sets(filter_sets: &[Vec<&str>]) -> Vec<Vec<String>> {
+    filter_sets
+        .iter()
+        .fold(vec![vec![]], |combinations, filter_set| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    filter_set.iter().map(move |atom| {
+                        let mut combination: Vec<String> = combination.clone();
+                        combination.push(negate_atom(atom));
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+/// Item matched by a query (e.g. a dedicated `title:beet_smart_cutoff` item), storing the cutoff
+/// in a flexible attribute to keep the state in the beets library instead of a JSON file
+#[derive(Clone, Debug)]
+pub struct Sentinel {
+    /// Query atoms matching exactly one item
+    pub query: Vec<String>,
+    /// Flexible attribute storing the cutoff
+    pub field: String,
+}
+
+/// Items matched by one of the OR'd filter sets of the timeless args
+#[derive(Clone, Debug)]
+pub struct FilterSetItems {
+    /// Atoms of the filter set, separated by spaces
+    pub label: String,
+    pub ids: HashSet<ItemId>,
+}
+/// Count of the items matched by a filter set, in a selection matched by several filter sets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterSetShare {
+    pub label: String,
+    /// Items matched by the filter set
+    pub count: usize,
+    /// Items matched by no other filter set
+    pub exclusive: usize,
+}
+/// Tags each item with the filter sets matching it, returning the share of each filter set and
+/// the total count of distinct items
+pub fn filter_set_composition(filter_sets: &[FilterSetItems]) -> (Vec<FilterSetShare>, usize) {
+    let mut matching_sets: std::collections::HashMap<ItemId, Vec<usize>> =
+        std::collections::HashMap::new();
+    for (index, filter_set) in filter_sets.iter().enumerate() {
+        for &id in &filter_set.ids {
+            matching_sets.entry(id).or_default().push(index);
+        }
+    }
+    let shares = filter_sets
+        .iter()
+        .enumerate()
+        .map(|(index, filter_set)| FilterSetShare {
+            label: filter_set.label.clone(),
+            count: filter_set.ids.len(),
+            exclusive: matching_sets
+                .values()
+                .filter(|sets| sets[..] == [index])
+                .count(),
+        })
+        .collect();
+    (shares, matching_sets.len())
+}
+
+/// Field recording when an item was added to the library
+pub const DEFAULT_DATE_FIELD: &str = "added";
+impl<'a, R> BeetCommand<'a, R> {
+    /// Replaces the date-like field (default [`DEFAULT_DATE_FIELD`])
+    ///
+    /// The field must be formatted as `YYYY-MM-DD HH:MM:SS` by beets (e.g. a date type field, with
+    /// the default `time_format`), and support range queries such as `FIELD:2024-01-01..`
+    pub fn with_date_field(self, date_field: String) -> Self {
+        Self { date_field, ..self }
+    }
+    /// Fetches the additional `extra_fields` with each entry, in [`DateEntry::extra`]
+    ///
+    /// Fields may come from plugins (e.g. `play_count` from `mpdstats`). When the fetch fails with
+    /// the fields (e.g. a plugin that is not enabled), it is retried without them.
+    pub fn with_extra_fields(self, extra_fields: Vec<String>) -> Self {
+        Self {
+            extra_fields,
+            ..self
+        }
+    }
+    /// Minimum rating of the items, if limited by [`Self::with_min_rating`]
+    pub fn min_rating(&self) -> Option<f64> {
+        self.min_rating
+    }
+    /// Additional fields fetched with each entry, empty once dropped after a failed fetch
+    pub fn extra_fields(&self) -> &[String] {
+        if self.extra_fields_failed.load(Ordering::Relaxed) {
+            &[]
+        } else {
+            &self.extra_fields
+        }
+    }
+    /// Limits the fetched (and counted) items to those added between `since` and `until`
+    /// (inclusive), to reduce the fetch size when the cutoff is known to be within the range
+    ///
+    /// Items newer than `until` are left out of the counts and of the queries after a cutoff (e.g.
+    /// the query recorded in a script), as if not in the library.
+    pub fn with_date_bounds(self, since: Option<Date>, until: Option<Date>) -> Self {
+        Self {
+            date_bounds: (since, until),
+            ..self
+        }
+    }
+    /// Reuses the results of identical count queries from the `count_cache` if specified (shared,
+    /// e.g. with the commands from [`Self::split_filter_sets`])
+    pub fn with_count_cache(self, count_cache: Option<Arc<CountCache>>) -> Self {
+        Self {
+            count_cache,
+            ..self
+        }
+    }
+    /// Excludes the items matching `exclude_args` (in the same format as the timeless args), e.g.
+    /// the filters of another playlist for an "everything except" playlist
+    ///
+    /// See [`negate_filter_sets`] for the expansion of the negated filter sets.
+    pub fn with_excluded(self, exclude_args: &str) -> Self {
+        let excluded = parse_filter_sets(exclude_args);
+        if excluded.is_empty() {
+            return self;
+        }
+        let negated = negate_filter_sets(&excluded);
+        let base_filter_sets = if self.timeless_filter_sets.is_empty() {
+            vec![vec![]]
+        } else {
+            self.timeless_filter_sets
+        };
+        let timeless_filter_sets = base_filter_sets
+            .iter()
+            .flat_map(|base| {
+                negated.iter().map(move |negated| {
+                    base.iter()
+                        .cloned()
+                        .chain(negated.iter().cloned().map(Cow::Owned))
+                        .collect()
+                })
+            })
+            .collect();
+        Self {
+            timeless_filter_sets,
+            ..self
+        }
+    }
+    /// Limits the fetched and counted items to those rated at least `min_rating` (e.g. by the
+    /// `mpdstats` plugin) if specified, appending the range atom `rating:MIN..` to each filter set
+    ///
+    /// Unrated items do not match the range, so they are excluded too.
+    pub fn with_min_rating(self, min_rating: Option<f64>) -> Self {
+        let Some(min_rating) = min_rating else {
+            return self;
+        };
+        let atom = rating_atom(min_rating);
+        let base_filter_sets = if self.timeless_filter_sets.is_empty() {
+            vec![vec![]]
+        } else {
+            self.timeless_filter_sets
+        };
+        let timeless_filter_sets = base_filter_sets
+            .into_iter()
+            .map(|mut filter_set| {
+                filter_set.push(Cow::Owned(atom.clone()));
+                filter_set
+            })
+            .collect();
+        Self {
+            timeless_filter_sets,
+            min_rating: Some(min_rating),
+            ..self
+        }
+    }
+    /// Reads the `library_db` directly for fetching and counting the items, instead of spawning
+    /// `beet list`
+    ///
+    /// Filtered queries (by the timeless args, [`Self::with_excluded`], or
+    /// [`Self::with_min_rating`]) still run `beet` with a notice, as beets query atoms are not
+    /// translated to SQL.
+    #[cfg(feature = "db")]
+    pub fn with_library_db(self, library_db: Arc<crate::db::LibraryDb>) -> Self {
+        if !self.timeless_filter_sets.is_empty() {
+            println!(
+                "[filters ({}) are only applied by `beet`, querying it instead of reading {}]",
+                self.filter_label(),
+                library_db.path().display()
+            );
+        }
+        Self {
+            library_db: Some(library_db),
+            ..self
+        }
+    }
+    /// Sets the arguments preceding each subcommand, e.g. `-m beets` when the command is `python3`
+    pub fn with_command_args(self, command_args: Vec<String>) -> Self {
+        Self {
+            command_args,
+            ..self
+        }
+    }
+    /// Splits into one command for each of the comma-separated filter sets of the timeless args
+    pub fn split_filter_sets(&self) -> Vec<Self>
+    where
+        R: Clone,
+    {
+        self.timeless_filter_sets
+            .iter()
+            .map(|filter_set| self.with_filter_sets(vec![filter_set.clone()]))
+            .collect()
+    }
+    /// Returns the command for the filter sets of other `timeless_args` (e.g. of a config file
+    /// profile), dropping the current ones including [`Self::with_excluded`] and
+    /// [`Self::with_min_rating`]
+    pub fn with_timeless_args(&self, timeless_args: &str) -> Self
+    where
+        R: Clone,
+    {
+        let filter_sets = parse_filter_sets(timeless_args)
+            .into_iter()
+            .map(|filter_set| {
+                filter_set
+                    .into_iter()
+                    .map(|atom| Cow::Owned(atom.to_owned()))
+                    .collect()
+            })
+            .collect();
+        Self {
+            min_rating: None,
+            ..self.with_filter_sets(filter_sets)
+        }
+    }
+    /// Returns the command for the items rated below the [`Self::with_min_rating`] (or unrated),
+    /// excluded from the other queries
+    pub fn below_min_rating(&self) -> Option<Self>
+    where
+        R: Clone,
+    {
+        let atom = rating_atom(self.min_rating?);
+        let negated = negate_atom(&atom);
+        let filter_sets = self
+            .timeless_filter_sets
+            .iter()
+            .map(|filter_set| {
+                filter_set
+                    .iter()
+                    .map(|elem| {
+                        if *elem == atom {
+                            Cow::Owned(negated.clone())
+                        } else {
+                            elem.clone()
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(Self {
+            min_rating: None,
+            ..self.with_filter_sets(filter_sets)
+        })
+    }
+    fn with_filter_sets(&self, timeless_filter_sets: Vec<Vec<Cow<'a, str>>>) -> Self
+    where
+        R: Clone,
+    {
+        Self {
+            runner: self.runner.clone(),
+            beet_command: self.beet_command.clone(),
+            command_args: self.command_args.clone(),
+            timeless_filter_sets,
+            max_entries: self.max_entries,
+            date_field: self.date_field.clone(),
+            extra_fields: self.extra_fields.clone(),
+            extra_fields_failed: Arc::clone(&self.extra_fields_failed),
+            date_bounds: self.date_bounds,
+            count_cache: self.count_cache.clone(),
+            min_rating: self.min_rating,
+            #[cfg(feature = "db")]
+            library_db: self.library_db.clone(),
+        }
+    }
+    /// Atoms of each filter set (including those excluding the [`Self::with_excluded`] items)
+    pub fn filter_sets(&self) -> Vec<Vec<&str>> {
+        self.timeless_filter_sets
+            .iter()
+            .map(|filter_set| filter_set.iter().map(|atom| atom.as_ref()).collect())
+            .collect()
+    }
+    /// Atoms of the filter set(s), separated by spaces (and commas between filter sets)
+    pub fn filter_label(&self) -> String {
+        let filter_sets: Vec<_> = self
+            .timeless_filter_sets
+            .iter()
+            .map(|filter_set| filter_set.join(" "))
+            .collect();
+        filter_sets.join(", ")
+    }
+    /// Replaces the [`ProcessRunner`] used to execute commands
+    pub fn with_runner<R2>(self, runner: R2) -> BeetCommand<'a, R2> {
+        let Self {
+            runner: _,
+            beet_command,
+            command_args,
+            timeless_filter_sets,
+            max_entries,
+            date_field,
+            extra_fields,
+            extra_fields_failed,
+            date_bounds,
+            count_cache,
+            min_rating,
+            #[cfg(feature = "db")]
+            library_db,
+        } = self;
+        BeetCommand {
+            runner,
+            beet_command,
+            command_args,
+            timeless_filter_sets,
+            max_entries,
+            date_field,
+            extra_fields,
+            extra_fields_failed,
+            date_bounds,
+            count_cache,
+            min_rating,
+            #[cfg(feature = "db")]
+            library_db,
+        }
+    }
+}
+impl<R: ProcessRunner> BeetCommand<'_, R> {
+    /// Query atom for items on or after the `date`
+    fn range_after(&self, date: Date) -> String {
+        date.query_range_after(&self.date_field)
+    }
+    /// Query atom for items on or after the `date` within the [`Self::with_date_bounds`], e.g.
+    /// `added:2024-01-01..2024-03-01` when bounded by an `until` date
+    fn range_after_within_bounds(&self, date: Date) -> String {
+        match self.date_bounds {
+            (None, None) => self.range_after(date),
+            (since, until) => {
+                let from = since.map_or(date, |since| since.max(date));
+                self.range_between(Some(from), until)
             }
-            // defer the "last" arg, to append comma if more arguments follow
-            prev_arg = Some(last);
         }
-        if let Some(prev_arg) = prev_arg {
-            // final filter_set, no trailing comma
-            command.arg(prev_arg);
+    }
+    /// Query atom for the [`Self::with_date_bounds`] range, if bounded
+    fn bounds_range(&self) -> Option<String> {
+        match self.date_bounds {
+            (None, None) => None,
+            (since, until) => Some(self.range_between(since, until)),
+        }
+    }
+    /// Query atom for items between the dates (inclusive), open-ended when unspecified
+    fn range_between(&self, from: Option<Date>, to: Option<Date>) -> String {
+        let format_bound = |bound: Option<Date>| bound.map(|date| date.to_string());
+        format!(
+            "{field}:{from}..{to}",
+            field = self.date_field,
+            from = format_bound(from).unwrap_or_default(),
+            to = format_bound(to).unwrap_or_default(),
+        )
+    }
+    /// Format for the lines parsed by [`DateEntry::parse_fields`]
+    fn entry_format(&self) -> String {
+        std::iter::once("id")
+            .chain(std::iter::once(self.date_field.as_str()))
+            .chain(DateEntry::FIELDS.iter().copied())
+            .chain(self.extra_fields().iter().map(String::as_str))
+            .map(|field| format!("${field}"))
+            .collect::<Vec<_>>()
+            .join("\t")
+    }
+    /// Sort atom for newest items first
+    fn sort_newest_first(&self) -> String {
+        format!("{field}-", field = self.date_field)
+    }
+
+    fn new_list_command(&self, extra_filters: &[&str]) -> std::process::Command {
+        self.new_query_command(&["list"], extra_filters)
+    }
+    /// Constructs the `beet` command, with any leading [`Self::with_command_args`]
+    fn new_command(&self) -> std::process::Command {
+        let mut command = std::process::Command::new(&self.beet_command);
+        command.args(&self.command_args);
+        command
+    }
+    /// Constructs the `beet` subcommand (with leading arguments) followed by the query
+    fn new_query_command(
+        &self,
+        subcommand_args: &[&str],
+        extra_filters: &[&str],
+    ) -> std::process::Command {
+        let mut command = self.new_command();
+        command.args(subcommand_args);
+
+        // add timeless_args with extra_filters (if any) added to each clause
+        let clauses: Vec<Vec<&str>> = if self.timeless_filter_sets.is_empty() {
+            // only filters are the extra_filters (if any)
+            vec![extra_filters.to_vec()]
         } else {
-            // no timeless_args
-            if let Some(extra_filter) = extra_filter {
-                // only filter is the extra_filter, so add it
-                command.arg(extra_filter);
+            self.timeless_filter_sets
+                .iter()
+                .map(|filter_set| {
+                    filter_set
+                        .iter()
+                        .map(AsRef::as_ref)
+                        .chain(extra_filters.iter().copied())
+                        .collect()
+                })
+                .collect()
+        };
+        let clause_count = clauses.len();
+        for (index, clause) in clauses.into_iter().enumerate() {
+            let Some((last, rest)) = clause.split_last() else {
+                continue;
+            };
+            command.args(rest);
+            if index + 1 < clause_count {
+                // another clause will follow, append comma to last arg
+                command.arg(format!("{last},"));
+            } else {
+                command.arg(last);
             }
         }
         command
     }
 
     pub fn query_timeless(&self) -> anyhow::Result<Vec<DateEntry>> {
-        let current_output = self
-            .new_list_command(None)
-            .arg("added-")
+        self.query_timeless_limited(self.max_entries)
+    }
+
+    /// Queries the newest entries, truncated to `max_entries` (instead of the configured limit)
+    pub fn query_timeless_limited(&self, max_entries: usize) -> anyhow::Result<Vec<DateEntry>> {
+        let current_output = self.fetch_timeless()?;
+        let parsed = parse_timeless(&current_output, max_entries, false)?;
+        Ok(parsed.entries)
+    }
+
+    /// Runs the query for [`Self::query_timeless`], returning the raw output for [`parse_timeless`]
+    ///
+    /// With [progress events](crate::progress) enabled, the output is read as it arrives.
+    pub fn fetch_timeless(&self) -> anyhow::Result<Vec<u8>> {
+        if let Some(lines) = self.fetch_direct(usize::MAX) {
+            return lines.context("reading items from the library database");
+        }
+        if crate::progress::is_enabled() {
+            return self.fetch_timeless_head(usize::MAX);
+        }
+        self.or_without_extra_fields(|| self.run_checked(&mut self.new_timeless_command()))
+            .context("beet ls [current_args]")
+    }
+
+    /// Runs the query for [`Self::fetch_timeless`], stopping once `max_lines` lines are output
+    /// (e.g. one more than the entries to parse, to detect truncation)
+    pub fn fetch_timeless_head(&self, max_lines: usize) -> anyhow::Result<Vec<u8>> {
+        if let Some(lines) = self.fetch_direct(max_lines) {
+            return lines.context("reading items from the library database");
+        }
+        self.or_without_extra_fields(|| {
+            let mut command = self.new_timeless_command();
+            println!("{}", describe_command(&command));
+            self.runner
+                .output_head(&mut command, max_lines)
+                .stdout_check_errors()
+        })
+        .context("beet ls [current_args]")
+    }
+    /// Runs the `fetch`, retrying without the [`Self::with_extra_fields`] if it failed with them
+    fn or_without_extra_fields(
+        &self,
+        fetch: impl Fn() -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let err = match fetch() {
+            Err(err) if !self.extra_fields().is_empty() => err,
+            result => return result,
+        };
+        let failed = err
+            .downcast_ref::<CommandError>()
+            .is_some_and(|err| !matches!(err, CommandError::Spawn(_)));
+        if !failed {
+            return Err(err);
+        }
+        println!(
+            "[WARNING: beet failed fetching the extra fields {}, e.g. for a plugin that is not \
+             enabled, retrying without them: {err:#}]",
+            self.extra_fields.join(", ")
+        );
+        self.extra_fields_failed.store(true, Ordering::Relaxed);
+        fetch()
+    }
+
+    fn new_timeless_command(&self) -> std::process::Command {
+        let bounds = self.bounds_range();
+        let filters: Vec<_> = bounds.as_deref().into_iter().collect();
+        let mut command = self.new_list_command(&filters);
+        command
+            .arg(self.sort_newest_first())
             .arg("--format")
-            .arg("$added $artist - $album - $title")
-            .stdout_check_errors()
-            .context("beet ls [current_args]")?;
+            .arg(self.entry_format());
+        command
+    }
+
+    /// Counts the distinct items added on or after the `date`
+    pub fn count_entries_after(&self, date: Date) -> anyhow::Result<usize> {
+        if let Some(count) = self.count_direct(Some(date), None) {
+            return count.context("counting items after the selection in the library database");
+        }
+        self.count_entries_matching(&self.range_after(date))
+            .context("beet ls [current_args] added:[selection]..")
+    }
+
+    /// Counts the distinct items added between the dates `from` and `to` (inclusive), in a single
+    /// query
+    pub fn count_entries_between(&self, from: Date, to: Date) -> anyhow::Result<usize> {
+        if let Some(count) = self.count_direct(Some(from), Some(to)) {
+            return count.context("counting items between dates in the library database");
+        }
+        let range = self.range_between(Some(from), Some(to));
+        self.count_entries_matching(&range)
+            .context("beet ls [current_args] added:[from]..[to]")
+    }
 
-        current_output
+    /// Counts the distinct items added on the `date` (e.g. to check the items on a boundary day
+    /// were all fetched)
+    pub fn count_entries_on(&self, date: Date) -> anyhow::Result<usize> {
+        if let Some(count) = self.count_direct(Some(date), Some(date)) {
+            return count.context("counting items on a date in the library database");
+        }
+        let day = format!("{}:{date}", self.date_field);
+        self.count_entries_matching(&day)
+            .context("beet ls [current_args] added:[day]")
+    }
+
+    /// Counts the items between the dates (within the [`Self::with_date_bounds`]) in the library
+    /// database, if read directly
+    #[cfg(feature = "db")]
+    fn count_direct(&self, from: Option<Date>, to: Option<Date>) -> Option<anyhow::Result<usize>> {
+        let library_db = self.direct_library_db()?;
+        let (since, until) = self.date_bounds;
+        let from = from.into_iter().chain(since).max();
+        let to = to.into_iter().chain(until).min();
+        Some(library_db.count_between(&self.date_field, (from, to)))
+    }
+    #[cfg(not(feature = "db"))]
+    fn count_direct(
+        &self,
+        _from: Option<Date>,
+        _to: Option<Date>,
+    ) -> Option<anyhow::Result<usize>> {
+        None
+    }
+    /// Library database to read directly (see [`Self::with_library_db`]), unless the queries are
+    /// filtered
+    #[cfg(feature = "db")]
+    fn direct_library_db(&self) -> Option<&crate::db::LibraryDb> {
+        self.library_db
+            .as_deref()
+            .filter(|_| self.timeless_filter_sets.is_empty())
+    }
+    /// Lists the newest items (within the [`Self::with_date_bounds`]) in the library database, if
+    /// read directly
+    #[cfg(feature = "db")]
+    fn fetch_direct(&self, max_lines: usize) -> Option<anyhow::Result<Vec<u8>>> {
+        let library_db = self.direct_library_db()?;
+        println!("[reading {}]", library_db.path().display());
+        Some(library_db.list_lines(&self.date_field, self.date_bounds, max_lines))
+    }
+    #[cfg(not(feature = "db"))]
+    fn fetch_direct(&self, _max_lines: usize) -> Option<anyhow::Result<Vec<u8>>> {
+        None
+    }
+
+    fn count_entries_matching(&self, range: &str) -> anyhow::Result<usize> {
+        let bounds = self.bounds_range();
+        let filters: Vec<_> = std::iter::once(range).chain(bounds.as_deref()).collect();
+        let mut command = self.new_list_command(&filters);
+        command.arg("--format").arg("$id");
+        let key = describe_command(&command);
+        let mut query = || {
+            let output = self.run_checked(&mut command)?;
+            let ids = parse_ids(&output).context("current_output beet command")?;
+            Ok(ids.len())
+        };
+        match &self.count_cache {
+            Some(count_cache) => count_cache.get_or_query(&key, query),
+            None => query(),
+        }
+    }
+
+    /// Lists the items added on or after the `date` matched by each filter set separately (empty
+    /// for fewer than two filter sets, which have a trivial composition)
+    pub fn query_filter_set_items_after(&self, date: Date) -> anyhow::Result<Vec<FilterSetItems>> {
+        if self.timeless_filter_sets.len() < 2 {
+            return Ok(vec![]);
+        }
+        let range = self.range_after_within_bounds(date);
+        self.timeless_filter_sets
+            .iter()
+            .map(|filter_set| {
+                let mut command = self.new_command();
+                command
+                    .arg("list")
+                    .args(filter_set.iter().map(AsRef::<str>::as_ref))
+                    .arg(&range)
+                    .arg("--format")
+                    .arg("$id");
+                let output = self
+                    .run_checked(&mut command)
+                    .context("beet ls [filter_set] added:[selection]..")?;
+                let label = filter_set.join(" ");
+                let ids = parse_ids(&output)
+                    .with_context(|| format!("filter set {label:?} beet command"))?;
+                Ok(FilterSetItems { label, ids })
+            })
+            .collect()
+    }
+
+    /// Returns the program and arguments listing the items added on or after the `date`, as a
+    /// shell command line (e.g. for recording the query in a script)
+    pub fn describe_list_after(&self, date: Date) -> String {
+        let command = self.new_list_command(&[&self.range_after_within_bounds(date)]);
+        shell_command_line(&command)
+    }
+
+    /// Returns the program and arguments fetching the entries, as a shell command line
+    pub fn describe_timeless(&self) -> String {
+        shell_command_line(&self.new_timeless_command())
+    }
+
+    /// Lists the items added on or after the `date`, newest first, in the specified `format`
+    pub fn query_after(&self, date: Date, format: &str) -> anyhow::Result<Vec<String>> {
+        let mut command = self.new_list_command(&[&self.range_after_within_bounds(date)]);
+        command
+            .arg(self.sort_newest_first())
+            .arg("--format")
+            .arg(format);
+        let output = self
+            .run_checked(&mut command)
+            .context("beet ls [current_args] added:[selection]..")?;
+
+        output
             .lines()
             .enumerate()
-            .take(self.max_entries)
+            .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
             .map(|(number, line)| {
-                DateEntry::try_from(line.with_context(|| {
-                    format!("line {} from current_output beet command", number + 1)
-                })?)
+                line.with_context(|| format!("line {} from query_after beet command", number + 1))
             })
-            .collect::<anyhow::Result<Vec<_>>>()
+            .collect()
     }
 
-    pub fn count_entries_after(&self, entry: &DateEntry) -> anyhow::Result<usize> {
-        let output = self
-            .new_list_command(Some(&format!("added:{date}..", date = entry.date)))
+    /// Sets each of the `FIELD=VALUE` assignments on the items added on or after the `date`
+    pub fn modify_entries_after(
+        &self,
+        date: Date,
+        assignments: &[String],
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let mut command =
+            self.new_query_command(&["modify", "-y"], &[&self.range_after_within_bounds(date)]);
+        command.args(assignments);
+        self.run_modify(&mut command, dry_run)
+            .context("beet modify -y [current_args] added:[selection].. [assignments]")
+    }
+
+    /// Clears each of the `FIELD=VALUE` assignments from the items added before the `date`
+    /// which currently have exactly that value (e.g. tagged by a previous, older cutoff)
+    pub fn clear_entries_before(
+        &self,
+        date: Date,
+        assignments: &[String],
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        for assignment in assignments {
+            let Some((field, value)) = assignment.split_once('=') else {
+                anyhow::bail!("invalid assignment {assignment:?}, expected FIELD=VALUE")
+            };
+            let current_value = format!("{field}::^{}$", escape_regex(value));
+            let before_date = format!("^{}", self.range_after(date));
+            let mut command =
+                self.new_query_command(&["modify", "-y"], &[&current_value, &before_date]);
+            command.arg(format!("{field}="));
+            self.run_modify(&mut command, dry_run)
+                .context("beet modify -y [current_args] [field::value] ^added:[selection]..")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the id of the [`Sentinel`] item, with the value of its field (if set)
+    fn query_sentinel(&self, sentinel: &Sentinel) -> anyhow::Result<(ItemId, Option<String>)> {
+        let Sentinel { query, field } = sentinel;
+        // NOTE: the sentinel is independent of the timeless args
+        let mut command = self.new_command();
+        command
+            .arg("list")
+            .args(query)
             .arg("--format")
-            .arg("$id")
-            .stdout_check_errors()
-            .context("beet ls [current_args] added:[selection]..")?;
+            .arg(format!("$id\t%ifdef{{{field},${field}}}"));
+        let output = self
+            .run_checked(&mut command)
+            .context("beet ls [sentinel_query]")?;
+        let output = String::from_utf8(output).context("non-utf8 output from sentinel query")?;
 
+        let lines: Vec<&str> = output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        let &[line] = &lines[..] else {
+            anyhow::bail!(
+                "sentinel query {query:?} matched {} items, expected exactly one",
+                lines.len()
+            )
+        };
+        let (id, value) = line.split_once('\t').unwrap_or((line, ""));
+        let id: ItemId = id
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid item id {id:?} from sentinel query"))?;
+        let value = value.trim();
+        Ok((id, (!value.is_empty()).then(|| value.to_owned())))
+    }
+
+    /// Reads the cutoff stored on the [`Sentinel`] item, if any
+    pub fn read_sentinel_cutoff(&self, sentinel: &Sentinel) -> anyhow::Result<Option<Date>> {
+        let (_, value) = self.query_sentinel(sentinel)?;
+        value
+            .map(|value| {
+                value.parse().with_context(|| {
+                    format!("stored cutoff in sentinel field {:?}", sentinel.field)
+                })
+            })
+            .transpose()
+    }
+
+    /// Stores the `cutoff` on the [`Sentinel`] item
+    pub fn write_sentinel_cutoff(
+        &self,
+        sentinel: &Sentinel,
+        cutoff: Date,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let (id, _) = self.query_sentinel(sentinel)?;
+        let mut command = self.new_command();
+        command
+            .args(["modify", "-y"])
+            .arg(format!("id:{id}"))
+            .arg(format!("{}={cutoff}", sentinel.field));
+        self.run_modify(&mut command, dry_run)
+            .context("beet modify -y id:[sentinel] [field]=[cutoff]")
+    }
+
+    /// Lists the file modification time of each of the items with the `ids`, e.g. to repair a
+    /// missing date (see [`Self::set_dates`])
+    pub fn query_mtimes(&self, ids: &[ItemId]) -> anyhow::Result<Vec<(ItemId, Timestamp)>> {
+        let Some((last, rest)) = ids.split_last() else {
+            return Ok(vec![]);
+        };
+        let mut command = self.new_command();
+        command
+            .arg("list")
+            .args(rest.iter().map(|id| format!("id:{id},")))
+            .arg(format!("id:{last}"))
+            .arg("--format")
+            .arg("$id\t$mtime");
+        let output = self
+            .run_checked(&mut command)
+            .context("beet ls id:[undated] --format $id\t$mtime")?;
         output
             .lines()
             .enumerate()
-            .try_fold(0, |sum, (number, line)| {
-                let line = line.with_context(|| {
-                    format!("line {} from current_output beet command", number + 1)
-                })?;
-                let current = if line.trim().is_empty() { 0 } else { 1 };
-                Ok(sum + current)
+            .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+            .map(|(number, line)| {
+                let parse_line = || {
+                    let line = line?;
+                    let (id, mtime) = line.split_once('\t').unwrap_or((&line, ""));
+                    let id = id
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("invalid item id {id:?}"))?;
+                    let mtime = mtime.trim().parse()?;
+                    anyhow::Ok((id, mtime))
+                };
+                parse_line()
+                    .with_context(|| format!("line {} from query_mtimes beet command", number + 1))
             })
+            .collect()
+    }
+
+    /// Constructs the commands setting the date field of each item to the paired timestamp
+    fn set_date_commands<'b>(
+        &'b self,
+        dates: &'b [(ItemId, Timestamp)],
+    ) -> impl Iterator<Item = std::process::Command> + 'b {
+        dates.iter().map(|(id, timestamp)| {
+            let mut command = self.new_command();
+            command
+                .args(["modify", "-y"])
+                .arg(format!("id:{id}"))
+                .arg(format!("{}={timestamp}", self.date_field));
+            command
+        })
+    }
+
+    /// Returns the shell command lines for [`Self::set_dates`], e.g. for the user to review
+    pub fn describe_set_dates(&self, dates: &[(ItemId, Timestamp)]) -> Vec<String> {
+        self.set_date_commands(dates)
+            .map(|command| shell_command_line(&command))
+            .collect()
+    }
+
+    /// Sets the date field of each item to the paired timestamp
+    pub fn set_dates(&self, dates: &[(ItemId, Timestamp)], dry_run: bool) -> anyhow::Result<()> {
+        for mut command in self.set_date_commands(dates) {
+            self.run_modify(&mut command, dry_run)
+                .context("beet modify -y id:[undated] [date_field]=[mtime]")?;
+        }
+        Ok(())
+    }
+
+    fn run_modify(&self, command: &mut std::process::Command, dry_run: bool) -> anyhow::Result<()> {
+        if dry_run {
+            println!("[dry-run] {}", describe_command(command));
+        } else {
+            let output = self.run_checked(command)?;
+            print!("{}", String::from_utf8_lossy(&output));
+        }
+        Ok(())
+    }
+
+    /// Runs `beet` (with the leading arguments) followed by the `args`, returning stdout
+    ///
+    /// Checks the exit status and stderr like the other commands, logging the command line to
+    /// stderr to keep stdout for the output. Retries up to `retries` times when the command
+    /// cannot be run (e.g. timed out, see [`ProcessRunner`]) or exits unsuccessfully, first after
+    /// the `retry_delay` (doubling for each retry), but not for errors written to stderr (e.g. an
+    /// invalid query). Only the [`READ_ONLY_COMMANDS`] are retried, as the others may have
+    /// partially modified the library before failing.
+    pub fn run_raw(
+        &self,
+        args: &[String],
+        retries: u32,
+        retry_delay: std::time::Duration,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut command = self.new_command();
+        command.args(args);
+        eprintln!("{}", describe_command(&command));
+        let subcommand = args.first().map(String::as_str).unwrap_or_default();
+        let retries = if retries > 0 && !READ_ONLY_COMMANDS.contains(&subcommand) {
+            eprintln!("[not retrying beet command {subcommand:?}, which may modify the library]");
+            0
+        } else {
+            retries
+        };
+        let mut delay = retry_delay;
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .runner
+                .output(&mut command)
+                .stdout_check_errors()
+                .with_context(|| format!("beet [current_args] {}", args.join(" ")));
+            match result {
+                Err(err) if attempt < retries && is_retryable(&err) => {
+                    attempt += 1;
+                    eprintln!("[beet failed: {err:#}, retry {attempt} of {retries} in {delay:?}]");
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn run_checked(&self, command: &mut std::process::Command) -> anyhow::Result<Vec<u8>> {
+        println!("{}", describe_command(command));
+        self.runner.output(command).stdout_check_errors()
     }
 }
 
-trait CheckErrors {
-    fn stdout_check_errors(self) -> anyhow::Result<Vec<u8>>;
+/// Parses the output of [`BeetCommand::fetch_timeless`], truncated to `max_entries`
+///
+/// When `lenient`, lines failing to parse are collected in [`ParsedEntries::failures`] instead
+/// of aborting.
+pub fn parse_timeless(
+    current_output: &[u8],
+    max_entries: usize,
+    lenient: bool,
+) -> anyhow::Result<ParsedEntries> {
+    let mut seen_ids = HashSet::new();
+    let source = "current_output beet command";
+    parse_lines(current_output, max_entries, lenient, source, |line| {
+        let entry = DateEntry::parse_fields(line)?;
+        // dedupe items matched by multiple filter sets
+        Ok(entry
+            .id
+            .is_none_or(|id| seen_ids.insert(id))
+            .then_some(entry))
+    })
 }
-impl CheckErrors for &mut std::process::Command {
-    fn stdout_check_errors(self) -> anyhow::Result<Vec<u8>> {
-        println!(
-            "{} {:?}",
-            self.get_program().to_str().unwrap_or("[non-utf8 str]"),
-            &self.get_args().collect::<Vec<_>>()
-        );
-        self.output().stdout_check_errors()
+
+/// Resolves the `beet` command to the canonical path of an executable file, returning the
+/// program with any arguments preceding each subcommand
+///
+/// Expands a leading `~` to the home directory, searches the `PATH` for a bare command name, and
+/// resolves relative paths and symlinks, to report a missing command at startup instead of when
+/// first running it. A command which is not a path to an existing file is split into words by
+/// [`split_words`], e.g. `python3 -m beets` or `nix run nixpkgs#beets --`.
+pub fn resolve_command_line(command: &str) -> anyhow::Result<(PathBuf, Vec<String>)> {
+    let home = std::env::var_os("HOME");
+    let search_dirs = path_dirs();
+    resolve_command_line_in(command, home.as_deref().map(Path::new), &search_dirs)
+}
+fn resolve_command_line_in(
+    command: &str,
+    home: Option<&Path>,
+    search_dirs: &[PathBuf],
+) -> anyhow::Result<(PathBuf, Vec<String>)> {
+    let whole_err = match resolve_command_path_in(Path::new(command), home, search_dirs) {
+        Ok(program) => return Ok((program, vec![])),
+        Err(err) => err,
+    };
+    let mut words = split_words(command)?.into_iter();
+    match words.next() {
+        Some(program) if program != command => {
+            let program = resolve_command_path_in(Path::new(&program), home, search_dirs)?;
+            Ok((program, words.collect()))
+        }
+        _ => Err(whole_err),
+    }
+}
+
+/// Splits the `command` into words on whitespace, respecting single and double quotes and
+/// backslash escapes (as a POSIX shell would, without expansions)
+fn split_words(command: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("unterminated single quote in {command:?}"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => anyhow::bail!("unterminated double quote in {command:?}"),
+                        },
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("unterminated double quote in {command:?}"),
+                    }
+                }
+            }
+            '\\' => {
+                let Some(c) = chars.next() else {
+                    anyhow::bail!("trailing backslash in {command:?}")
+                };
+                word.get_or_insert_with(String::new).push(c);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Searches the `PATH`, then common install locations (e.g. `~/.local/bin`, pipx and nix
+/// profiles), for the `beet` command
+pub fn find_beet_command() -> anyhow::Result<PathBuf> {
+    let home = std::env::var_os("HOME");
+    let home = home.as_deref().map(Path::new);
+    let search_dirs: Vec<_> = path_dirs()
+        .into_iter()
+        .chain(common_install_dirs(home))
+        .collect();
+    resolve_command_path_in(Path::new("beet"), home, &search_dirs).context(
+        "no beet command found in PATH or common install locations, specify --beet-command",
+    )
+}
+fn path_dirs() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|search_path| std::env::split_paths(&search_path).collect())
+        .unwrap_or_default()
+}
+/// Directories where `beet` is commonly installed outside of the `PATH`
+fn common_install_dirs(home: Option<&Path>) -> Vec<PathBuf> {
+    let home_dirs = home.into_iter().flat_map(|home| {
+        [
+            ".local/bin",
+            ".local/pipx/venvs/beets/bin",
+            ".local/share/pipx/venvs/beets/bin",
+            ".nix-profile/bin",
+        ]
+        .map(|dir| home.join(dir))
+    });
+    let system_dirs = ["/run/current-system/sw/bin", "/usr/local/bin"].map(PathBuf::from);
+    home_dirs.chain(system_dirs).collect()
+}
+fn resolve_command_path_in(
+    path: &Path,
+    home: Option<&Path>,
+    search_dirs: &[PathBuf],
+) -> anyhow::Result<PathBuf> {
+    let expanded = match path.strip_prefix("~") {
+        Ok(rest) => {
+            let home = home.context("cannot expand \"~\" without HOME")?;
+            home.join(rest)
+        }
+        Err(_) => path.to_owned(),
+    };
+    let is_bare_name =
+        expanded.components().count() == 1 && expanded.parent() == Some(Path::new(""));
+    let found = if is_bare_name {
+        search_dirs
+            .iter()
+            .map(|dir| dir.join(&expanded))
+            .find(|candidate| candidate.is_file())
+            .with_context(|| format!("beet command {expanded:?} not found in PATH"))?
+    } else {
+        expanded
+    };
+    let resolved = found
+        .canonicalize()
+        .with_context(|| format!("beet command {found:?} not found"))?;
+    let metadata = std::fs::metadata(&resolved)
+        .with_context(|| format!("reading beet command {resolved:?}"))?;
+    if !metadata.is_file() {
+        anyhow::bail!("beet command {resolved:?} is not a file");
     }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            anyhow::bail!("beet command {resolved:?} is not executable");
+        }
+    }
+    Ok(resolved)
+}
+
+/// Quotes the `word` for a POSIX shell (when needed), inverse of [`split_words`]
+pub fn shell_quote(word: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Parses the distinct item ids from the lines of `beet ls --format $id`
+fn parse_ids(output: &[u8]) -> anyhow::Result<HashSet<ItemId>> {
+    let mut ids = HashSet::new();
+    for (number, line) in output.lines().enumerate() {
+        let line = line.with_context(|| format!("line {}", number + 1))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let id: ItemId = line
+            .parse()
+            .with_context(|| format!("invalid item id {line:?} on line {}", number + 1))?;
+        ids.insert(id);
+    }
+    Ok(ids)
+}
+
+/// Formats the program and arguments of the `command` as a shell command line
+fn shell_command_line(command: &std::process::Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn describe_command(command: &std::process::Command) -> String {
+    format!(
+        "{} {:?}",
+        command.get_program().to_str().unwrap_or("[non-utf8 str]"),
+        &command.get_args().collect::<Vec<_>>()
+    )
+}
+
+/// Escapes the regex metacharacters in `value`, for use in a beets regex query (`field::regex`)
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Failure of a subprocess, distinguishing a command that could not be run from one reporting an
+/// error (e.g. an invalid query)
+#[derive(Debug)]
+pub enum CommandError {
+    /// Running the command failed (e.g. not found)
+    Spawn(std::io::Error),
+    /// The command wrote to stderr
+    Stderr(String),
+    /// The command exited unsuccessfully
+    Status(std::process::ExitStatus),
+}
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spawn(_) => write!(f, "running subprocess"),
+            Self::Stderr(stderr) => write!(f, "subprocess stderr: {stderr}"),
+            Self::Status(status) => write!(f, "subprocess status: {status:?}"),
+        }
+    }
+}
+impl std::error::Error for CommandError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Spawn(err) => Some(err),
+            Self::Stderr(_) | Self::Status(_) => None,
+        }
+    }
+}
+
+/// Whether the failure may succeed when retried, unlike errors reported by the command on stderr
+fn is_retryable(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<CommandError>(),
+        Some(CommandError::Spawn(_) | CommandError::Status(_))
+    )
+}
+
+trait CheckErrors {
+    fn stdout_check_errors(self) -> anyhow::Result<Vec<u8>>;
 }
 impl CheckErrors for Result<std::process::Output, std::io::Error> {
     fn stdout_check_errors(self) -> anyhow::Result<Vec<u8>> {
@@ -139,15 +1540,15 @@ impl CheckErrors for Result<std::process::Output, std::io::Error> {
             status,
             stdout,
             stderr,
-        } = self?;
+        } = self.map_err(CommandError::Spawn)?;
 
-        let stderr = std::str::from_utf8(&stderr).context("non-utf8 in beet stderr")?;
         if !stderr.is_empty() {
-            anyhow::bail!("subprocess stderr: {stderr}");
+            let stderr = String::from_utf8_lossy(&stderr).into_owned();
+            return Err(CommandError::Stderr(stderr).into());
         }
 
         if !status.success() {
-            anyhow::bail!("subprocess status: {status:?}");
+            return Err(CommandError::Status(status).into());
         }
 
         Ok(stdout)
@@ -157,13 +1558,54 @@ impl CheckErrors for Result<std::process::Output, std::io::Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use std::{
+        cell::RefCell,
+        collections::VecDeque,
+        process::{ExitStatus, Output},
+    };
+
+    /// Records each invocation, and responds with queued outputs
+    #[derive(Default)]
+    struct FakeRunner {
+        invocations: RefCell<Vec<Vec<String>>>,
+        responses: RefCell<VecDeque<std::io::Result<Output>>>,
+    }
+    impl FakeRunner {
+        fn respond(self, status: ExitStatus, stdout: &str, stderr: &str) -> Self {
+            self.responses.borrow_mut().push_back(Ok(Output {
+                status,
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: stderr.as_bytes().to_vec(),
+            }));
+            self
+        }
+        fn respond_success(self, stdout: &str) -> Self {
+            self.respond(ExitStatus::default(), stdout, "")
+        }
+    }
+    impl ProcessRunner for &FakeRunner {
+        fn output(&self, command: &mut std::process::Command) -> std::io::Result<Output> {
+            let invocation = std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|os_str| os_str.to_str().expect("valid utf8 in test case").to_owned())
+                .collect();
+            self.invocations.borrow_mut().push(invocation);
+            self.responses
+                .borrow_mut()
+                .pop_front()
+                .expect("FakeRunner response for each invocation")
+        }
+    }
+
+    fn date(s: &str) -> Date {
+        s.parse().expect("valid date in test case")
+    }
 
     fn beet_list_command(timeless_args: &str, extra_filter: Option<&str>) -> Vec<String> {
         let beet_command = PathBuf::from("beet");
         let max_entries = 0;
         let command = BeetCommand::new(beet_command, timeless_args, max_entries)
-            .new_list_command(extra_filter);
+            .new_list_command(extra_filter.as_slice());
         command
             .get_args()
             .map(|os_str| os_str.to_str().expect("valid utf8 in test case").to_owned())
@@ -189,4 +1631,766 @@ mod tests {
             beet_list_command("a\nb\nc,d\ne,f\ng", extra)
         );
     }
+
+    #[test]
+    fn beet_command_excluded_args() {
+        let excluded_list_command = |timeless_args, exclude_args| -> Vec<String> {
+            BeetCommand::new(PathBuf::from("beet"), timeless_args, 0)
+                .with_excluded(exclude_args)
+                .new_list_command(&[])
+                .get_args()
+                .map(|os_str| os_str.to_str().expect("valid utf8 in test case").to_owned())
+                .collect()
+        };
+        assert_eq!(excluded_list_command("a\nb", ""), ["list", "a", "b"]);
+        assert_eq!(
+            excluded_list_command("", "genre:classical"),
+            ["list", "^genre:classical"]
+        );
+        insta::assert_ron_snapshot!("args_excluded", excluded_list_command("a,b", "c\n^d,e"));
+    }
+
+    #[test]
+    fn beet_command_min_rating() {
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a\nb,c", 0);
+        assert!(beets.below_min_rating().is_none());
+
+        let beets = beets.with_min_rating(Some(0.8));
+        assert_eq!(
+            beets.filter_sets(),
+            [vec!["a", "b", "rating:0.8.."], vec!["c", "rating:0.8.."]]
+        );
+        let below = beets.below_min_rating().expect("limited by rating");
+        assert_eq!(
+            below.filter_sets(),
+            [vec!["a", "b", "^rating:0.8.."], vec!["c", "^rating:0.8.."]]
+        );
+        assert!(below.below_min_rating().is_none());
+
+        let unfiltered = BeetCommand::new(PathBuf::from("beet"), "", 0).with_min_rating(Some(4.0));
+        assert_eq!(unfiltered.filter_sets(), [vec!["rating:4.."]]);
+
+        let replaced = beets.with_timeless_args("d").with_excluded("e");
+        assert_eq!(replaced.filter_sets(), [vec!["d", "^e"]]);
+        assert_eq!(replaced.min_rating(), None);
+    }
+
+    #[test]
+    fn sentinel_invocations() {
+        let sentinel = Sentinel {
+            query: vec!["title:marker".to_owned()],
+            field: "cutoff".to_owned(),
+        };
+        let runner = FakeRunner::default()
+            .respond_success("5\t2024-01-02\n")
+            .respond_success("5\t\n")
+            .respond_success("Modifying 1 items.\n")
+            .respond_success("5\t\n6\t\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+
+        let stored = beets
+            .read_sentinel_cutoff(&sentinel)
+            .expect("read succeeds");
+        assert_eq!(stored, Some(date("2024-01-02")));
+        beets
+            .write_sentinel_cutoff(&sentinel, date("2024-02-03"), false)
+            .expect("write succeeds");
+        let err = beets
+            .read_sentinel_cutoff(&sentinel)
+            .expect_err("multiple items fail");
+        assert_eq!(
+            err.to_string(),
+            r#"sentinel query ["title:marker"] matched 2 items, expected exactly one"#
+        );
+
+        let list = [
+            "beet",
+            "list",
+            "title:marker",
+            "--format",
+            "$id\t%ifdef{cutoff,$cutoff}",
+        ];
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [
+                &list[..],
+                &list[..],
+                &["beet", "modify", "-y", "id:5", "cutoff=2024-02-03"],
+                &list[..],
+            ]
+        );
+    }
+
+    #[test]
+    fn query_timeless_invocation() {
+        let runner = FakeRunner::default().respond_success(
+            "12\t2024-03-02 10:11:12\tArtist\tAlbum\tArtist\tTitle 1\t1000\tmbid-1\n\
+             12\t2024-03-02 10:11:12\tArtist\tAlbum\tArtist\tTitle 1\t1000\tmbid-1\n\
+             7\t2024-03-01 09:00:00\tArtist\tAlbum\tArtist\tTitle 2\t2000\tmbid-1\n\
+             2\t2024-02-01 08:00:00\tOther\tRecord\tOther\tTitle 3\t\t\n",
+        );
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a\nb,c", 3).with_runner(&runner);
+
+        let entries = beets.query_timeless().expect("query succeeds");
+
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [[
+                "beet",
+                "list",
+                "a",
+                "b,",
+                "c",
+                "added-",
+                "--format",
+                "$id\t$added\t$albumartist\t$album\t$artist\t$title\t$filesize\t$mb_albumid\t$genre\t$format\t$comp\t$play_count"
+            ]]
+        );
+        assert_eq!(
+            entries[0].musicbrainz_url().as_deref(),
+            Some("https://musicbrainz.org/release/mbid-1")
+        );
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                let DateEntry { id, timestamp, .. } = entry;
+                (*id, timestamp.to_string(), entry.to_string())
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    Some(12),
+                    "2024-03-02 10:11:12".to_owned(),
+                    "Artist - Album - Title 1".to_owned()
+                ),
+                (
+                    Some(7),
+                    "2024-03-01 09:00:00".to_owned(),
+                    "Artist - Album - Title 2".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn extra_fields_fallback() {
+        let line = "12\t2024-03-02 10:11:12\tArtist\tAlbum\tArtist\tTitle\t\t\t\t\tFalse\t3";
+        let runner = FakeRunner::default()
+            .respond_success(&format!("{line}\t5\t$rating\n"))
+            .respond(ExitStatus::default(), "", "error: unknown field")
+            .respond_success(&format!("{line}\n"));
+        let beets = BeetCommand::new(PathBuf::from("beet"), "", 10)
+            .with_extra_fields(vec!["play_count".to_owned(), "rating".to_owned()])
+            .with_runner(&runner);
+
+        let entries = beets.query_timeless().expect("query succeeds");
+        assert_eq!(entries[0].extra_field(0, "play_count"), Some("5"));
+        assert_eq!(entries[0].extra_field(1, "rating"), None);
+
+        let entries = beets.query_timeless().expect("retried without the fields");
+        assert_eq!(entries[0].extra, Vec::<String>::new());
+        assert!(beets.extra_fields().is_empty());
+        let formats: Vec<_> = runner
+            .invocations
+            .borrow()
+            .iter()
+            .map(|invocation| invocation.last().expect("format").clone())
+            .collect();
+        let format = "$id\t$added\t$albumartist\t$album\t$artist\t$title\t$filesize\t$mb_albumid\t$genre\t$format\t$comp\t$play_count";
+        let extended = format!("{format}\t$play_count\t$rating");
+        assert_eq!(formats, [&extended, &extended, format]);
+    }
+
+    #[test]
+    fn parse_timeless_crlf() {
+        let output = b"12\t2024-03-02 10:11:12\tArtist\tAlbum\tArtist\tTitle 1\t1000\tmbid \r\n\
+                       7\t2024-03-01 09:00:00\tArtist\tAlbum\tArtist\tTitle 2\t\t\r\n\
+                       \r\n";
+
+        let parsed = parse_timeless(output, 10, false).expect("valid output");
+
+        let entries: Vec<_> = parsed
+            .entries
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.mb_albumid.as_deref()))
+            .collect();
+        assert_eq!(entries, [("Title 1", Some("mbid")), ("Title 2", None)]);
+    }
+
+    #[test]
+    fn count_entries_after_invocation() {
+        let runner = FakeRunner::default().respond_success("1\n2\n\n3\n2\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+
+        let count = beets
+            .count_entries_after(date("2024-03-01"))
+            .expect("count succeeds");
+
+        assert_eq!(count, 3);
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [["beet", "list", "a", "added:2024-03-01..", "--format", "$id"]]
+        );
+    }
+
+    #[test]
+    fn filter_set_items_invocations() {
+        let runner = FakeRunner::default()
+            .respond_success("1\n2\n3\n")
+            .respond_success("3\n4\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a\nb,c", 0).with_runner(&runner);
+
+        let filter_sets = beets
+            .query_filter_set_items_after(date("2024-03-01"))
+            .expect("queries succeed");
+
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [
+                [
+                    "beet",
+                    "list",
+                    "a",
+                    "b",
+                    "added:2024-03-01..",
+                    "--format",
+                    "$id"
+                ]
+                .as_slice(),
+                ["beet", "list", "c", "added:2024-03-01..", "--format", "$id"].as_slice(),
+            ]
+        );
+        let (shares, total) = filter_set_composition(&filter_sets);
+        assert_eq!(total, 4);
+        assert_eq!(
+            shares,
+            [
+                FilterSetShare {
+                    label: "a b".to_owned(),
+                    count: 3,
+                    exclusive: 2,
+                },
+                FilterSetShare {
+                    label: "c".to_owned(),
+                    count: 2,
+                    exclusive: 1,
+                },
+            ]
+        );
+
+        let single = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+        let filter_sets = single
+            .query_filter_set_items_after(date("2024-03-01"))
+            .expect("no queries");
+        assert!(filter_sets.is_empty());
+    }
+
+    #[test]
+    fn describe_list_as_shell() {
+        let beets = BeetCommand::new(
+            PathBuf::from("/usr/bin/beet"),
+            "genre:rock\nartist:it's,a b",
+            0,
+        )
+        .with_command_args(vec!["-c".to_owned(), "config.yaml".to_owned()]);
+        let command_line = beets.describe_list_after(date("2024-03-01"));
+        assert_eq!(
+            command_line,
+            r"/usr/bin/beet -c config.yaml list genre:rock 'artist:it'\''s' added:2024-03-01.., 'a b' added:2024-03-01.."
+        );
+        let words = split_words(&command_line).expect("valid quoting");
+        assert_eq!(words[5..7], ["artist:it's", "added:2024-03-01..,"]);
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[cfg(feature = "db")]
+    #[test]
+    fn direct_db_filtered_fallback() {
+        let path = std::env::temp_dir().join(format!("direct_db_test_{}.db", std::process::id()));
+        rusqlite::Connection::open(&path)
+            .expect("create test database")
+            .execute_batch(
+                "CREATE TABLE items (id INTEGER PRIMARY KEY, added FLOAT);
+                 INSERT INTO items VALUES (1, 1709294400), (2, 1709553600);",
+            )
+            .expect("populate test database");
+        let library_db = Arc::new(crate::db::LibraryDb::open(&path).expect("open"));
+        let runner = FakeRunner::default().respond_success("2\n");
+
+        let unfiltered = BeetCommand::new(PathBuf::from("beet"), "", 0)
+            .with_runner(&runner)
+            .with_library_db(Arc::clone(&library_db));
+        let count = unfiltered
+            .count_entries_after(date("2024-03-01"))
+            .expect("count succeeds");
+        assert_eq!(count, 2);
+        assert!(runner.invocations.borrow().is_empty());
+
+        let filtered = BeetCommand::new(PathBuf::from("beet"), "genre:jazz", 0)
+            .with_runner(&runner)
+            .with_library_db(library_db);
+        let count = filtered
+            .count_entries_after(date("2024-03-01"))
+            .expect("count succeeds");
+        assert_eq!(count, 1);
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [[
+                "beet",
+                "list",
+                "genre:jazz",
+                "added:2024-03-01..",
+                "--format",
+                "$id"
+            ]]
+        );
+        std::fs::remove_file(&path).expect("remove test file");
+    }
+
+    #[test]
+    fn queries_after_within_bounds() {
+        let runner = FakeRunner::default()
+            .respond_success("1\n")
+            .respond_success("2\n")
+            .respond_success("3\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a,b", 0)
+            .with_date_bounds(None, Some(date("2024-03-10")))
+            .with_runner(&runner);
+        let cutoff = date("2024-03-01");
+
+        assert_eq!(
+            beets.describe_list_after(cutoff),
+            "beet list a added:2024-03-01..2024-03-10, b added:2024-03-01..2024-03-10"
+        );
+        let lines = beets.query_after(cutoff, "$id").expect("query succeeds");
+        assert_eq!(lines, ["1"]);
+        let composition = beets
+            .query_filter_set_items_after(cutoff)
+            .expect("query succeeds");
+        assert_eq!(composition.len(), 2);
+        let ranges: Vec<_> = runner
+            .invocations
+            .borrow()
+            .iter()
+            .map(|invocation| invocation[3].trim_end_matches(',').to_owned())
+            .collect();
+        assert_eq!(ranges, ["added:2024-03-01..2024-03-10"; 3]);
+
+        // the later of the cutoff and the `since` bound
+        let beets = BeetCommand::new(PathBuf::from("beet"), "", 0)
+            .with_date_bounds(Some(date("2024-03-05")), Some(date("2024-03-10")));
+        assert_eq!(
+            beets.describe_list_after(cutoff),
+            "beet list added:2024-03-05..2024-03-10"
+        );
+    }
+
+    #[test]
+    fn clear_then_modify_invocations() {
+        let runner = FakeRunner::default()
+            .respond_success("")
+            .respond_success("");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+        let assignments = ["tag=new.1".to_owned()];
+
+        beets
+            .clear_entries_before(date("2024-03-01"), &assignments, false)
+            .expect("clear succeeds");
+        beets
+            .modify_entries_after(date("2024-03-01"), &assignments, false)
+            .expect("modify succeeds");
+
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [
+                vec![
+                    "beet",
+                    "modify",
+                    "-y",
+                    "a",
+                    "tag::^new\\.1$",
+                    "^added:2024-03-01..",
+                    "tag="
+                ],
+                vec![
+                    "beet",
+                    "modify",
+                    "-y",
+                    "a",
+                    "added:2024-03-01..",
+                    "tag=new.1"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn stderr_is_error() {
+        let runner = FakeRunner::default().respond(ExitStatus::default(), "", "oh no");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "", 0).with_runner(&runner);
+
+        let err = beets.query_timeless().expect_err("stderr fails the query");
+
+        assert_eq!(
+            format!("{:#}", err),
+            "beet ls [current_args]: subprocess stderr: oh no"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_status_is_error() {
+        use std::os::unix::process::ExitStatusExt as _;
+
+        let runner = FakeRunner::default().respond(ExitStatus::from_raw(1 << 8), "", "");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "", 0).with_runner(&runner);
+
+        let err = beets
+            .count_entries_after(date("2024-03-01"))
+            .expect_err("exit status fails the query");
+
+        assert!(
+            format!("{:#}", err).contains("subprocess status"),
+            "{err:#}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_command_paths() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let dir = std::env::temp_dir().join(format!("resolve_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("bin")).expect("create test dir");
+        let beet = dir.join("bin/beet");
+        std::fs::write(&beet, "#!/bin/sh\n").expect("write test command");
+        std::fs::set_permissions(&beet, std::fs::Permissions::from_mode(0o755))
+            .expect("set permissions");
+        std::os::unix::fs::symlink(&beet, dir.join("beet-link")).expect("create symlink");
+        let plain = dir.join("bin/plain");
+        std::fs::write(&plain, "").expect("write test file");
+        let beet = beet.canonicalize().expect("canonical path");
+
+        let home = Some(dir.as_path());
+        let search_dirs = [dir.join("missing"), dir.join("bin")];
+        let resolve = |path: &str| {
+            resolve_command_path_in(Path::new(path), home, &search_dirs)
+                .map_err(|err| err.to_string())
+        };
+
+        assert_eq!(resolve("beet"), Ok(beet.clone()));
+        assert_eq!(resolve("~/bin/beet"), Ok(beet.clone()));
+        assert_eq!(resolve("~/beet-link"), Ok(beet.clone()));
+        let missing = resolve("beets").expect_err("not in search path");
+        assert!(missing.contains("not found in PATH"), "{missing}");
+        let not_executable = resolve("~/bin/plain").expect_err("not executable");
+        assert!(
+            not_executable.contains("is not executable"),
+            "{not_executable}"
+        );
+
+        let resolve_line = |command: &str| {
+            resolve_command_line_in(command, home, &search_dirs).map_err(|err| err.to_string())
+        };
+        assert_eq!(resolve_line("~/beet-link"), Ok((beet.clone(), vec![])));
+        assert_eq!(resolve_line("'beet'"), Ok((beet.clone(), vec![])));
+        assert_eq!(
+            resolve_line("beet -c ~/config.yaml"),
+            Ok((
+                beet.clone(),
+                vec!["-c".to_owned(), "~/config.yaml".to_owned()]
+            ))
+        );
+        let missing = resolve_line("beets").expect_err("not in search path");
+        assert!(missing.contains("not found in PATH"), "{missing}");
+
+        let install_dirs = common_install_dirs(home);
+        assert_eq!(install_dirs[0], dir.join(".local/bin"));
+
+        std::fs::remove_dir_all(&dir).expect("remove test dir");
+    }
+
+    #[test]
+    fn split_command_words() {
+        let split = |command: &str| split_words(command).map_err(|err| err.to_string());
+        assert_eq!(
+            split("nix run nixpkgs#beets --"),
+            Ok(vec![
+                "nix".to_owned(),
+                "run".to_owned(),
+                "nixpkgs#beets".to_owned(),
+                "--".to_owned()
+            ])
+        );
+        assert_eq!(
+            split(r#"  "/opt/my beets/beet" -c 'a b'\ c "" "x\"y"  "#),
+            Ok(vec![
+                "/opt/my beets/beet".to_owned(),
+                "-c".to_owned(),
+                "a b c".to_owned(),
+                String::new(),
+                "x\"y".to_owned(),
+            ])
+        );
+        assert!(split("beet 'unterminated").is_err());
+        assert!(split(r"beet \").is_err());
+    }
+
+    #[test]
+    fn repair_dates_from_mtime() {
+        let runner = FakeRunner::default()
+            .respond_success("3\t2023-05-06 07:08:09\n\n5\t1970-01-01 00:00:00\n")
+            .respond_success("");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+
+        let mtimes = beets.query_mtimes(&[3, 5]).expect("query succeeds");
+        let repairs: Vec<_> = mtimes
+            .into_iter()
+            .filter(|(_, mtime)| !mtime.is_epoch())
+            .collect();
+        assert_eq!(
+            beets.describe_set_dates(&repairs),
+            ["beet modify -y id:3 'added=2023-05-06 07:08:09'"]
+        );
+        beets.set_dates(&repairs, false).expect("modify succeeds");
+
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [
+                vec!["beet", "list", "id:3,", "id:5", "--format", "$id\t$mtime"],
+                vec!["beet", "modify", "-y", "id:3", "added=2023-05-06 07:08:09"],
+            ]
+        );
+        assert!(beets.query_mtimes(&[]).expect("no query").is_empty());
+    }
+
+    #[test]
+    fn date_bounds_queries() {
+        let runner = FakeRunner::default()
+            .respond_success("")
+            .respond_success("1\n2\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0)
+            .with_date_bounds(Some(date("2024-01-01")), Some(date("2024-06-30")))
+            .with_runner(&runner);
+
+        beets.fetch_timeless().expect("fetch succeeds");
+        let count = beets
+            .count_entries_after(date("2024-03-01"))
+            .expect("count succeeds");
+        assert_eq!(count, 2);
+
+        let invocations = runner.invocations.borrow();
+        assert_eq!(
+            invocations[0][..4],
+            ["beet", "list", "a", "added:2024-01-01..2024-06-30"]
+        );
+        assert_eq!(
+            invocations[1][..5],
+            [
+                "beet",
+                "list",
+                "a",
+                "added:2024-03-01..",
+                "added:2024-01-01..2024-06-30"
+            ]
+        );
+
+        let since_only = BeetCommand::new(PathBuf::from("beet"), "", 0)
+            .with_date_bounds(Some(date("2024-01-01")), None);
+        assert_eq!(
+            since_only.bounds_range().as_deref(),
+            Some("added:2024-01-01..")
+        );
+    }
+
+    #[test]
+    fn fetch_head_lines() {
+        let runner = FakeRunner::default().respond_success("1\t...\n2\t...\n3\t...\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+
+        let output = beets.fetch_timeless_head(2).expect("fetch succeeds");
+        assert_eq!(output, b"1\t...\n2\t...\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn system_runner_stops_after_head() {
+        // never exits unless stopped
+        let mut command = std::process::Command::new("yes");
+        let output = SystemRunner
+            .output_head(&mut command, 3)
+            .expect("spawns yes");
+        assert_eq!(output.stdout, b"y\ny\ny\n");
+        assert!(output.status.success());
+
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "echo one; exit 3"]);
+        let output = SystemRunner
+            .output_head(&mut command, 3)
+            .expect("spawns sh");
+        assert_eq!(output.stdout, b"one\n");
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn count_entries_on_day() {
+        let runner = FakeRunner::default().respond_success("4\n5\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+
+        let count = beets
+            .count_entries_on(date("2024-03-01"))
+            .expect("count succeeds");
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [vec![
+                "beet",
+                "list",
+                "a",
+                "added:2024-03-01",
+                "--format",
+                "$id"
+            ]]
+        );
+    }
+
+    #[test]
+    fn raw_pass_through() {
+        let runner = FakeRunner::default().respond_success("3 items\n").respond(
+            ExitStatus::default(),
+            "",
+            "unknown command\n",
+        );
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0)
+            .with_command_args(vec!["-l".to_owned(), "lib.db".to_owned()])
+            .with_runner(&runner);
+        let args = ["stats".to_owned(), "genre:jazz".to_owned()];
+
+        let run_raw = || beets.run_raw(&args, 2, std::time::Duration::ZERO);
+        let output = run_raw().expect("command succeeds");
+        assert_eq!(output, b"3 items\n");
+        let err = run_raw().expect_err("stderr is an error, not retried");
+        assert!(format!("{err:#}").contains("unknown command"), "{err:#}");
+
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [["beet", "-l", "lib.db", "stats", "genre:jazz"]; 2]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn raw_retries() {
+        use std::os::unix::process::ExitStatusExt as _;
+
+        let failed = ExitStatus::from_raw(1 << 8);
+        let timed_out = || std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+        let runner = FakeRunner::default().respond(failed, "", "");
+        runner.responses.borrow_mut().push_back(Err(timed_out()));
+        let runner = runner.respond_success("3 items\n").respond(failed, "", "");
+        runner.responses.borrow_mut().push_back(Err(timed_out()));
+        let beets = BeetCommand::new(PathBuf::from("beet"), "", 0).with_runner(&runner);
+        let args = ["stats".to_owned()];
+        let run_raw = |retries| beets.run_raw(&args, retries, std::time::Duration::ZERO);
+
+        let output = run_raw(2).expect("succeeds on the last retry");
+        assert_eq!(output, b"3 items\n");
+        let err = run_raw(1).expect_err("fails after the retries");
+        assert!(
+            matches!(
+                err.downcast_ref::<CommandError>(),
+                Some(CommandError::Spawn(_))
+            ),
+            "{err:#}"
+        );
+        assert_eq!(runner.invocations.borrow().len(), 5);
+
+        let args = ["modify".to_owned(), "genre=rock".to_owned()];
+        let runner = FakeRunner::default().respond(failed, "", "");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "", 0).with_runner(&runner);
+        beets
+            .run_raw(&args, 2, std::time::Duration::ZERO)
+            .expect_err("not retried");
+        assert_eq!(runner.invocations.borrow().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout_runner_kills_process() {
+        let timeout = std::time::Duration::from_millis(100);
+        let start = std::time::Instant::now();
+        let mut command = std::process::Command::new("sleep");
+        command.arg("10");
+        let err = TimeoutRunner::new(timeout)
+            .output(&mut command)
+            .expect_err("killed after the timeout");
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+        let mut command = std::process::Command::new("echo");
+        command.arg("done");
+        let output = TimeoutRunner::new(std::time::Duration::from_secs(5))
+            .output(&mut command)
+            .expect("exits within the timeout");
+        assert_eq!(output.stdout, b"done\n");
+    }
+
+    #[test]
+    fn filter_file() {
+        let text = "# rock\ngenre:rock\n  year:2000..  \n\n\n# jazz\ngenre:jazz\n\n";
+
+        let args = filter_file_args(text);
+
+        assert_eq!(args, "genre:rock\nyear:2000..,genre:jazz");
+        assert_eq!(
+            parse_filter_sets(&args),
+            [vec!["genre:rock", "year:2000.."], vec!["genre:jazz"]]
+        );
+    }
+
+    #[test]
+    fn lint_filters() {
+        let lints = lint_filter_args("genre:rock\n\n title:a:b,\ngenre:jazz,,:live\nmy field:x");
+
+        let lints: Vec<_> = lints.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lints,
+            [
+                r#"filter set 1, atom "": empty atom, passed to beet as an empty argument"#,
+                r#"filter set 1, atom " title:a:b": leading or trailing whitespace, part of the query"#,
+                r#"filter set 1, atom " title:a:b": unquoted colon in the value, beets splits the field at the first colon only"#,
+                r#"filter set 3, atom "": empty filter set (stray comma), ignored"#,
+                r#"filter set 4, atom ":live": empty field before the colon"#,
+                r#"filter set 4, atom "my field:x": whitespace in the field name"#,
+            ]
+        );
+        assert!(lint_filter_args("genre:rock\nyear:2000..,\ngenre::^j.*z").is_empty());
+    }
+
+    #[test]
+    fn count_entries_in_range() {
+        let runner = FakeRunner::default().respond_success("4\n5\n6\n");
+        let beets = BeetCommand::new(PathBuf::from("beet"), "a", 0).with_runner(&runner);
+
+        let count = beets
+            .count_entries_between(date("2024-03-01"), date("2024-03-31"))
+            .expect("count succeeds");
+
+        assert_eq!(count, 3);
+        assert_eq!(
+            *runner.invocations.borrow(),
+            [vec![
+                "beet",
+                "list",
+                "a",
+                "added:2024-03-01..2024-03-31",
+                "--format",
+                "$id"
+            ]]
+        );
+    }
 }
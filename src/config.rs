@@ -0,0 +1,190 @@
+//! Config file of argument values (e.g. `beet_command` and `output_key`), as an alternative to
+//! long command lines or many environment variables
+//!
+//! The file is TOML with one top-level key per argument, e.g.
+//!
+//! ```toml
+//! beet_command = "python3 -m beets"
+//! timeless_args = "genre:rock"
+//! targets = [30, 50, 70]
+//! output_file = "/var/lib/playlists/cutoffs.json"
+//! output_key = "rock"
+//! ```
+//!
+//! Tables under `profiles` define named profiles, each with its own argument values (e.g. the
+//! filters and output key of one playlist) taking precedence over the top-level values:
+//!
+//! ```toml
+//! [profiles.jazz]
+//! timeless_args = "genre:jazz"
+//! output_key = "jazz"
+//! ```
+
+use anyhow::Context as _;
+use std::path::Path;
+
+/// Name of the config file within the config directory (see [`crate::dirs`])
+pub const FILE_NAME: &str = "config.toml";
+
+/// Argument values of a config file, each formatted as for an environment variable (lists
+/// separated by commas)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Values of the top-level keys
+    pub values: Vec<(String, String)>,
+    /// Names and values of the profiles, in the order defined
+    pub profiles: Vec<(String, Vec<(String, String)>)>,
+}
+impl Config {
+    /// Reads the config file at the `path`
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+        text.parse().with_context(|| format!("parsing {path:?}"))
+    }
+    /// Values of the profile `name` followed by the top-level values, failing if not defined
+    pub fn profile_values(&self, name: &str) -> anyhow::Result<Vec<(String, String)>> {
+        let Some((_, values)) = self.profiles.iter().find(|(profile, _)| profile == name) else {
+            let names: Vec<_> = self
+                .profiles
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect();
+            anyhow::bail!(
+                "unknown profile {name:?}, expected one of: {}",
+                names.join(", ")
+            )
+        };
+        Ok(values.iter().chain(&self.values).cloned().collect())
+    }
+}
+impl std::str::FromStr for Config {
+    type Err = anyhow::Error;
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let mut table: toml::Table = text.parse()?;
+        let profiles = match table.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles
+                .into_iter()
+                .map(|(name, profile)| {
+                    let toml::Value::Table(profile) = profile else {
+                        anyhow::bail!("expected a table for profile {name:?}")
+                    };
+                    let values =
+                        parse_values(profile).with_context(|| format!("in profile {name:?}"))?;
+                    Ok((name, values))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            Some(_) => anyhow::bail!("expected a table of profiles for key \"profiles\""),
+            None => vec![],
+        };
+        Ok(Self {
+            values: parse_values(table)?,
+            profiles,
+        })
+    }
+}
+
+fn parse_values(table: toml::Table) -> anyhow::Result<Vec<(String, String)>> {
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                toml::Value::Array(items) => items
+                    .into_iter()
+                    .map(scalar)
+                    .collect::<anyhow::Result<Vec<_>>>()?
+                    .join(","),
+                value => scalar(value)?,
+            };
+            Ok((key, value))
+        })
+        .collect::<anyhow::Result<_>>()
+        .context("expected strings, numbers, booleans, or lists of them")
+}
+fn scalar(value: toml::Value) -> anyhow::Result<String> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        value => anyhow::bail!("unsupported {} value {value}", value.type_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let text = r#"
+            beet_command = "python3 -m beets"
+            timeless_args = """
+genre:rock
+^format:MP3"""
+            targets = [30, 50, 70]
+            compilations = 0.5
+            sparklines = true
+        "#;
+        let config: Config = text.parse().expect("valid config");
+        let values: Vec<_> = config
+            .values
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("beet_command", "python3 -m beets"),
+                ("timeless_args", "genre:rock\n^format:MP3"),
+                ("targets", "30,50,70"),
+                ("compilations", "0.5"),
+                ("sparklines", "true"),
+            ]
+        );
+        assert!(config.profiles.is_empty());
+        for invalid in [
+            "[output]\nkey = \"rock\"",
+            "targets = [[30]]",
+            "targets = ",
+            "profiles = 1",
+            "profiles.jazz = 1",
+            "[profiles.jazz.nested]\nkey = 1",
+        ] {
+            assert!(invalid.parse::<Config>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn profile_values() {
+        let text = r#"
+            output_key = "default"
+            targets = [30]
+
+            [profiles.rock]
+            timeless_args = "genre:rock"
+            output_key = "rock"
+
+            [profiles.jazz]
+            timeless_args = "genre:jazz"
+        "#;
+        let config: Config = text.parse().expect("valid config");
+        let names: Vec<_> = config.profiles.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["rock", "jazz"]);
+
+        let value = |profile: &str, key: &str| {
+            let values = config.profile_values(profile).expect("defined profile");
+            values
+                .into_iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value)
+        };
+        assert_eq!(value("rock", "output_key").as_deref(), Some("rock"));
+        assert_eq!(value("jazz", "output_key").as_deref(), Some("default"));
+        assert_eq!(value("jazz", "targets").as_deref(), Some("30"));
+        let err = config.profile_values("pop").expect_err("unknown profile");
+        assert_eq!(
+            err.to_string(),
+            r#"unknown profile "pop", expected one of: rock, jazz"#
+        );
+    }
+}
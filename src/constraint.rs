@@ -0,0 +1,314 @@
+use crate::{artist, transitions_from, Date, DateEntry, Transition, TransitionOptions};
+
+/// Limits on the chosen cutoff, for selecting a breakpoint without a specific target count
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Constraints {
+    /// Most units included after the cutoff
+    pub max_count: Option<usize>,
+    /// Earliest allowed cutoff (e.g. the start of a rolling window)
+    pub not_before: Option<Date>,
+}
+impl Constraints {
+    /// Returns the constraint violated by the `transition` including `count` units, if any
+    fn violation(&self, transition: &Transition<'_>, count: usize) -> Option<Violation> {
+        let Self {
+            max_count,
+            not_before,
+        } = *self;
+        if let Some(max_count) = max_count.filter(|&max_count| count > max_count) {
+            return Some(Violation::MaxCount { max_count, count });
+        }
+        not_before
+            .filter(|&not_before| transition.cutoff < not_before)
+            .map(|not_before| Violation::NotBefore {
+                not_before,
+                cutoff: transition.cutoff,
+            })
+    }
+}
+
+/// Constraint excluding a transition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    MaxCount { max_count: usize, count: usize },
+    NotBefore { not_before: Date, cutoff: Date },
+}
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::MaxCount { max_count, count } => {
+                write!(f, "count {count} exceeds the maximum {max_count}")
+            }
+            Violation::NotBefore { not_before, cutoff } => {
+                write!(
+                    f,
+                    "cutoff {cutoff} precedes the earliest allowed {not_before}"
+                )
+            }
+        }
+    }
+}
+
+/// Transition chosen by [`solve`]
+#[derive(Debug)]
+pub struct Solution<'a> {
+    pub transition: Transition<'a>,
+    /// Number of units included by the transition
+    pub count: usize,
+    /// Constraint preventing an older cutoff, or `None` when limited by the available entries
+    pub limited_by: Option<Violation>,
+}
+
+/// Finds the oldest transition (including the most entries) satisfying all of the `constraints`
+///
+/// Both constraints only exclude older transitions, so the satisfying transitions are the
+/// newest ones, up to the first violation. Returns the violation of the newest transition when
+/// none satisfy the constraints.
+pub fn solve<'a>(
+    items: &'a [DateEntry],
+    constraints: Constraints,
+    options: TransitionOptions,
+) -> anyhow::Result<Solution<'a>> {
+    let running_counts = options.running_counts(items);
+    let mut solution: Option<Solution<'a>> = None;
+    for transition in transitions_from(items, 0, options.snap) {
+        let count = running_counts[transition.index];
+        if let Some(violation) = constraints.violation(&transition, count) {
+            return match solution {
+                Some(solution) => Ok(Solution {
+                    limited_by: Some(violation),
+                    ..solution
+                }),
+                None => anyhow::bail!("constraints conflict, the newest breakpoint {violation}"),
+            };
+        }
+        solution = Some(Solution {
+            transition,
+            count,
+            limited_by: None,
+        });
+    }
+    solution.ok_or_else(|| anyhow::anyhow!("no transitions between dates in the entries"))
+}
+
+/// Finds the newest transition including at least `min_count` entries (e.g. widening a window
+/// after a long gap in additions), ignoring `constraints.not_before`
+pub fn widen<'a>(
+    items: &'a [DateEntry],
+    min_count: usize,
+    constraints: Constraints,
+    options: TransitionOptions,
+) -> anyhow::Result<Solution<'a>> {
+    let constraints = Constraints {
+        not_before: None,
+        ..constraints
+    };
+    let running_counts = options.running_counts(items);
+    let Some(transition) = transitions_from(items, 0, options.snap)
+        .find(|transition| transition.index + 1 >= min_count)
+    else {
+        anyhow::bail!("fewer than the minimum {min_count} entries in the fetched entries")
+    };
+    let count = running_counts[transition.index];
+    if let Some(violation) = constraints.violation(&transition, count) {
+        anyhow::bail!(
+            "constraints conflict, widening to the minimum {min_count} entries {violation}"
+        )
+    }
+    Ok(Solution {
+        transition,
+        count,
+        limited_by: None,
+    })
+}
+
+/// Returns the cutoff including at least `min_count` of the `items` (e.g. of one filter set),
+/// keeping the `cutoff` when it already does, or `None` when the items are too few
+pub fn balance_cutoff(
+    items: &[DateEntry],
+    cutoff: Date,
+    min_count: usize,
+    options: TransitionOptions,
+) -> Option<Date> {
+    let within = items
+        .iter()
+        .take_while(|item| item.date() >= cutoff)
+        .count();
+    if within >= min_count {
+        return Some(cutoff);
+    }
+    transitions_from(items, 0, options.snap)
+        .find(|transition| transition.index + 1 >= min_count)
+        .map(|transition| transition.cutoff)
+}
+
+/// Returns the items beyond the `max_per_artist` newest of each artist (grouped by
+/// [`artist::normalize`]) among the `items` on or after the `cutoff`, newest first
+pub fn artist_surplus(items: &[DateEntry], cutoff: Date, max_per_artist: usize) -> Vec<&DateEntry> {
+    let mut counts = std::collections::HashMap::new();
+    items
+        .iter()
+        .take_while(|item| item.date() >= cutoff)
+        .filter(|item| {
+            let count = counts.entry(artist::normalize(&item.artist)).or_insert(0);
+            *count += 1;
+            *count > max_per_artist
+        })
+        .collect()
+}
+
+/// Action when the chosen cutoff includes fewer entries than the minimum count
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MinCountAction {
+    /// Refuse the cutoff
+    #[default]
+    Error,
+    /// Print a warning, keeping the cutoff
+    Warn,
+    /// Move the cutoff earlier until the minimum is met
+    Widen,
+}
+impl std::str::FromStr for MinCountAction {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "widen" => Ok(Self::Widen),
+            _ => anyhow::bail!("unknown action {s:?}, expected \"error\", \"warn\", or \"widen\""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(dates: &[&str]) -> Vec<DateEntry> {
+        dates
+            .iter()
+            .map(|date| {
+                DateEntry::try_from(format!("{date} 10:00:00 Artist - Album - Title"))
+                    .expect("valid entry in test case")
+            })
+            .collect()
+    }
+    fn date(s: &str) -> Date {
+        s.parse().expect("valid date in test case")
+    }
+
+    #[test]
+    fn solve_constraints() {
+        let items = entries(&[
+            "2024-03-05",
+            "2024-03-04",
+            "2024-03-04",
+            "2024-03-03",
+            "2024-03-02",
+            "2024-03-01",
+        ]);
+        let solve = |max_count, not_before: Option<&str>| {
+            let constraints = Constraints {
+                max_count,
+                not_before: not_before.map(date),
+            };
+            solve(&items, constraints, TransitionOptions::default())
+                .map(|solution| (solution.transition.cutoff.to_string(), solution.limited_by))
+                .map_err(|err| err.to_string())
+        };
+
+        assert_eq!(
+            solve(Some(4), None),
+            Ok((
+                "2024-03-03".to_owned(),
+                Some(Violation::MaxCount {
+                    max_count: 4,
+                    count: 5
+                })
+            ))
+        );
+        assert_eq!(
+            solve(Some(10), Some("2024-03-04")),
+            Ok((
+                "2024-03-04".to_owned(),
+                Some(Violation::NotBefore {
+                    not_before: date("2024-03-04"),
+                    cutoff: date("2024-03-03")
+                })
+            ))
+        );
+        assert_eq!(solve(None, None), Ok(("2024-03-02".to_owned(), None)));
+        assert_eq!(
+            solve(Some(0), None),
+            Err(
+                "constraints conflict, the newest breakpoint count 1 exceeds the maximum 0"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn widen_to_min_count() {
+        let items = entries(&["2024-03-05", "2024-01-04", "2024-01-04", "2024-01-03"]);
+        let widen = |max_count| {
+            let constraints = Constraints {
+                max_count,
+                not_before: Some(date("2024-03-01")),
+            };
+            widen(&items, 2, constraints, TransitionOptions::default())
+                .map(|solution| (solution.transition.cutoff.to_string(), solution.count))
+                .map_err(|err| err.to_string())
+        };
+
+        assert_eq!(widen(None), Ok(("2024-01-04".to_owned(), 3)));
+        assert!(widen(Some(2)).is_err());
+    }
+
+    #[test]
+    fn balance_cutoff_per_set() {
+        let items = entries(&["2024-03-05", "2024-03-04", "2024-01-04", "2024-01-03"]);
+        let balance = |min_count| {
+            balance_cutoff(
+                &items,
+                date("2024-03-04"),
+                min_count,
+                TransitionOptions::default(),
+            )
+            .map(|cutoff| cutoff.to_string())
+        };
+
+        assert_eq!(balance(2), Some("2024-03-04".to_owned()));
+        assert_eq!(balance(3), Some("2024-01-04".to_owned()));
+        assert_eq!(balance(4), None);
+    }
+
+    #[test]
+    fn artist_surplus_beyond_cap() {
+        let mut items = entries(&[
+            "2024-03-05",
+            "2024-03-04",
+            "2024-03-03",
+            "2024-03-02",
+            "2024-01-04",
+        ]);
+        for (item, artist) in items.iter_mut().zip([
+            "Björk",
+            "Beyoncé",
+            "BJORK",
+            "Bjork feat. Thom Yorke",
+            "Björk",
+        ]) {
+            item.artist = artist.to_owned();
+        }
+        let surplus = |max_per_artist| {
+            artist_surplus(&items, date("2024-03-01"), max_per_artist)
+                .iter()
+                .map(|item| item.artist.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(surplus(1), ["BJORK", "Bjork feat. Thom Yorke"]);
+        assert_eq!(surplus(2), ["Bjork feat. Thom Yorke"]);
+        assert!(surplus(3).is_empty());
+    }
+}
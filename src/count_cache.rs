@@ -0,0 +1,195 @@
+//! Cache of the item counts queried from beets, to avoid re-running identical count queries
+//! within a run (or across quick successive runs, when stored in a file)
+
+use crate::watch::modified_with_wal;
+use anyhow::Context as _;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+/// Counts keyed by the query (the command line, including the filters and dates), discarded
+/// when the beets library database changes
+#[derive(Debug)]
+pub struct CountCache {
+    /// Library database checked for changes before each lookup, if known
+    library_db: Option<PathBuf>,
+    state: Mutex<CacheState>,
+}
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Modification time of the library database when the counts were queried (in nanoseconds
+    /// since the epoch)
+    db_modified: Option<u128>,
+    counts: HashMap<String, usize>,
+}
+impl CountCache {
+    /// Empty cache, invalidated when the `library_db` changes (if specified)
+    pub fn new(library_db: Option<PathBuf>) -> Self {
+        Self {
+            library_db,
+            state: Mutex::default(),
+        }
+    }
+    /// Loads the counts written by [`Self::save`] to the `path` (empty if the file is missing)
+    pub fn load(path: &Path, library_db: Option<PathBuf>) -> anyhow::Result<Self> {
+        let cache = Self::new(library_db);
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(cache),
+            Err(err) => return Err(err).with_context(|| format!("reading {path:?}")),
+        };
+        let state = parse_state(&text).with_context(|| format!("parsing {path:?}"))?;
+        *cache.lock() = state;
+        Ok(cache)
+    }
+    /// Writes the counts to the `path` atomically (via a temporary file renamed into place), so
+    /// concurrent runs sharing the file never read it partially written
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = {
+            let state = self.lock();
+            let db_modified = state
+                .db_modified
+                .map(|nanos| nanos.to_string())
+                .unwrap_or_default();
+            let mut counts: Vec<_> = state.counts.iter().collect();
+            counts.sort();
+            std::iter::once(format!("{db_modified}\n"))
+                .chain(
+                    counts
+                        .into_iter()
+                        .map(|(key, count)| format!("{count}\t{key}\n")),
+                )
+                .collect::<String>()
+        };
+        let mut temp_path = path.to_owned().into_os_string();
+        temp_path.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = PathBuf::from(temp_path);
+        std::fs::write(&temp_path, text).with_context(|| format!("writing {temp_path:?}"))?;
+        std::fs::rename(&temp_path, path).with_context(|| format!("replacing {path:?}"))
+    }
+    /// Returns the cached count for the `key`, or the result of the `query` (cached on success)
+    ///
+    /// Runs the `query` without caching when the library database cannot be read.
+    pub fn get_or_query(
+        &self,
+        key: &str,
+        query: impl FnOnce() -> anyhow::Result<usize>,
+    ) -> anyhow::Result<usize> {
+        let db_modified = match &self.library_db {
+            Some(library_db) => match modified_nanos(library_db) {
+                Ok(nanos) => Some(nanos),
+                Err(_) => return query(),
+            },
+            None => None,
+        };
+        {
+            let mut state = self.lock();
+            if state.db_modified != db_modified {
+                *state = CacheState {
+                    db_modified,
+                    counts: HashMap::new(),
+                };
+            }
+            if let Some(&count) = state.counts.get(key) {
+                return Ok(count);
+            }
+        }
+        // query without holding the lock, for concurrent queries (e.g. counting several keys)
+        let count = query()?;
+        let mut state = self.lock();
+        if state.db_modified == db_modified {
+            state.counts.insert(key.to_owned(), count);
+        }
+        Ok(count)
+    }
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn modified_nanos(path: &Path) -> anyhow::Result<u128> {
+    let modified = modified_with_wal(path)?;
+    Ok(modified.duration_since(SystemTime::UNIX_EPOCH)?.as_nanos())
+}
+
+fn parse_state(text: &str) -> anyhow::Result<CacheState> {
+    let mut lines = text.lines();
+    let db_modified = match lines.next().unwrap_or_default() {
+        "" => None,
+        nanos => Some(
+            nanos
+                .parse()
+                .context("invalid database modification time")?,
+        ),
+    };
+    let counts = lines
+        .map(|line| {
+            let (count, key) = line
+                .split_once('\t')
+                .with_context(|| format!("missing tab in line {line:?}"))?;
+            let count = count
+                .parse()
+                .with_context(|| format!("invalid count in line {line:?}"))?;
+            Ok((key.to_owned(), count))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(CacheState {
+        db_modified,
+        counts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, time::Duration};
+
+    #[test]
+    fn invalidate_on_change() {
+        let dir = std::env::temp_dir();
+        let db_path = dir.join(format!("count_cache_test_{}.db", std::process::id()));
+        let cache_path = dir.join(format!("count_cache_test_{}.tsv", std::process::id()));
+        std::fs::write(&db_path, "v1").expect("write test file");
+        let set_modified = |secs| {
+            let file = std::fs::File::options()
+                .write(true)
+                .open(&db_path)
+                .expect("open test file");
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .expect("set modified time");
+        };
+        set_modified(1_000);
+        let queries = &Cell::new(0);
+        let query = |count| {
+            move || {
+                queries.set(queries.get() + 1);
+                Ok(count)
+            }
+        };
+
+        let cache = CountCache::new(Some(db_path.clone()));
+        let first = cache.get_or_query("added:2024-03-01..", query(5));
+        let cached = cache.get_or_query("added:2024-03-01..", query(6));
+        let other = cache.get_or_query("added:2024-04-01..", query(2));
+        cache.save(&cache_path).expect("save");
+
+        let loaded = CountCache::load(&cache_path, Some(db_path.clone())).expect("load");
+        let reloaded = loaded.get_or_query("added:2024-03-01..", query(7));
+        set_modified(2_000);
+        let changed = loaded.get_or_query("added:2024-03-01..", query(8));
+
+        std::fs::remove_file(&db_path).expect("remove test file");
+        std::fs::remove_file(&cache_path).expect("remove cache file");
+        let counts: Vec<usize> = [first, cached, other, reloaded, changed]
+            .into_iter()
+            .map(|count| count.expect("count"))
+            .collect();
+        assert_eq!(counts, [5, 5, 2, 5, 8]);
+        assert_eq!(queries.get(), 3);
+    }
+}
@@ -0,0 +1,587 @@
+use std::str::FromStr;
+
+/// Calendar date in the proleptic Gregorian calendar
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    // NOTE: field order defines the derived ordering
+    year: i32,
+    month: u8,
+    day: u8,
+}
+impl Date {
+    pub fn new(year: i32, month: u8, day: u8) -> anyhow::Result<Self> {
+        if !(1..=12).contains(&month) {
+            anyhow::bail!("month out of range: {month}")
+        }
+        let month_length = days_in_month(year, month);
+        if !(1..=month_length).contains(&day) {
+            anyhow::bail!("day out of range for {year:04}-{month:02}: {day}")
+        }
+        Ok(Self { year, month, day })
+    }
+    pub fn year(self) -> i32 {
+        self.year
+    }
+    pub fn month(self) -> u8 {
+        self.month
+    }
+    pub fn day(self) -> u8 {
+        self.day
+    }
+    /// Number of days since 1970-01-01 (negative for earlier dates)
+    pub fn days_since_epoch(self) -> i64 {
+        // algorithm `days_from_civil` from https://howardhinnant.github.io/date_algorithms.html
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+    /// Inverse of [`Self::days_since_epoch`]
+    pub fn from_days_since_epoch(days: i64) -> Self {
+        // algorithm `civil_from_days` from https://howardhinnant.github.io/date_algorithms.html
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Self {
+            year: i32::try_from(year).expect("year in range"),
+            month: u8::try_from(month).expect("month in range"),
+            day: u8::try_from(day).expect("day in range"),
+        }
+    }
+    /// Current date in UTC
+    pub fn today() -> Self {
+        Timestamp::now().date()
+    }
+    /// Number of days from `self` until `later` (negative if `later` is earlier)
+    pub fn days_until(self, later: Self) -> i64 {
+        later.days_since_epoch() - self.days_since_epoch()
+    }
+    /// Date `days` later (or earlier, if negative)
+    pub fn add_days(self, days: i64) -> Self {
+        Self::from_days_since_epoch(self.days_since_epoch() + days)
+    }
+    /// Same day of the month `months` earlier, clamped to the end of shorter months
+    pub fn sub_months(self, months: u32) -> Self {
+        let month_index = i64::from(self.year) * 12 + i64::from(self.month - 1) - i64::from(months);
+        let year = i32::try_from(month_index.div_euclid(12)).expect("year in range");
+        let month = u8::try_from(month_index.rem_euclid(12) + 1).expect("month in range");
+        let day = self.day.min(days_in_month(year, month));
+        Self { year, month, day }
+    }
+    /// Describes the age relative to `today`, e.g. "7 weeks ago"
+    pub fn describe_ago(self, today: Self) -> String {
+        describe_days_ago(self.days_until(today))
+    }
+    /// Beets range query atom for items with `field` on or after this date, e.g. `added:2024-03-01..`
+    pub fn query_range_after(self, field: &str) -> String {
+        format!("{field}:{self}..")
+    }
+    /// Day of the week, numbered from Monday = 0 to Sunday = 6
+    pub fn weekday_from_monday(self) -> u8 {
+        // 1970-01-01 was a Thursday
+        let weekday = (self.days_since_epoch() + 3).rem_euclid(7);
+        u8::try_from(weekday).expect("weekday in range")
+    }
+    pub fn start_of_month(self) -> Self {
+        Self { day: 1, ..self }
+    }
+    /// Monday of the same week
+    pub fn start_of_week(self) -> Self {
+        let days = self.days_since_epoch() - i64::from(self.weekday_from_monday());
+        Self::from_days_since_epoch(days)
+    }
+}
+impl FromStr for Date {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        // YYYY-MM-DD
+        let parse = || {
+            let (year, rest) = s.split_once('-')?;
+            let (month, day) = rest.split_once('-')?;
+            Some((digits(year, 4)?, digits(month, 2)?, digits(day, 2)?))
+        };
+        let Some((year, month, day)) = parse() else {
+            anyhow::bail!("invalid date {s:?}, expected YYYY-MM-DD")
+        };
+        Self::new(year, month, day)
+    }
+}
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { year, month, day } = self;
+        write!(f, "{year:04}-{month:02}-{day:02}")
+    }
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Default `time_format` of beets, the only format parsed as a [`Timestamp`]
+///
+/// A customized `time_format` in the beets config fails to parse, instead of reading the fields
+/// at the wrong offsets.
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Date and time of day, as formatted by beets (`YYYY-MM-DD HH:MM:SS`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    // NOTE: field order defines the derived ordering
+    date: Date,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+impl Timestamp {
+    /// Start of the Unix epoch, e.g. for beets items with an `added` value of 0
+    pub const UNIX_EPOCH: Self = Self {
+        date: Date {
+            year: 1970,
+            month: 1,
+            day: 1,
+        },
+        hour: 0,
+        minute: 0,
+        second: 0,
+    };
+    pub fn new(date: Date, hour: u8, minute: u8, second: u8) -> anyhow::Result<Self> {
+        if hour >= 24 || minute >= 60 || second >= 60 {
+            anyhow::bail!("time out of range: {hour:02}:{minute:02}:{second:02}")
+        }
+        Ok(Self {
+            date,
+            hour,
+            minute,
+            second,
+        })
+    }
+    /// Current date and time in UTC
+    pub fn now() -> Self {
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let days = i64::try_from(seconds / SECONDS_PER_DAY).expect("days in range");
+        let time = seconds % SECONDS_PER_DAY;
+        let [hour, minute, second] = [time / 3600, time / 60 % 60, time % 60]
+            .map(|part| u8::try_from(part).expect("time in range"));
+        Self {
+            date: Date::from_days_since_epoch(days),
+            hour,
+            minute,
+            second,
+        }
+    }
+    pub fn date(self) -> Date {
+        self.date
+    }
+    /// Whether the timestamp is on the first day of the Unix epoch in any time zone, e.g. an
+    /// `added` value of 0 (treated as missing)
+    pub fn is_epoch(self) -> bool {
+        self.date <= Self::UNIX_EPOCH.date.add_days(1)
+    }
+}
+impl FromStr for Timestamp {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        // YYYY-MM-DD HH:MM:SS
+        let parse = || {
+            let (date, time) = s.split_once(' ')?;
+            let mut parts = time.split(':');
+            let mut next_part = || digits(parts.next()?, 2);
+            let time = (next_part()?, next_part()?, next_part()?);
+            parts.next().is_none().then_some((date, time))
+        };
+        let Some((date, (hour, minute, second))) = parse() else {
+            anyhow::bail!(
+                "invalid timestamp {s:?}, expected YYYY-MM-DD HH:MM:SS (the beets `time_format` \
+                 {TIME_FORMAT:?})"
+            )
+        };
+        Self::new(date.parse()?, hour, minute, second)
+    }
+}
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            date,
+            hour,
+            minute,
+            second,
+        } = self;
+        write!(f, "{date} {hour:02}:{minute:02}:{second:02}")
+    }
+}
+
+/// Describes a number of elapsed days in human terms, e.g. "7 weeks ago"
+pub fn describe_days_ago(days: i64) -> String {
+    let (amount, unit) = match days {
+        ..=-1 => return "in the future".to_owned(),
+        0 => return "today".to_owned(),
+        1 => return "yesterday".to_owned(),
+        2..=13 => (days, "day"),
+        14..=59 => (days / 7, "week"),
+        60..=729 => (days / 30, "month"),
+        _ => (days / 365, "year"),
+    };
+    format!("{amount} {unit}s ago")
+}
+
+/// Parses exactly `len` ASCII digits (rejecting signs and whitespace, accepted by `str::parse`)
+fn digits<T: FromStr>(part: &str, len: usize) -> Option<T> {
+    if part.len() != len || !part.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Division of the calendar into consecutive periods, e.g. for rounding candidate cutoffs
+pub trait Period: std::fmt::Debug {
+    /// Returns the first date of the period containing `date`, or `None` if the date precedes
+    /// the first period
+    fn period_start(&self, date: Date) -> Option<Date>;
+}
+
+/// Calendar period used to round candidate cutoffs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Snap {
+    /// First day of a calendar month
+    Month,
+    /// Monday of an ISO week
+    Week,
+    /// Month starting on the `start_day` (at most 28), e.g. a fiscal month
+    FiscalMonth { start_day: u8 },
+}
+impl Period for Snap {
+    fn period_start(&self, date: Date) -> Option<Date> {
+        let start = match *self {
+            Snap::Month => date.start_of_month(),
+            Snap::Week => date.start_of_week(),
+            Snap::FiscalMonth { start_day } => {
+                let month = if date.day() >= start_day {
+                    date.start_of_month()
+                } else {
+                    date.start_of_month().sub_months(1)
+                };
+                month.add_days(i64::from(start_day) - 1)
+            }
+        };
+        Some(start)
+    }
+}
+impl FromStr for Snap {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "month" => Ok(Self::Month),
+            "week" => Ok(Self::Week),
+            _ => {
+                let Some(start_day) = s.strip_prefix("fiscal-month:") else {
+                    anyhow::bail!(
+                        "unknown snap period {s:?}, expected \"month\", \"week\", or \"fiscal-month:DAY\""
+                    )
+                };
+                let start_day: u8 = start_day.parse()?;
+                if !(1..=28).contains(&start_day) {
+                    anyhow::bail!("fiscal month start day {start_day} must be from 1 to 28");
+                }
+                Ok(Self::FiscalMonth { start_day })
+            }
+        }
+    }
+}
+
+/// Periods starting on each of a list of dates, e.g. "since my last DJ set"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anchors {
+    /// Sorted from oldest to newest
+    dates: Vec<Date>,
+}
+impl Anchors {
+    pub fn new(mut dates: Vec<Date>) -> Self {
+        dates.sort_unstable();
+        dates.dedup();
+        Self { dates }
+    }
+}
+impl Period for Anchors {
+    fn period_start(&self, date: Date) -> Option<Date> {
+        let after = self.dates.partition_point(|&anchor| anchor <= date);
+        after.checked_sub(1).map(|index| self.dates[index])
+    }
+}
+impl FromStr for Anchors {
+    type Err = anyhow::Error;
+    /// Parses one date per line, ignoring blank lines and `#` comments
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let dates = s
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index, line.split('#').next().unwrap_or_default().trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(index, line)| {
+                line.parse()
+                    .map_err(|err: anyhow::Error| err.context(format!("anchor line {}", index + 1)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self::new(dates))
+    }
+}
+
+/// Meaning of a stored (or displayed) cutoff date, relative to the items added on that date
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Items added on the cutoff date are included, e.g. `added:2024-03-02..`
+    #[default]
+    Inclusive,
+    /// Items added on the cutoff date are excluded, only later items are included (the query
+    /// bound is the next day, e.g. `added:2024-03-02..` for the cutoff 2024-03-01)
+    Exclusive,
+}
+impl Boundary {
+    /// Returns the cutoff date for the `earliest` date included
+    pub fn cutoff(self, earliest: Date) -> Date {
+        match self {
+            Boundary::Inclusive => earliest,
+            Boundary::Exclusive => earliest.add_days(-1),
+        }
+    }
+    /// Returns the earliest date included by the `cutoff` date (inverse of [`Self::cutoff`])
+    pub fn earliest(self, cutoff: Date) -> Date {
+        match self {
+            Boundary::Inclusive => cutoff,
+            Boundary::Exclusive => cutoff.add_days(1),
+        }
+    }
+    /// Describes the cutoff for the `earliest` date included, e.g. "on or after 2024-03-02"
+    /// or "after 2024-03-01"
+    pub fn describe(self, earliest: Date) -> String {
+        let cutoff = self.cutoff(earliest);
+        match self {
+            Boundary::Inclusive => format!("on or after {cutoff}"),
+            Boundary::Exclusive => format!("after {cutoff}"),
+        }
+    }
+}
+impl FromStr for Boundary {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "inclusive" => Ok(Self::Inclusive),
+            "exclusive" => Ok(Self::Exclusive),
+            _ => anyhow::bail!("unknown boundary {s:?}, expected \"inclusive\" or \"exclusive\""),
+        }
+    }
+}
+
+/// Length of a rolling window ending today, e.g. `90d`, `12w`, `6m`, or `1y`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Window {
+    amount: u32,
+    unit: WindowUnit,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WindowUnit {
+    Days,
+    Weeks,
+    Months,
+    Years,
+}
+impl Window {
+    /// Returns the first date of the window ending on `today`
+    pub fn start(self, today: Date) -> Date {
+        let Self { amount, unit } = self;
+        match unit {
+            WindowUnit::Days => today.add_days(-i64::from(amount)),
+            WindowUnit::Weeks => today.add_days(-7 * i64::from(amount)),
+            WindowUnit::Months => today.sub_months(amount),
+            WindowUnit::Years => today.sub_months(amount.saturating_mul(12)),
+        }
+    }
+}
+impl FromStr for Window {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let split_index = s.len().saturating_sub(1);
+        let (amount, unit) = s.split_at_checked(split_index).unwrap_or((s, ""));
+        let unit = match unit {
+            "d" => WindowUnit::Days,
+            "w" => WindowUnit::Weeks,
+            "m" => WindowUnit::Months,
+            "y" => WindowUnit::Years,
+            _ => anyhow::bail!("invalid window {s:?}, expected a number with unit d, w, m, or y"),
+        };
+        let amount = amount
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid window {s:?}, expected a number with unit"))?;
+        Ok(Self { amount, unit })
+    }
+}
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { amount, unit } = self;
+        let unit = match unit {
+            WindowUnit::Days => "d",
+            WindowUnit::Weeks => "w",
+            WindowUnit::Months => "m",
+            WindowUnit::Years => "y",
+        };
+        write!(f, "{amount}{unit}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> Date {
+        s.parse().expect("valid date in test case")
+    }
+
+    #[test]
+    fn days_since_epoch_roundtrip() {
+        for (s, days) in [
+            ("1970-01-01", 0),
+            ("1969-12-31", -1),
+            ("2000-02-29", 11_016),
+            ("2024-03-01", 19_783),
+        ] {
+            assert_eq!(date(s).days_since_epoch(), days, "{s}");
+            assert_eq!(Date::from_days_since_epoch(days), date(s), "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_rejects_invalid() {
+        for s in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-1-01",
+            "20240101",
+            "",
+            "2024-+1-01",
+            "+024-01-01",
+            "2024-01-٠1",
+        ] {
+            assert!(s.parse::<Date>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn timestamp_roundtrip() {
+        let timestamp: Timestamp = "2024-03-01 09:05:00".parse().expect("valid timestamp");
+        assert_eq!(timestamp.date(), date("2024-03-01"));
+        assert_eq!(timestamp.to_string(), "2024-03-01 09:05:00");
+        assert!(timestamp < "2024-03-01 10:00:00".parse().expect("valid timestamp"));
+
+        for s in [
+            "2024-03-01",
+            "2024-03-01 9:05:00",
+            "2024-03-01 24:00:00",
+            "2024-03-01 09:05",
+            "2024-03-01 +9:05:00",
+            "2024-03-01T09:05:00",
+            "03/01/2024 09:05:00",
+        ] {
+            assert!(s.parse::<Timestamp>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn window_start() {
+        let start = |window: &str, today| {
+            let window: Window = window.parse().expect("valid window in test case");
+            window.start(date(today)).to_string()
+        };
+        assert_eq!(start("90d", "2024-03-31"), "2024-01-01");
+        assert_eq!(start("2w", "2024-03-01"), "2024-02-16");
+        assert_eq!(start("1m", "2024-03-31"), "2024-02-29");
+        assert_eq!(start("14m", "2024-03-15"), "2023-01-15");
+        assert_eq!(start("1y", "2024-02-29"), "2023-02-28");
+
+        for s in ["", "d", "90", "-1d", "90x"] {
+            assert!(s.parse::<Window>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn days_ago() {
+        assert_eq!(describe_days_ago(0), "today");
+        assert_eq!(describe_days_ago(5), "5 days ago");
+        assert_eq!(describe_days_ago(49), "7 weeks ago");
+        assert_eq!(describe_days_ago(400), "13 months ago");
+        assert_eq!(describe_days_ago(800), "2 years ago");
+    }
+
+    #[test]
+    fn snap_period_start() {
+        let period_start = |snap: Snap, s| snap.period_start(date(s)).expect("always a period");
+        // 2024-03-14 is a Thursday
+        assert_eq!(period_start(Snap::Month, "2024-03-14"), date("2024-03-01"));
+        assert_eq!(period_start(Snap::Week, "2024-03-14"), date("2024-03-11"));
+        assert_eq!(period_start(Snap::Week, "2024-03-11"), date("2024-03-11"));
+        assert_eq!(period_start(Snap::Week, "2024-03-03"), date("2024-02-26"));
+
+        let fiscal: Snap = "fiscal-month:15".parse().expect("valid snap");
+        assert_eq!(period_start(fiscal, "2024-03-15"), date("2024-03-15"));
+        assert_eq!(period_start(fiscal, "2024-03-14"), date("2024-02-15"));
+        assert_eq!(period_start(fiscal, "2024-01-02"), date("2023-12-15"));
+        assert!("fiscal-month:30".parse::<Snap>().is_err());
+    }
+
+    #[test]
+    fn anchor_period_start() {
+        let anchors: Anchors = "2024-03-09 # club night\n\n2024-01-20\n"
+            .parse()
+            .expect("valid anchors");
+        assert_eq!(anchors.period_start(date("2024-01-19")), None);
+        assert_eq!(
+            anchors.period_start(date("2024-03-08")),
+            Some(date("2024-01-20"))
+        );
+        assert_eq!(
+            anchors.period_start(date("2024-03-09")),
+            Some(date("2024-03-09"))
+        );
+        assert!("2024-03-09\nsoon".parse::<Anchors>().is_err());
+    }
+
+    #[test]
+    fn boundary_semantics() {
+        let earliest = date("2024-03-01");
+        for boundary in [Boundary::Inclusive, Boundary::Exclusive] {
+            assert_eq!(boundary.earliest(boundary.cutoff(earliest)), earliest);
+        }
+        assert_eq!(Boundary::Exclusive.cutoff(earliest), date("2024-02-29"));
+        assert_eq!(
+            Boundary::Inclusive.describe(earliest),
+            "on or after 2024-03-01"
+        );
+        assert_eq!(Boundary::Exclusive.describe(earliest), "after 2024-02-29");
+        assert_eq!(
+            "exclusive".parse::<Boundary>().expect("valid"),
+            Boundary::Exclusive
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Direct reads of the beets library database (`library.db`), instead of spawning `beet list` for
+//! each query
+//!
+//! Only the `items` table is read, so the date field must be a fixed column (e.g. `added` or
+//! `mtime`, not a flexible attribute). Beets query atoms are not translated to SQL, so filtered
+//! queries (e.g. by the timeless args) still spawn `beet list` instead.
+
+use crate::date::{Date, TIME_FORMAT};
+use anyhow::Context as _;
+use rusqlite::{Connection, OpenFlags};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Read-only connection to a beets library database
+#[derive(Debug)]
+pub struct LibraryDb {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+impl LibraryDb {
+    /// Opens the database at the `path` read-only (failing if missing, unlike SQLite's default)
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_context(|| format!("opening beets library {path:?}"))?;
+        Ok(Self {
+            path: path.to_owned(),
+            connection: Mutex::new(connection),
+        })
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Lists the items between the dates (inclusive, open-ended when unspecified) newest first,
+    /// stopping after `max_lines`
+    ///
+    /// Each line has the tab-separated fields of [`crate::DateEntry::parse_fields`], as formatted by
+    /// `beet list` (with local times, and without the `filesize` which is not stored).
+    pub fn list_lines(
+        &self,
+        date_field: &str,
+        range: (Option<Date>, Option<Date>),
+        max_lines: usize,
+    ) -> anyhow::Result<Vec<u8>> {
+        let connection = self.lock();
+        let column = checked_column(&connection, date_field)?;
+        let columns = ["albumartist", "album", "artist", "title"].map(|name| format!(", {name}"));
+        let sql = format!(
+            "SELECT id, strftime('{TIME_FORMAT}', {column}, 'unixepoch', 'localtime'){columns}, \
+             mb_albumid, genre, format, comp, {play_count} FROM items WHERE {range} \
+             ORDER BY {column} DESC LIMIT ?",
+            columns = columns.concat(),
+            // flexible attribute (e.g. set by the mpdstats plugin)
+            play_count = "(SELECT value FROM item_attributes \
+                          WHERE entity_id = items.id AND key = 'play_count')",
+            range = range_condition(&column, range),
+        );
+        let limit = i64::try_from(max_lines).unwrap_or(i64::MAX);
+        let mut statement = connection.prepare(&sql).context(sql.clone())?;
+        let mut rows = statement.query([limit])?;
+        let mut lines = vec![];
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let mut fields = vec![id.to_string()];
+            for index in 1..=5 {
+                let text: Option<String> = row.get(index)?;
+                fields.push(text.unwrap_or_default());
+            }
+            // `filesize` is a property of the file, not stored in the library
+            fields.push(String::new());
+            for index in 6..=8 {
+                let text: Option<String> = row.get(index)?;
+                fields.push(text.unwrap_or_default());
+            }
+            let comp: Option<bool> = row.get(9)?;
+            let comp = if comp.unwrap_or(false) {
+                "True"
+            } else {
+                "False"
+            };
+            fields.push(comp.to_owned());
+            let play_count: Option<String> = row.get(10)?;
+            fields.push(play_count.unwrap_or_default());
+
+            lines.extend_from_slice(fields.join("\t").as_bytes());
+            lines.push(b'\n');
+        }
+        Ok(lines)
+    }
+    /// Counts the items between the dates (inclusive, open-ended when unspecified)
+    pub fn count_between(
+        &self,
+        date_field: &str,
+        range: (Option<Date>, Option<Date>),
+    ) -> anyhow::Result<usize> {
+        let connection = self.lock();
+        let column = checked_column(&connection, date_field)?;
+        let sql = format!(
+            "SELECT COUNT(*) FROM items WHERE {range}",
+            range = range_condition(&column, range),
+        );
+        let count: i64 = connection
+            .query_row(&sql, [], |row| row.get(0))
+            .context(sql)?;
+        Ok(usize::try_from(count).expect("nonnegative count"))
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Returns the quoted column of the `field` in the `items` table, failing for flexible attributes
+/// (stored in a separate table)
+fn checked_column(connection: &Connection, field: &str) -> anyhow::Result<String> {
+    let mut statement = connection.prepare("SELECT name FROM pragma_table_info('items')")?;
+    let columns = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()
+        .context("reading the columns of the items table")?;
+    if columns.is_empty() {
+        anyhow::bail!("no items table in the database, expected a beets library");
+    }
+    if !columns.iter().any(|column| column == field) {
+        anyhow::bail!(
+            "date field {field:?} is not a column of the items table (flexible attributes are \
+             only available through `beet`)"
+        );
+    }
+    Ok(format!("\"{field}\""))
+}
+
+/// SQL condition for the `column` between the dates in local time, as in the beets range query
+/// `FIELD:FROM..TO`
+fn range_condition(column: &str, (from, to): (Option<Date>, Option<Date>)) -> String {
+    let day = format!("date({column}, 'unixepoch', 'localtime')");
+    let bounds: Vec<_> = [(from, ">="), (to, "<=")]
+        .into_iter()
+        .filter_map(|(bound, operator)| Some(format!("{day} {operator} '{}'", bound?)))
+        .collect();
+    if bounds.is_empty() {
+        "1".to_owned()
+    } else {
+        bounds.join(" AND ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DateEntry;
+
+    #[test]
+    fn list_and_count_items() {
+        let path = std::env::temp_dir().join(format!("db_test_{}.db", std::process::id()));
+        {
+            let connection = Connection::open(&path).expect("create test database");
+            connection
+                .execute_batch(
+                    "CREATE TABLE items (id INTEGER PRIMARY KEY, added FLOAT, albumartist TEXT, \
+                     album TEXT, artist TEXT, title TEXT, mb_albumid TEXT, genre TEXT, \
+                     format TEXT, comp INTEGER);
+                     CREATE TABLE item_attributes (id INTEGER PRIMARY KEY, entity_id INTEGER, \
+                     key TEXT, value TEXT);
+                     INSERT INTO item_attributes VALUES (1, 3, 'play_count', '4');
+                     INSERT INTO items VALUES (1, 1709294400.5, 'Air', 'Moon Safari', 'Air', \
+                     'Sexy Boy', '', 'Electronic', 'FLAC', 0);
+                     INSERT INTO items VALUES (2, 1709467200, 'Various Artists', 'Hits', \
+                     'Björk', 'Army of Me', NULL, NULL, 'MP3', 1);
+                     INSERT INTO items VALUES (3, 1709553600, 'Air', 'Talkie Walkie', 'Air', \
+                     'Cherry Blossom Girl', 'abc', NULL, NULL, 0);",
+                )
+                .expect("populate test database");
+        }
+        let db = LibraryDb::open(&path).expect("open");
+        let date = |date: &str| date.parse::<Date>().expect("date");
+
+        // noon UTC, the same date in most time zones
+        let lines = db.list_lines("added", (None, None), 2).expect("list items");
+        let entries: Vec<_> = String::from_utf8(lines)
+            .expect("utf8")
+            .lines()
+            .map(|line| DateEntry::parse_fields(line).expect("valid line"))
+            .collect();
+        assert_eq!(entries.len(), 2);
+        let [newest, second] = &entries[..] else {
+            unreachable!()
+        };
+        assert_eq!(
+            (
+                newest.date(),
+                newest.title.as_str(),
+                newest.genre.as_deref()
+            ),
+            (date("2024-03-04"), "Cherry Blossom Girl", None)
+        );
+        assert_eq!(newest.mb_albumid.as_deref(), Some("abc"));
+        assert_eq!((newest.play_count, second.play_count), (Some(4), None));
+        assert!(second.compilation && second.filesize.is_none());
+
+        let count = |range| db.count_between("added", range).expect("count");
+        assert_eq!(count((None, None)), 3);
+        assert_eq!(count((Some(date("2024-03-03")), None)), 2);
+        assert_eq!(count((None, Some(date("2024-03-03")))), 2);
+        assert_eq!(
+            count((Some(date("2024-03-03")), Some(date("2024-03-03")))),
+            1
+        );
+        assert!(db.count_between("playlist", (None, None)).is_err());
+
+        std::fs::remove_file(&path).expect("remove test file");
+    }
+}
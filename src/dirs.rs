@@ -0,0 +1,157 @@
+//! Per-user directories for the config, cache, and state files (e.g. history), following the XDG
+//! base directories, with the macOS and Windows equivalents
+
+use anyhow::Context as _;
+use std::{ffi::OsString, path::PathBuf};
+
+/// Name of the subdirectory within each base directory
+const APP_DIR: &str = "beet_smart_cutoff";
+
+/// Kind of file, determining its base directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Settings edited by the user
+    Config,
+    /// Data that may be deleted at any time (e.g. cached counts)
+    Cache,
+    /// Data kept across runs but not worth backing up (e.g. sessions, history, and logs)
+    State,
+}
+impl Kind {
+    /// XDG variable overriding the base directory, and its default relative to the home directory
+    fn xdg(self) -> (&'static str, &'static str) {
+        match self {
+            Kind::Config => ("XDG_CONFIG_HOME", ".config"),
+            Kind::Cache => ("XDG_CACHE_HOME", ".cache"),
+            Kind::State => ("XDG_STATE_HOME", ".local/state"),
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Config => "config",
+            Kind::Cache => "cache",
+            Kind::State => "state",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Platform {
+    /// XDG base directories (Linux and other unix)
+    Xdg,
+    MacOs,
+    Windows,
+}
+impl Platform {
+    const CURRENT: Self = if cfg!(target_os = "macos") {
+        Self::MacOs
+    } else if cfg!(windows) {
+        Self::Windows
+    } else {
+        Self::Xdg
+    };
+}
+
+/// Returns the directory for files of the `kind`, creating it if missing
+pub fn dir(kind: Kind) -> anyhow::Result<PathBuf> {
+    let dir =
+        dir_in(kind, Platform::CURRENT, |name| std::env::var_os(name)).with_context(|| {
+            format!(
+                "no {} directory found, set HOME (or {})",
+                kind.name(),
+                kind.xdg().0
+            )
+        })?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {dir:?}"))?;
+    Ok(dir)
+}
+
+/// Returns the `path`, or else the `file_name` within the directory for files of the `kind`
+/// (e.g. for an option given without a value)
+pub fn or_default(path: Option<PathBuf>, kind: Kind, file_name: &str) -> anyhow::Result<PathBuf> {
+    match path {
+        Some(path) => Ok(path),
+        None => Ok(dir(kind)?.join(file_name)),
+    }
+}
+
+/// Returns the `file_name` within the directory for files of the `kind`, if the file exists
+/// (without creating the directory, e.g. for an optional config file)
+pub fn existing(kind: Kind, file_name: &str) -> Option<PathBuf> {
+    let path = dir_in(kind, Platform::CURRENT, |name| std::env::var_os(name))?.join(file_name);
+    path.is_file().then_some(path)
+}
+
+fn dir_in(
+    kind: Kind,
+    platform: Platform,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+    // relative paths are invalid per the XDG specification, and ignored
+    let absolute = |name: &str| {
+        var(name)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+    };
+    let (xdg_var, xdg_default) = kind.xdg();
+    let base = match platform {
+        Platform::Xdg => absolute(xdg_var).or_else(|| Some(absolute("HOME")?.join(xdg_default))),
+        // XDG variables take precedence, for users keeping dotfiles in the unix layout
+        Platform::MacOs => absolute(xdg_var).or_else(|| {
+            let library = absolute("HOME")?.join("Library");
+            Some(match kind {
+                Kind::Config | Kind::State => library.join("Application Support"),
+                Kind::Cache => library.join("Caches"),
+            })
+        }),
+        Platform::Windows => match kind {
+            Kind::Config => absolute("APPDATA"),
+            Kind::Cache | Kind::State => absolute("LOCALAPPDATA"),
+        },
+    }?;
+    Some(base.join(APP_DIR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn base_directories() {
+        let dir = |kind, platform, vars: &[(&str, &str)]| {
+            dir_in(kind, platform, |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| OsString::from(value))
+            })
+        };
+        let home = [("HOME", "/home/user")];
+        let path = |path: &str| Some(PathBuf::from(path));
+
+        assert_eq!(
+            dir(Kind::Cache, Platform::Xdg, &home),
+            path("/home/user/.cache/beet_smart_cutoff")
+        );
+        assert_eq!(
+            dir(Kind::State, Platform::Xdg, &home),
+            path("/home/user/.local/state/beet_smart_cutoff")
+        );
+        let with_xdg = [("HOME", "/home/user"), ("XDG_CONFIG_HOME", "/etc/user")];
+        assert_eq!(
+            dir(Kind::Config, Platform::Xdg, &with_xdg),
+            path("/etc/user/beet_smart_cutoff")
+        );
+        let relative_xdg = [("HOME", "/home/user"), ("XDG_CONFIG_HOME", "config")];
+        assert_eq!(
+            dir(Kind::Config, Platform::Xdg, &relative_xdg),
+            path("/home/user/.config/beet_smart_cutoff")
+        );
+        assert_eq!(
+            dir(Kind::Cache, Platform::MacOs, &home),
+            path("/home/user/Library/Caches/beet_smart_cutoff")
+        );
+        assert_eq!(dir(Kind::Config, Platform::Xdg, &[]), None);
+        assert_eq!(dir(Kind::Config, Platform::Windows, &home), None);
+    }
+}
@@ -0,0 +1,314 @@
+//! Fakes for testing code built on this crate without running `beet` or reading a terminal
+//!
+//! - [`EntryBuilder`] (and [`entries`]) constructs [`DateEntry`] values
+//! - [`ScriptedRunner`] responds to [`BeetCommand`](crate::beet_command::BeetCommand)
+//!   invocations with canned output (e.g. from [`timeless_output`])
+//! - [`scripted_prompt`] answers a [`Prompt`] from a list of lines
+//! - `MemoryState` holds the JSON state in memory (with the `json` feature)
+
+use crate::{beet_command::ProcessRunner, prompt::Prompt, DateEntry, ItemId};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    process::{ExitStatus, Output},
+};
+
+/// Builder for a [`DateEntry`], with placeholder names derived from the id
+#[derive(Debug)]
+pub struct EntryBuilder {
+    entry: DateEntry,
+}
+impl EntryBuilder {
+    /// Starts an entry with the `id`, added at the `timestamp` (`YYYY-MM-DD HH:MM:SS`)
+    ///
+    /// # Panics
+    /// Panics if the `timestamp` is invalid.
+    pub fn new(id: ItemId, timestamp: &str) -> Self {
+        let timestamp = timestamp
+            .parse()
+            .unwrap_or_else(|err| panic!("invalid timestamp {timestamp:?}: {err:#}"));
+        Self {
+            entry: DateEntry {
+                id: Some(id),
+                timestamp,
+                album_artist: format!("Artist{id}"),
+                album: format!("Album{id}"),
+                artist: format!("Artist{id}"),
+                title: format!("Title{id}"),
+                filesize: None,
+                mb_albumid: None,
+                genre: None,
+                format: None,
+                compilation: false,
+                play_count: None,
+                extra: vec![],
+            },
+        }
+    }
+    /// Sets the album artist and album (shared by the tracks of an album)
+    pub fn album(mut self, album_artist: &str, album: &str) -> Self {
+        self.entry.album_artist = album_artist.to_owned();
+        self.entry.album = album.to_owned();
+        self
+    }
+    pub fn artist(mut self, artist: &str) -> Self {
+        self.entry.artist = artist.to_owned();
+        self
+    }
+    pub fn title(mut self, title: &str) -> Self {
+        self.entry.title = title.to_owned();
+        self
+    }
+    pub fn filesize(mut self, filesize: u64) -> Self {
+        self.entry.filesize = Some(filesize);
+        self
+    }
+    pub fn mb_albumid(mut self, mb_albumid: &str) -> Self {
+        self.entry.mb_albumid = Some(mb_albumid.to_owned());
+        self
+    }
+    pub fn genre(mut self, genre: &str) -> Self {
+        self.entry.genre = Some(genre.to_owned());
+        self
+    }
+    pub fn format(mut self, format: &str) -> Self {
+        self.entry.format = Some(format.to_owned());
+        self
+    }
+    pub fn play_count(mut self, play_count: u64) -> Self {
+        self.entry.play_count = Some(play_count);
+        self
+    }
+    /// Marks the entry as part of a compilation
+    pub fn compilation(mut self) -> Self {
+        self.entry.compilation = true;
+        self
+    }
+    pub fn build(self) -> DateEntry {
+        self.entry
+    }
+}
+
+/// Builds entries numbered from 1 for each of the `timestamps` (newest first, as fetched)
+///
+/// # Panics
+/// Panics if any timestamp is invalid.
+pub fn entries(timestamps: &[&str]) -> Vec<DateEntry> {
+    (1..)
+        .zip(timestamps)
+        .map(|(id, timestamp)| EntryBuilder::new(id, timestamp).build())
+        .collect()
+}
+
+/// Formats the `entries` as the output of the `beet list` query fetching the entries
+pub fn timeless_output(entries: &[DateEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            let DateEntry {
+                id,
+                timestamp,
+                album_artist,
+                album,
+                artist,
+                title,
+                filesize,
+                mb_albumid,
+                genre,
+                format,
+                compilation,
+                play_count,
+                extra,
+            } = entry;
+            let id = id.unwrap_or_default();
+            let filesize = filesize.map(|filesize| filesize.to_string());
+            let filesize = filesize.as_deref().unwrap_or_default();
+            let mb_albumid = mb_albumid.as_deref().unwrap_or_default();
+            let genre = genre.as_deref().unwrap_or_default();
+            let format = format.as_deref().unwrap_or_default();
+            let comp = if *compilation { "True" } else { "False" };
+            let play_count = play_count.map_or("$play_count".to_owned(), |plays| plays.to_string());
+            let extra: String = extra.iter().map(|value| format!("\t{value}")).collect();
+            format!(
+                "{id}\t{timestamp}\t{album_artist}\t{album}\t{artist}\t{title}\t{filesize}\t{mb_albumid}\t{genre}\t{format}\t{comp}\t{play_count}{extra}\n"
+            )
+        })
+        .collect()
+}
+
+/// Records each invocation, and responds with the queued outputs in order
+///
+/// Use a reference as the runner (see [`BeetCommand::with_runner`]), to inspect the
+/// [`Self::invocations`] afterward.
+///
+/// [`BeetCommand::with_runner`]: crate::beet_command::BeetCommand::with_runner
+#[derive(Debug, Default)]
+pub struct ScriptedRunner {
+    invocations: RefCell<Vec<Vec<String>>>,
+    responses: RefCell<VecDeque<Output>>,
+}
+impl ScriptedRunner {
+    /// Queues a successful response with the `stdout`
+    pub fn respond(self, stdout: &str) -> Self {
+        self.respond_with(ExitStatus::default(), stdout, "")
+    }
+    /// Queues a response with the `status`, `stdout`, and `stderr`
+    pub fn respond_with(self, status: ExitStatus, stdout: &str, stderr: &str) -> Self {
+        self.responses.borrow_mut().push_back(Output {
+            status,
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        });
+        self
+    }
+    /// Program and arguments of each invocation so far
+    pub fn invocations(&self) -> Vec<Vec<String>> {
+        self.invocations.borrow().clone()
+    }
+}
+impl ProcessRunner for &ScriptedRunner {
+    fn output(&self, command: &mut std::process::Command) -> std::io::Result<Output> {
+        let invocation = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|os_str| os_str.to_string_lossy().into_owned())
+            .collect();
+        self.invocations.borrow_mut().push(invocation);
+        self.responses.borrow_mut().pop_front().ok_or_else(|| {
+            std::io::Error::other("ScriptedRunner has no response queued for the invocation")
+        })
+    }
+}
+
+/// Prompt answering with each of the `answers` in order, then reaching the end of input
+pub fn scripted_prompt(answers: &[&str]) -> Prompt {
+    let input: String = answers.iter().map(|answer| format!("{answer}\n")).collect();
+    Prompt::with_input(std::io::Cursor::new(input))
+}
+
+/// State of the JSON output file, held in memory
+#[cfg(feature = "json")]
+#[derive(Clone, Debug, Default)]
+pub struct MemoryState {
+    pub map: serde_json::Map<String, serde_json::Value>,
+}
+#[cfg(feature = "json")]
+impl MemoryState {
+    /// Stores the `cutoff` at the `key` as written by a run (see [`crate::json::insert_value`])
+    pub fn insert_cutoff(
+        &mut self,
+        key: &str,
+        cutoff: crate::Date,
+        on_existing: crate::json::OnExisting,
+    ) -> anyhow::Result<()> {
+        crate::json::insert_value(
+            &mut self.map,
+            key.to_owned(),
+            cutoff.to_string(),
+            on_existing,
+        )
+    }
+    /// Returns the latest cutoff stored at the `key`, if any
+    pub fn cutoff(&self, key: &str) -> Option<crate::Date> {
+        crate::json::latest_value(&self.map, key)?
+            .as_str()?
+            .parse()
+            .ok()
+    }
+    /// Writes the state to the `path`, e.g. for running the binary against it
+    pub fn write(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        crate::json::write_json_file(path, self.map.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{beet_command::BeetCommand, prompt::Key};
+
+    #[test]
+    fn scripted_beet_query() {
+        let fetched = vec![
+            EntryBuilder::new(2, "2024-03-02 10:00:00")
+                .album("Band", "Record")
+                .filesize(1000)
+                .build(),
+            EntryBuilder::new(1, "2024-03-01 09:00:00")
+                .mb_albumid("0000-mbid")
+                .build(),
+        ];
+        let runner = ScriptedRunner::default().respond(&timeless_output(&fetched));
+        let beets =
+            BeetCommand::new(std::path::PathBuf::from("beet"), "a", 10).with_runner(&runner);
+
+        let entries = beets.query_timeless().expect("query succeeds");
+
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.id,
+                    entry.album_key(),
+                    entry.filesize,
+                    entry.mb_albumid.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Some(2), ("Band", "Record"), Some(1000), None),
+                (
+                    Some(1),
+                    ("Artist1", "Album1"),
+                    None,
+                    Some("0000-mbid".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(runner.invocations().len(), 1);
+        assert!(beets.count_entries_after(entries[0].date()).is_err());
+    }
+
+    #[test]
+    fn scripted_answers() {
+        let mut prompt = scripted_prompt(&["2", " q "]);
+        assert_eq!(prompt.read_line("first?").expect("answer"), "2");
+        assert_eq!(prompt.read_line("second?").expect("answer"), "q");
+        assert!(prompt.read_line("third?").is_err());
+
+        let mut prompt = scripted_prompt(&[">", "h", "b", "", "q"]);
+        let keys: Vec<_> = (0..5)
+            .map(|_| prompt.read_key("status").expect("answer"))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                Key::Right,
+                Key::Left,
+                Key::Bookmark,
+                Key::Enter,
+                Key::Cancel
+            ]
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn memory_state_history() {
+        use crate::json::OnExisting;
+
+        let mut state = MemoryState::default();
+        let [first, second] = ["2024-03-01", "2024-04-01"].map(|date| date.parse().expect("date"));
+        state
+            .insert_cutoff("recent", first, OnExisting::AppendHistory)
+            .expect("insert");
+        state
+            .insert_cutoff("recent", second, OnExisting::AppendHistory)
+            .expect("append");
+        assert_eq!(state.cutoff("recent"), Some(second));
+        assert!(state
+            .insert_cutoff("recent", first, OnExisting::Error)
+            .is_err());
+        assert_eq!(entries(&["2024-03-01 00:00:00"])[0].id, Some(1));
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::Context as _;
+use std::{
+    io::{BufRead, Read as _, Write},
+    net::{TcpStream, ToSocketAddrs as _},
+    time::Duration,
+};
+
+/// Largest request body read (and discarded) by [`read_request`]
+const MAX_BODY_LENGTH: u64 = 64 * 1024;
+
+/// Method and path of a minimal HTTP/1.1 request
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+}
+
+/// Reads the request line and headers, discarding any body
+pub fn read_request(reader: &mut impl BufRead) -> anyhow::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        anyhow::bail!("invalid request line {line:?}")
+    };
+    let request = Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+    };
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid content-length {value:?}"))?;
+            }
+        }
+    }
+    std::io::copy(
+        &mut reader.take(content_length.min(MAX_BODY_LENGTH)),
+        &mut std::io::sink(),
+    )?;
+    Ok(request)
+}
+
+/// Writes a complete response with the JSON `body`, closing the connection
+pub fn write_response(
+    writer: &mut impl Write,
+    status: u16,
+    reason: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    )?;
+    writer.flush()
+}
+
+/// Plain `http://` URL, e.g. `http://homeassistant.local:8123/api/webhook/playlist`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    /// Path and query, starting with `/`
+    pub path: String,
+}
+impl std::str::FromStr for Url {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let Some(rest) = s.strip_prefix("http://") else {
+            anyhow::bail!("expected a URL starting with http:// (https is not supported): {s:?}")
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .with_context(|| format!("invalid port {port:?}"))?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            anyhow::bail!("missing host in URL {s:?}");
+        }
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { host, port, path } = self;
+        write!(f, "http://{host}:{port}{path}")
+    }
+}
+
+/// Sends a `POST` request with the JSON `body` (and the `bearer_token` if any), failing for a
+/// response status other than 2xx
+pub fn post_json(
+    url: &Url,
+    bearer_token: Option<&str>,
+    body: &str,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let Url { host, port, path } = url;
+    let address = (host.as_str(), *port)
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("no address for host {host:?}"))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let authorization = bearer_token
+        .map(|token| format!("Authorization: Bearer {token}\r\n"))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\n\
+         Host: {host}:{port}\r\n\
+         {authorization}\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    )?;
+    stream.flush()?;
+
+    let mut status_line = String::new();
+    std::io::BufReader::new(stream).read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .with_context(|| format!("invalid status line {status_line:?}"))?;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("response {}", status_line.trim_end());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_and_response() {
+        let mut input: &[u8] =
+            b"POST /reevaluate HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\n{}\r\n";
+        let request = read_request(&mut input).expect("valid request");
+        assert_eq!(
+            request,
+            Request {
+                method: "POST".to_owned(),
+                path: "/reevaluate".to_owned(),
+            }
+        );
+        assert!(input.is_empty(), "body discarded");
+        assert!(read_request(&mut &b"garbage\r\n\r\n"[..]).is_err());
+
+        let mut output = vec![];
+        write_response(&mut output, 200, "OK", "{}").expect("write");
+        assert_eq!(
+            String::from_utf8(output).expect("utf8"),
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}"
+        );
+    }
+
+    #[test]
+    fn post_to_url() {
+        assert_eq!(
+            "http://example.com"
+                .parse::<Url>()
+                .expect("url")
+                .to_string(),
+            "http://example.com:80/"
+        );
+        assert!("https://example.com/hook".parse::<Url>().is_err());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("address").port();
+        let server = std::thread::spawn(move || {
+            ["200 OK", "500 Internal Server Error"].map(|status| {
+                let (stream, _) = listener.accept().expect("accept");
+                let mut reader = std::io::BufReader::new(stream);
+                let mut head = String::new();
+                while reader.read_line(&mut head).expect("read") > 2 {}
+                write!(reader.get_mut(), "HTTP/1.1 {status}\r\n\r\n").expect("respond");
+                head
+            })
+        });
+        let url: Url = format!("http://127.0.0.1:{port}/hook")
+            .parse()
+            .expect("url");
+        let timeout = Duration::from_secs(5);
+        let accepted = post_json(&url, Some("secret"), "{}", timeout);
+        let rejected = post_json(&url, None, "{}", timeout);
+        let [first, second] = server.join().expect("server");
+
+        assert!(accepted.is_ok());
+        assert!(first.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(first.contains("Authorization: Bearer secret\r\n"));
+        assert!(!second.contains("Authorization"));
+        let err = rejected.expect_err("status 500");
+        assert_eq!(
+            err.to_string(),
+            "response HTTP/1.1 500 Internal Server Error"
+        );
+    }
+}
@@ -1,50 +1,745 @@
-use crate::JsonMap;
+use crate::{Date, JsonMap};
+use anyhow::Context as _;
 use std::{
     fs::File,
     io::{BufReader, BufWriter},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
 };
 
+/// Location of the object holding the output keys
+#[derive(Clone, Debug)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    /// Element of a top-level array holding the keys, instead of the top-level object
+    pub array_match: Option<ArrayMatch>,
+    /// Accept comments and trailing commas (e.g. in a hand-edited file), see [`strip_comments`]
+    pub comments: bool,
+}
+impl From<PathBuf> for OutputFile {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            array_match: None,
+            comments: false,
+        }
+    }
+}
+
+/// Array element (object) with a field equal to a string value, e.g. `name=recent`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArrayMatch {
+    pub field: String,
+    pub value: String,
+}
+impl ArrayMatch {
+    /// Returns the index of the matching element, failing if several elements match
+    fn position(&self, elements: &[serde_json::Value]) -> anyhow::Result<Option<usize>> {
+        let Self { field, value } = self;
+        let mut matches = elements
+            .iter()
+            .enumerate()
+            .filter(|(_, element)| element.get(field).and_then(|v| v.as_str()) == Some(value))
+            .map(|(index, _)| index);
+        let first = matches.next();
+        if matches.next().is_some() {
+            anyhow::bail!("multiple array elements match {self}");
+        }
+        Ok(first)
+    }
+}
+impl std::str::FromStr for ArrayMatch {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let Some((field, value)) = s.split_once('=') else {
+            anyhow::bail!("invalid array match {s:?}, expected FIELD=VALUE")
+        };
+        Ok(Self {
+            field: field.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+}
+impl std::fmt::Display for ArrayMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { field, value } = self;
+        write!(f, "{field}={value}")
+    }
+}
+
 pub struct JsonFile {
+    /// Object holding the output keys, if present
     pub map: Option<JsonMap>,
     pub path: PathBuf,
+    /// Elements of an array-shaped file, with the location of the map
+    array: Option<ArrayElements>,
+    /// Whether comments were accepted when reading, see [`OutputFile::comments`]
+    comments: bool,
+}
+struct ArrayElements {
+    elements: Vec<serde_json::Value>,
+    /// Index of the matched element, if any
+    index: Option<usize>,
+    array_match: ArrayMatch,
 }
-pub fn read_json_file(path: PathBuf) -> anyhow::Result<JsonFile> {
+impl JsonFile {
+    /// Reads the file again, e.g. for the changes of a concurrent run (see [`lock_writes`])
+    pub fn reread(&self) -> anyhow::Result<Self> {
+        read_json_file(OutputFile {
+            path: self.path.clone(),
+            array_match: self.array.as_ref().map(|array| array.array_match.clone()),
+            comments: self.comments,
+        })
+    }
+    /// Writes the `map` in place of the object read (see [`write_json_file`]), appending a new
+    /// element for an array without a matching element
+    pub fn write(self, mut map: JsonMap) -> anyhow::Result<()> {
+        let Some(array) = self.array else {
+            return write_json_file(&self.path, map);
+        };
+        let ArrayElements {
+            mut elements,
+            index,
+            array_match,
+        } = array;
+        match index {
+            Some(index) => elements[index] = serde_json::Value::Object(map),
+            None => {
+                let ArrayMatch { field, value } = array_match;
+                map.insert(field, serde_json::Value::String(value));
+                elements.push(serde_json::Value::Object(map));
+            }
+        }
+        let element_count = elements.len();
+        write_json_value(&self.path, &serde_json::Value::Array(elements))?;
+        let filename = self.path.display();
+        println!("Saved {element_count} elements to {filename}");
+        Ok(())
+    }
+}
+pub fn read_json_file(output_file: impl Into<OutputFile>) -> anyhow::Result<JsonFile> {
+    let OutputFile {
+        path,
+        array_match,
+        comments,
+    } = output_file.into();
     let file = match File::open(&path) {
         Ok(file) => file,
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            return Ok(JsonFile { map: None, path })
+            let array = array_match.map(|array_match| ArrayElements {
+                elements: vec![],
+                index: None,
+                array_match,
+            });
+            return Ok(JsonFile {
+                map: None,
+                path,
+                array,
+                comments,
+            });
         }
         Err(e) => Err(e)?,
     };
-    let file = BufReader::new(file);
-    let value: serde_json::Value = serde_json::from_reader(file)?;
-
-    let serde_json::Value::Object(map) = value else {
-        anyhow::bail!("unexpected JSON value: {value:?}")
+    let value: serde_json::Value = if comments {
+        let text = std::io::read_to_string(file)?;
+        let stripped = strip_comments(&text);
+        if stripped.len() != text.len() {
+            println!(
+                "[note: comments and trailing commas in {} are not kept when writing]",
+                path.display()
+            );
+        }
+        serde_json::from_str(&stripped)?
+    } else {
+        serde_json::from_reader(BufReader::new(file))?
     };
 
-    let entry_count = map.len();
     let filename = path.display();
-    println!("Loaded {entry_count} entries from {filename}");
-
-    Ok(JsonFile {
-        map: Some(map),
-        path,
-    })
+    match (value, array_match) {
+        (serde_json::Value::Object(map), None) => {
+            let entry_count = map.len();
+            println!("Loaded {entry_count} entries from {filename}");
+            Ok(JsonFile {
+                map: Some(map),
+                path,
+                array: None,
+                comments,
+            })
+        }
+        (serde_json::Value::Array(elements), Some(array_match)) => {
+            let index = array_match.position(&elements)?;
+            let map = index
+                .map(|index| match &elements[index] {
+                    serde_json::Value::Object(map) => Ok(map.clone()),
+                    element => {
+                        anyhow::bail!("array element {array_match} is not an object: {element}")
+                    }
+                })
+                .transpose()?;
+            let element_count = elements.len();
+            println!("Loaded {element_count} elements from {filename}");
+            Ok(JsonFile {
+                map,
+                path,
+                array: Some(ArrayElements {
+                    elements,
+                    index,
+                    array_match,
+                }),
+                comments,
+            })
+        }
+        (serde_json::Value::Array(_), None) => {
+            anyhow::bail!(
+                "unexpected JSON array, specify the element to update with --output-array-match"
+            )
+        }
+        (value, _) => anyhow::bail!("unexpected JSON value: {value:?}"),
+    }
 }
-pub fn write_json_file(path: impl AsRef<Path>, value: JsonMap) -> anyhow::Result<()> {
+/// Removes `//` line comments, `/* */` block comments, and trailing commas before a closing
+/// bracket or brace from the JSON `text` (keeping the line breaks, for error positions)
+pub fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    // position of a comma, removed if only whitespace (or comments) precede the closing bracket
+    let mut pending_comma = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                pending_comma = None;
+                stripped.push(c);
+                while let Some(c) = chars.next() {
+                    stripped.push(c);
+                    match c {
+                        '\\' => stripped.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        stripped.push(c);
+                    }
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            ',' => {
+                pending_comma = Some(stripped.len());
+                stripped.push(c);
+            }
+            '}' | ']' => {
+                if let Some(position) = pending_comma.take() {
+                    stripped.remove(position);
+                }
+                stripped.push(c);
+            }
+            c if c.is_whitespace() => stripped.push(c),
+            c => {
+                pending_comma = None;
+                stripped.push(c);
+            }
+        }
+    }
+    stripped
+}
+/// Locks the sibling file `PATH.lock` exclusively, waiting for other processes holding it (e.g.
+/// concurrent profile runs), until the returned file is dropped
+///
+/// Serializes the read-modify-write of the file at `path`, which is replaced when written (so
+/// cannot be locked itself).
+pub fn lock_writes(path: &Path) -> anyhow::Result<File> {
+    let mut lock_path = path.to_owned().into_os_string();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
     let file = File::options()
         .write(true)
         .create(true)
-        .truncate(true)
-        .open(&path)?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer_pretty(writer, &value)?;
-
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("opening lock file {lock_path:?}"))?;
+    file.lock()
+        .with_context(|| format!("locking {lock_path:?}"))?;
+    Ok(file)
+}
+/// Writes the file atomically (via a temporary file renamed into place), to never leave a
+/// partially written file
+pub fn write_json_file(path: impl AsRef<Path>, value: JsonMap) -> anyhow::Result<()> {
     let entry_count = value.len();
+    write_json_value(path.as_ref(), &serde_json::Value::Object(value))?;
     let filename = path.as_ref().display();
     println!("Saved {entry_count} entries to {filename}");
+    Ok(())
+}
+fn write_json_value(path: &Path, value: &serde_json::Value) -> anyhow::Result<()> {
+    let mut temp_path = path.to_owned().into_os_string();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
 
+    let file = File::options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp_path)?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)?;
     Ok(())
 }
+
+/// Handling of an existing value at the output key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnExisting {
+    /// Overwrite any existing value
+    Replace,
+    /// Overwrite an existing string (e.g. from a previous run), but fail for other values
+    #[default]
+    Error,
+    /// Keep the previous values in an array, appending the new value
+    AppendHistory,
+}
+impl std::str::FromStr for OnExisting {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "replace" => Ok(Self::Replace),
+            "error" => Ok(Self::Error),
+            "append-history" => Ok(Self::AppendHistory),
+            _ => anyhow::bail!(
+                "unknown strategy {s:?}, expected \"replace\", \"error\", or \"append-history\""
+            ),
+        }
+    }
+}
+
+/// Fails if [`insert_value`] would fail for the existing value at `key`
+pub fn check_existing(map: &JsonMap, key: &str, on_existing: OnExisting) -> anyhow::Result<()> {
+    match (on_existing, map.get(key)) {
+        (OnExisting::Error, Some(existing)) if !existing.is_string() => {
+            anyhow::bail!("existing value for key {key:?} is not a string: {existing}")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Inserts the `value` at `key`, merging with any existing value according to `on_existing`
+pub fn insert_value(
+    map: &mut JsonMap,
+    key: String,
+    value: String,
+    on_existing: OnExisting,
+) -> anyhow::Result<()> {
+    use serde_json::Value;
+    check_existing(map, &key, on_existing)?;
+    // NOTE: modify in place, to preserve the order of keys
+    let slot = map.entry(key).or_insert(Value::Null);
+    *slot = match (on_existing, slot.take()) {
+        (OnExisting::Replace | OnExisting::Error, _) => Value::String(value),
+        (OnExisting::AppendHistory, Value::Null) => Value::Array(vec![Value::String(value)]),
+        (OnExisting::AppendHistory, Value::Array(mut history)) => {
+            history.push(Value::String(value));
+            Value::Array(history)
+        }
+        (OnExisting::AppendHistory, existing) => Value::Array(vec![existing, Value::String(value)]),
+    };
+    Ok(())
+}
+
+/// Returns the value at `key`, or the latest value of its history (see
+/// [`OnExisting::AppendHistory`])
+pub fn latest_value<'a>(map: &'a JsonMap, key: &str) -> Option<&'a serde_json::Value> {
+    match map.get(key)? {
+        serde_json::Value::Array(history) => history.last(),
+        value => Some(value),
+    }
+}
+
+/// Output keys whose cutoffs nest, innermost first, e.g. `fresh,recent,archive` for playlist
+/// tiers where fresh ⊂ recent ⊂ archive (so each cutoff is no older than the next)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nesting {
+    pub keys: Vec<String>,
+}
+impl Nesting {
+    /// Returns the stored cutoffs of the other keys which do not nest with the `cutoff` written
+    /// at `key` (none if the `key` is not part of the nesting)
+    ///
+    /// Stored values that are not dates are ignored.
+    pub fn violations(&self, map: &JsonMap, key: &str, cutoff: Date) -> Vec<NestingViolation> {
+        let Some(position) = self.keys.iter().position(|nested| nested == key) else {
+            return vec![];
+        };
+        let stored = |key: &str| latest_value(map, key)?.as_str()?.parse::<Date>().ok();
+        let mut violations = vec![];
+        // inner keys require a cutoff no older than the next outer key
+        let mut bound = cutoff;
+        for inner in self.keys[..position].iter().rev() {
+            match stored(inner) {
+                Some(inner_cutoff) if inner_cutoff < bound => {
+                    violations.push(NestingViolation {
+                        key: inner.clone(),
+                        stored: inner_cutoff,
+                        fixed: bound,
+                    });
+                }
+                Some(inner_cutoff) => bound = inner_cutoff,
+                None => {}
+            }
+        }
+        // outer keys require a cutoff no newer than the next inner key
+        let mut bound = cutoff;
+        for outer in &self.keys[position + 1..] {
+            match stored(outer) {
+                Some(outer_cutoff) if outer_cutoff > bound => {
+                    violations.push(NestingViolation {
+                        key: outer.clone(),
+                        stored: outer_cutoff,
+                        fixed: bound,
+                    });
+                }
+                Some(outer_cutoff) => bound = outer_cutoff,
+                None => {}
+            }
+        }
+        violations
+    }
+}
+impl std::str::FromStr for Nesting {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let keys: Vec<String> = s.split(',').map(|key| key.trim().to_owned()).collect();
+        if keys.len() < 2 || keys.iter().any(String::is_empty) {
+            anyhow::bail!("expected at least two comma-separated keys, innermost first: {s:?}");
+        }
+        if let Some(duplicate) = keys
+            .iter()
+            .enumerate()
+            .find_map(|(index, key)| keys[..index].contains(key).then_some(key))
+        {
+            anyhow::bail!("duplicate key {duplicate:?} in nesting {s:?}");
+        }
+        Ok(Self { keys })
+    }
+}
+/// Stored cutoff of a key out of order with a newly written cutoff (see [`Nesting::violations`])
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NestingViolation {
+    pub key: String,
+    pub stored: Date,
+    /// Nearest cutoff that nests
+    pub fixed: Date,
+}
+impl std::fmt::Display for NestingViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { key, stored, fixed } = self;
+        let relation = if fixed > stored { "after" } else { "before" };
+        write!(
+            f,
+            "cutoff {stored} stored at key {key:?} breaks the nesting, expected on or {relation} {fixed}"
+        )
+    }
+}
+
+/// Handling of stored cutoffs which would not nest with the written cutoff
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnNestingViolation {
+    /// Refuse to write the cutoff
+    #[default]
+    Error,
+    /// Write the cutoff, printing a warning
+    Warn,
+    /// Adjust the stored cutoffs of the other keys to nest
+    Fix,
+}
+impl std::str::FromStr for OnNestingViolation {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "fix" => Ok(Self::Fix),
+            _ => anyhow::bail!("unknown action {s:?}, expected \"error\", \"warn\", or \"fix\""),
+        }
+    }
+}
+
+/// Checks the stored cutoffs against the `cutoff` written at `key`, per the `nesting` (if any)
+///
+/// Returns the adjusted cutoffs to write when fixing violations.
+pub fn check_nesting(
+    nesting: Option<&(Nesting, OnNestingViolation)>,
+    map: &JsonMap,
+    key: &str,
+    cutoff: Date,
+) -> anyhow::Result<Vec<(String, Date)>> {
+    let Some((nesting, on_violation)) = nesting else {
+        return Ok(vec![]);
+    };
+    let violations = nesting.violations(map, key, cutoff);
+    match on_violation {
+        _ if violations.is_empty() => Ok(vec![]),
+        OnNestingViolation::Error => {
+            for violation in &violations {
+                println!("[{violation}]");
+            }
+            anyhow::bail!(
+                "cutoff {cutoff} for key {key:?} breaks the nesting {:?} (see --on-nesting-violation)",
+                nesting.keys.join(",")
+            )
+        }
+        OnNestingViolation::Warn => {
+            for violation in &violations {
+                println!("[WARNING: {violation}]");
+            }
+            Ok(vec![])
+        }
+        OnNestingViolation::Fix => Ok(violations
+            .into_iter()
+            .map(|violation| {
+                println!("[{violation}, adjusting to {}]", violation.fixed);
+                let NestingViolation { key, fixed, .. } = violation;
+                (key, fixed)
+            })
+            .collect()),
+    }
+}
+
+/// Key holding the [`LastRun`] metadata for each output key, alongside the cutoffs
+pub const METADATA_KEY: &str = "_beet_smart_cutoff";
+
+/// Interactive selection from the previous run, preloaded for the next run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LastRun {
+    /// Candidate strategy, e.g. `targets:30,50,70`
+    pub strategy: String,
+    /// Number of the chosen candidate
+    pub choice: NonZeroUsize,
+}
+
+/// Returns the [`LastRun`] stored for the output `key`, if any
+pub fn read_last_run(map: &JsonMap, key: &str) -> Option<LastRun> {
+    let last_run = map.get(METADATA_KEY)?.get(key)?;
+    let strategy = last_run.get("strategy")?.as_str()?.to_owned();
+    let choice = last_run.get("choice")?.as_u64()?;
+    let choice = NonZeroUsize::new(usize::try_from(choice).ok()?)?;
+    Some(LastRun { strategy, choice })
+}
+
+/// Stores the [`LastRun`] for the output `key`, replacing any previous metadata for the key
+pub fn insert_last_run(map: &mut JsonMap, key: String, last_run: LastRun) -> anyhow::Result<()> {
+    use serde_json::Value;
+    let LastRun { strategy, choice } = last_run;
+    let metadata = map
+        .entry(METADATA_KEY)
+        .or_insert_with(|| Value::Object(JsonMap::new()));
+    let Value::Object(metadata) = metadata else {
+        anyhow::bail!("existing value for key {METADATA_KEY:?} is not an object: {metadata}")
+    };
+    metadata.insert(
+        key,
+        serde_json::json!({ "strategy": strategy, "choice": choice.get() }),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn insert(existing: serde_json::Value, on_existing: OnExisting) -> anyhow::Result<String> {
+        let mut map = JsonMap::new();
+        if !existing.is_null() {
+            map.insert("key".to_owned(), existing);
+        }
+        insert_value(&mut map, "key".to_owned(), "new".to_owned(), on_existing)?;
+        Ok(map["key"].to_string())
+    }
+
+    #[test]
+    fn insert_on_existing() {
+        let ok = |existing, on_existing| insert(existing, on_existing).expect("insert succeeds");
+
+        assert_eq!(ok(json!(null), OnExisting::Error), r#""new""#);
+        assert_eq!(ok(json!("old"), OnExisting::Error), r#""new""#);
+        assert!(insert(json!(["old"]), OnExisting::Error).is_err());
+        assert_eq!(ok(json!({"a": 1}), OnExisting::Replace), r#""new""#);
+        assert_eq!(ok(json!(null), OnExisting::AppendHistory), r#"["new"]"#);
+        assert_eq!(
+            ok(json!("old"), OnExisting::AppendHistory),
+            r#"["old","new"]"#
+        );
+        assert_eq!(
+            ok(json!(["older", "old"]), OnExisting::AppendHistory),
+            r#"["older","old","new"]"#
+        );
+    }
+
+    #[test]
+    fn last_run_roundtrip() {
+        let last_run = LastRun {
+            strategy: "targets:30,50,70".to_owned(),
+            choice: NonZeroUsize::new(2).expect("nonzero"),
+        };
+        let mut map = JsonMap::new();
+        map.insert("key".to_owned(), json!("2024-01-01"));
+        assert_eq!(read_last_run(&map, "key"), None);
+
+        insert_last_run(&mut map, "key".to_owned(), last_run.clone()).expect("insert succeeds");
+        assert_eq!(read_last_run(&map, "key"), Some(last_run.clone()));
+        assert_eq!(read_last_run(&map, "other"), None);
+        assert_eq!(map["key"], json!("2024-01-01"));
+
+        map.insert(METADATA_KEY.to_owned(), json!("unexpected"));
+        assert!(insert_last_run(&mut map, "key".to_owned(), last_run).is_err());
+    }
+
+    #[test]
+    fn array_element_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("json_array_test_{}.json", std::process::id()));
+        let output_file = |value: &str| OutputFile {
+            path: path.clone(),
+            array_match: Some(value.parse().expect("valid match")),
+            comments: false,
+        };
+        let elements = json!([
+            {"name": "recent", "cutoff": "2024-01-01", "size": 50},
+            {"name": "older", "cutoff": "2023-01-01"},
+        ]);
+        std::fs::write(&path, elements.to_string()).expect("write test file");
+
+        let mut json_file = read_json_file(output_file("name=recent")).expect("read");
+        let mut map = json_file.map.take().expect("matched element");
+        assert_eq!(map["size"], json!(50));
+        map.insert("cutoff".to_owned(), json!("2024-03-01"));
+        json_file.write(map).expect("write");
+
+        let json_file = read_json_file(output_file("name=new")).expect("read");
+        assert!(json_file.map.is_none());
+        json_file.write(JsonMap::new()).expect("write");
+
+        let contents = std::fs::read_to_string(&path).expect("read test file");
+        std::fs::remove_file(&path).expect("remove test file");
+        let written: serde_json::Value = serde_json::from_str(&contents).expect("valid JSON");
+        assert_eq!(
+            written,
+            json!([
+                {"name": "recent", "cutoff": "2024-03-01", "size": 50},
+                {"name": "older", "cutoff": "2023-01-01"},
+                {"name": "new"},
+            ])
+        );
+        assert!("name".parse::<ArrayMatch>().is_err());
+    }
+
+    #[test]
+    fn locked_reread() {
+        let path = std::env::temp_dir().join(format!("json_lock_test_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"a": "2024-01-01"}"#).expect("write test file");
+        let json_file = read_json_file(path.clone()).expect("read");
+
+        // concurrent run writing another key
+        write_json_file(
+            &path,
+            json!({"a": "2024-01-01", "b": "2024-02-01"})
+                .as_object()
+                .cloned()
+                .expect("object"),
+        )
+        .expect("write");
+
+        let lock = lock_writes(&path).expect("lock");
+        let json_file = json_file.reread().expect("reread");
+        let mut map = json_file.map.clone().expect("present");
+        assert_eq!(map["b"], json!("2024-02-01"));
+        map.insert("a".to_owned(), json!("2024-03-01"));
+        json_file.write(map).expect("write");
+        drop(lock);
+
+        let contents = std::fs::read_to_string(&path).expect("read test file");
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        std::fs::remove_file(&path).expect("remove test file");
+        std::fs::remove_file(lock_path).expect("remove lock file");
+        let written: serde_json::Value = serde_json::from_str(&contents).expect("valid JSON");
+        assert_eq!(written, json!({"a": "2024-03-01", "b": "2024-02-01"}));
+    }
+
+    #[test]
+    fn nesting_violations() {
+        let nesting: Nesting = "fresh, recent,archive".parse().expect("valid nesting");
+        let map = json!({
+            "fresh": "2024-03-01",
+            "recent": ["2023-12-01", "2024-02-01"],
+            "archive": "2023-06-01",
+        });
+        let map = map.as_object().expect("object");
+        let date = |s: &str| s.parse::<Date>().expect("valid date");
+        let violations = |key, cutoff| nesting.violations(map, key, date(cutoff));
+
+        assert_eq!(violations("recent", "2024-01-01"), []);
+        assert_eq!(
+            violations("recent", "2024-04-01"),
+            [NestingViolation {
+                key: "fresh".to_owned(),
+                stored: date("2024-03-01"),
+                fixed: date("2024-04-01"),
+            }]
+        );
+        let violations = violations("fresh", "2023-01-01");
+        let keys: Vec<_> = violations
+            .iter()
+            .map(|violation| (violation.key.as_str(), violation.fixed))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("recent", date("2023-01-01")),
+                ("archive", date("2023-01-01"))
+            ]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            r#"cutoff 2024-02-01 stored at key "recent" breaks the nesting, expected on or before 2023-01-01"#
+        );
+        assert_eq!(nesting.violations(map, "other", date("2000-01-01")), []);
+
+        for s in ["fresh", "a,,b", "a,b,a"] {
+            assert!(s.parse::<Nesting>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn commented_json() {
+        let text = r#"{
+  // cutoff of the "recent" playlist
+  "recent": "2024-03-01", /* updated by
+    beet_smart_cutoff */
+  "url": "http://example.com/*not a comment*/",
+  "tiers": ["a", "b",],
+}"#;
+        let stripped = strip_comments(text);
+        assert_eq!(stripped.lines().count(), text.lines().count());
+        let value: serde_json::Value = serde_json::from_str(&stripped).expect("valid JSON");
+        assert_eq!(
+            value,
+            json!({
+                "recent": "2024-03-01",
+                "url": "http://example.com/*not a comment*/",
+                "tiers": ["a", "b"],
+            })
+        );
+        assert_eq!(strip_comments(r#"["a\"//", 1]"#), r#"["a\"//", 1]"#);
+    }
+}
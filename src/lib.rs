@@ -1,70 +1,941 @@
+use anyhow::Context as _;
+
+#[cfg(feature = "json")]
 type JsonMap = serde_json::Map<String, serde_json::Value>;
+#[cfg(feature = "json")]
 pub mod json;
 
+pub mod date;
+pub use date::{Date, Timestamp};
+
+pub mod shuffle;
+
+pub mod parallel;
+
+pub mod constraint;
+
+pub mod simulate;
+
+pub mod strategy;
+
+pub mod timing;
+
+pub mod watch;
+
+pub mod count_cache;
+
+pub mod dirs;
+
+#[cfg(feature = "config")]
+pub mod config;
+
+pub mod progress;
+
+pub mod sink;
+
+pub mod artist;
+
+#[cfg(unix)]
+pub mod notify;
+
+#[cfg(any(feature = "serve", feature = "webhook"))]
+pub mod http;
+
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+#[cfg(feature = "db")]
+pub mod db;
+
+#[cfg(feature = "json")]
+pub mod report;
+
+#[cfg(feature = "json")]
+pub mod session;
+
+#[cfg(feature = "json")]
+pub mod audit;
+
+#[cfg(feature = "json")]
+pub mod transcript;
+
+#[cfg(feature = "patch")]
+pub mod patch;
+
 pub mod prompt;
 
+pub mod selection;
+
+pub mod template;
+
+#[cfg(feature = "harness")]
+pub mod harness;
+
+pub mod render;
+
 pub mod beet_command;
 
+/// Identifier of a beets library item (`$id`)
+pub type ItemId = u64;
+
 #[derive(Debug)]
 pub struct DateEntry {
-    pub date: String,
-    pub entry: String,
+    /// Library item id, when known (absent for entries from other sources)
+    pub id: Option<ItemId>,
+    pub timestamp: Timestamp,
+    pub album_artist: String,
+    pub album: String,
+    pub artist: String,
+    pub title: String,
+    /// File size in bytes, when known
+    pub filesize: Option<u64>,
+    /// MusicBrainz release id of the album, when known
+    pub mb_albumid: Option<String>,
+    /// Genre(s) of the item, when known
+    pub genre: Option<String>,
+    /// Audio format (e.g. `FLAC`), when known
+    pub format: Option<String>,
+    /// Whether the item belongs to a compilation (`$comp`), e.g. a various artists album
+    pub compilation: bool,
+    /// Number of times the item was played (`$play_count`, e.g. from the mpdstats plugin), when
+    /// known
+    pub play_count: Option<u64>,
+    /// Values of the additional fields following [`Self::FIELDS`] (e.g. `play_count` from a
+    /// plugin), see [`Self::extra_field`]
+    pub extra: Vec<String>,
+}
+impl DateEntry {
+    /// Field names (in order) of the tab-separated lines parsed by [`Self::parse_fields`],
+    /// excluding the leading `$id` and date field
+    pub const FIELDS: &'static [&'static str] = &[
+        "albumartist",
+        "album",
+        "artist",
+        "title",
+        "filesize",
+        "mb_albumid",
+        "genre",
+        "format",
+        "comp",
+        "play_count",
+    ];
+
+    /// Parses a tab-separated line of `$id`, the date field, then [`Self::FIELDS`]
+    ///
+    /// The trailing `genre`, `format`, `comp`, and `play_count` fields may be omitted, and any
+    /// further fields are kept in [`Self::extra`]. A `play_count` formatted as the literal
+    /// `$play_count` (without a plugin setting it) is unknown.
+    pub fn parse_fields(s: &str) -> anyhow::Result<Self> {
+        let mut fields = s.split('\t');
+        let mut next_field = |name: &str| {
+            fields
+                .next()
+                .with_context(|| format!("missing field {name}: {s:?}"))
+        };
+        let id = next_field("id")?;
+        let id = id
+            .parse()
+            .with_context(|| format!("invalid item id {id:?}"))?;
+        let timestamp = match next_field("date")? {
+            "" => Timestamp::UNIX_EPOCH,
+            timestamp => timestamp.parse()?,
+        };
+        Ok(Self {
+            id: Some(id),
+            timestamp,
+            album_artist: next_field("albumartist")?.to_owned(),
+            album: next_field("album")?.to_owned(),
+            artist: next_field("artist")?.to_owned(),
+            title: next_field("title")?.to_owned(),
+            filesize: match next_field("filesize")? {
+                "" => None,
+                filesize => Some(
+                    filesize
+                        .parse()
+                        .with_context(|| format!("invalid filesize {filesize:?}"))?,
+                ),
+            },
+            mb_albumid: match next_field("mb_albumid")? {
+                "" => None,
+                mb_albumid => Some(mb_albumid.to_owned()),
+            },
+            genre: optional_field(fields.next()),
+            format: optional_field(fields.next()),
+            compilation: match fields.next().unwrap_or_default() {
+                "" | "0" | "False" | "false" => false,
+                "1" | "True" | "true" => true,
+                comp => anyhow::bail!("invalid comp flag {comp:?}"),
+            },
+            play_count: match fields.next().unwrap_or_default() {
+                "" | "$play_count" => None,
+                play_count => Some(
+                    play_count
+                        .parse()
+                        .with_context(|| format!("invalid play count {play_count:?}"))?,
+                ),
+            },
+            extra: fields.map(str::to_owned).collect(),
+        })
+    }
+    pub fn date(&self) -> Date {
+        self.timestamp.date()
+    }
+    /// Value of the extra field `name` at the `index` (in the order fetched), if set
+    ///
+    /// Beets formats a field missing from the item (e.g. from a plugin that is not enabled) as the
+    /// literal `$name`, treated as unset.
+    pub fn extra_field(&self, index: usize, name: &str) -> Option<&str> {
+        self.extra
+            .get(index)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty() && value.strip_prefix('$') != Some(name))
+    }
+    /// Whether the date is missing (parsed as the Unix epoch) or the Unix epoch in any time
+    /// zone, e.g. for items imported with `added: 0`
+    pub fn is_undated(&self) -> bool {
+        self.timestamp.is_epoch()
+    }
+    /// Link to the MusicBrainz release of the album, when known
+    pub fn musicbrainz_url(&self) -> Option<String> {
+        let mb_albumid = self.mb_albumid.as_ref()?;
+        Some(format!("https://musicbrainz.org/release/{mb_albumid}"))
+    }
+    /// Key identifying the album of the entry (or the track itself, for singletons)
+    pub fn album_key(&self) -> (&str, &str) {
+        if self.album.is_empty() {
+            (&self.artist, &self.title)
+        } else {
+            (&self.album_artist, &self.album)
+        }
+    }
+}
+fn optional_field(field: Option<&str>) -> Option<String> {
+    field.filter(|field| !field.is_empty()).map(str::to_owned)
+}
+impl std::fmt::Display for DateEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            artist,
+            album,
+            title,
+            ..
+        } = self;
+        write!(f, "{artist} - {album} - {title}")
+    }
 }
+/// Parses a line formatted as `$added $artist - $album - $title`
 impl TryFrom<String> for DateEntry {
     type Error = anyhow::Error;
 
     fn try_from(s: String) -> anyhow::Result<Self> {
-        // 01234567890123456789...
-        // YYYY-MM-DD HH:MM:SS
-        const DATE_LENGTH: usize = 10;
-        const ENTRY_START: usize = 20;
-        // NOTE: Date portion is guaranteed to be ascii
-        if ENTRY_START < s.len() {
+        // YYYY-MM-DD HH:MM:SS ENTRY
+        let mut parts = s.splitn(3, ' ');
+        if let (Some(date), Some(time), Some(entry)) = (
+            parts.next(),
+            parts.next(),
+            parts.next().filter(|entry| !entry.is_empty()),
+        ) {
+            let timestamp = format!("{date} {time}").parse()?;
+            let (artist, album, title) = match entry.splitn(3, " - ").collect::<Vec<_>>()[..] {
+                [artist, album, title] => (artist, album, title),
+                _ => ("", "", entry),
+            };
             Ok(DateEntry {
-                date: s[..DATE_LENGTH].to_owned(),
-                entry: s[ENTRY_START..].to_owned(),
+                id: None,
+                timestamp,
+                album_artist: artist.to_owned(),
+                album: album.to_owned(),
+                artist: artist.to_owned(),
+                title: title.to_owned(),
+                filesize: None,
+                mb_albumid: None,
+                genre: None,
+                format: None,
+                compilation: false,
+                play_count: None,
+                extra: vec![],
             })
         } else {
             anyhow::bail!("entry too short: {s}")
         }
     }
 }
+/// Parses lines formatted as `$added $artist - $album - $title`, truncated to `max_entries`
+///
+/// When `lenient`, lines failing to parse are collected in [`ParsedEntries::failures`] instead
+/// of aborting.
+pub fn parse_entry_lines(
+    input: &[u8],
+    max_entries: usize,
+    lenient: bool,
+) -> anyhow::Result<ParsedEntries> {
+    parse_lines(input, max_entries, lenient, "entries input", |line| {
+        DateEntry::try_from(line.to_owned()).map(Some)
+    })
+}
 
-#[derive(Clone, Copy, Debug)]
+/// Entries parsed from lines of text
+#[derive(Debug, Default)]
+pub struct ParsedEntries {
+    pub entries: Vec<DateEntry>,
+    /// Lines skipped in lenient mode
+    pub failures: Vec<ParseFailure>,
+    /// Whether lines beyond `max_entries` were ignored
+    pub truncated: bool,
+}
+/// Line of input that failed to parse as a [`DateEntry`]
+#[derive(Clone, Debug)]
+pub struct ParseFailure {
+    /// Line number, starting from 1
+    pub line_number: usize,
+    /// Content of the line (lossy, if not valid UTF-8)
+    pub content: String,
+    pub error: String,
+}
+/// Strips the line ending (`\r` from CRLF output) and trailing whitespace from the `line`
+///
+/// Keeps trailing tabs, which separate (possibly empty) trailing fields.
+fn normalize_line(line: &str) -> &str {
+    line.trim_end_matches(|c: char| c.is_whitespace() && c != '\t')
+}
+
+/// Parses the non-empty lines of `input` using `parse_line` (which may skip lines by returning
+/// `None`), truncated to `max_entries` lines
+///
+/// Lines are normalized by [`normalize_line`] before parsing.
+pub(crate) fn parse_lines(
+    input: &[u8],
+    max_entries: usize,
+    lenient: bool,
+    source: &str,
+    mut parse_line: impl FnMut(&str) -> anyhow::Result<Option<DateEntry>>,
+) -> anyhow::Result<ParsedEntries> {
+    let mut parsed = ParsedEntries::default();
+    let mut progress = progress::Progress::start("parse");
+    let mut lines = input
+        .split(|&byte| byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty());
+    for (index, line) in lines.by_ref().take(max_entries) {
+        let line_number = index + 1;
+        let result = std::str::from_utf8(line)
+            .context("non-utf8 line")
+            .and_then(|line| parse_line(normalize_line(line)));
+        match result {
+            Ok(entry) => parsed.entries.extend(entry),
+            Err(err) if lenient => parsed.failures.push(ParseFailure {
+                line_number,
+                content: normalize_line(&String::from_utf8_lossy(line)).to_owned(),
+                error: format!("{err:#}"),
+            }),
+            Err(err) => return Err(err.context(format!("line {line_number} from {source}"))),
+        }
+        progress.update(parsed.entries.len());
+    }
+    progress.finish(parsed.entries.len());
+    parsed.truncated = lines.next().is_some();
+    Ok(parsed)
+}
+
+/// Counts the distinct library items of the `entries`, as counted by a `beet ls` query for
+/// the same items (entries without an id are counted individually)
+pub fn count_items(entries: &[DateEntry]) -> usize {
+    let mut ids = std::collections::HashSet::new();
+    entries
+        .iter()
+        .filter(|entry| entry.id.is_none_or(|id| ids.insert(id)))
+        .count()
+}
+
+/// Counts the `entries` (sorted newest first) in each period, newest first, e.g. for additions
+/// per week
+///
+/// Entries preceding the first period are not counted.
+pub fn count_per_period(entries: &[DateEntry], period: &dyn date::Period) -> Vec<(Date, usize)> {
+    let mut counts: Vec<(Date, usize)> = vec![];
+    for period_start in entries
+        .iter()
+        .filter_map(|entry| period.period_start(entry.date()))
+    {
+        match counts.last_mut() {
+            Some((start, count)) if *start == period_start => *count += 1,
+            _ => counts.push((period_start, 1)),
+        }
+    }
+    counts
+}
+
+#[derive(Clone, Debug)]
 pub struct Transition<'a> {
     pub index: usize,
     pub included: &'a DateEntry,
     pub excluded: &'a DateEntry,
+    /// Earliest date included by the transition (may precede `included.date()` when snapped)
+    pub cutoff: Date,
 }
 pub fn find_transition(items: &[DateEntry], target_count: usize) -> Option<Transition<'_>> {
+    find_transition_with(items, target_count, TransitionOptions::default())
+}
+
+/// Unit counted toward the target counts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CountUnit {
+    /// Each entry counts once
+    #[default]
+    Tracks,
+    /// Consecutive entries with an identical timestamp (e.g. a bulk import) count once
+    Events,
+    /// Entries from the same album count once
+    Albums,
+}
+impl CountUnit {
+    /// Returns the number of units counted from the start through each item
+    pub fn running_counts(self, items: &[DateEntry]) -> Vec<usize> {
+        self.weighted_running_counts(items, CompilationWeight::default())
+    }
+    /// Returns the number of units counted from the start through each item, with units
+    /// started by a compilation item counting as the `compilations` weight (rounded down)
+    pub fn weighted_running_counts(
+        self,
+        items: &[DateEntry],
+        compilations: CompilationWeight,
+    ) -> Vec<usize> {
+        self.running_counts_by(items, |item| {
+            if item.compilation {
+                compilations.per_mille
+            } else {
+                CompilationWeight::FULL
+            }
+        })
+    }
+    /// Returns the number of units counted from the start through each item, with each unit
+    /// counting as the `per_mille` weight of the item starting it (rounded down)
+    pub(crate) fn running_counts_by(
+        self,
+        items: &[DateEntry],
+        per_mille_of: impl Fn(&DateEntry) -> usize,
+    ) -> Vec<usize> {
+        let mut per_mille = 0;
+        let mut prev = None;
+        let mut seen_albums: std::collections::HashSet<(&str, &str)> =
+            std::collections::HashSet::new();
+        items
+            .iter()
+            .map(|item| {
+                let is_new_unit = match self {
+                    CountUnit::Tracks => true,
+                    CountUnit::Events => {
+                        prev.is_none_or(|prev: &DateEntry| prev.timestamp != item.timestamp)
+                    }
+                    CountUnit::Albums => seen_albums.insert(item.album_key()),
+                };
+                if is_new_unit {
+                    per_mille += per_mille_of(item);
+                }
+                prev = Some(item);
+                per_mille / CompilationWeight::FULL
+            })
+            .collect()
+    }
+    /// Plural name of the unit
+    pub fn name(self) -> &'static str {
+        match self {
+            CountUnit::Tracks => "tracks",
+            CountUnit::Events => "events",
+            CountUnit::Albums => "albums",
+        }
+    }
+}
+impl std::str::FromStr for CountUnit {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "tracks" => Ok(Self::Tracks),
+            "events" => Ok(Self::Events),
+            "albums" => Ok(Self::Albums),
+            _ => {
+                anyhow::bail!("unknown unit {s:?}, expected \"tracks\", \"events\", or \"albums\"")
+            }
+        }
+    }
+}
+
+/// Weight of each compilation item (see [`DateEntry::compilation`]) toward the target counts,
+/// e.g. so a large various artists album takes less of the playlist budget
+///
+/// Parsed from `full`, `exclude`, or a fraction from 0 to 1 (e.g. `0.25`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompilationWeight {
+    per_mille: usize,
+}
+impl CompilationWeight {
+    const FULL: usize = 1000;
+    /// Whether compilation items count less than other items
+    pub fn is_reduced(self) -> bool {
+        self.per_mille < Self::FULL
+    }
+}
+impl Default for CompilationWeight {
+    fn default() -> Self {
+        Self {
+            per_mille: Self::FULL,
+        }
+    }
+}
+impl std::str::FromStr for CompilationWeight {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let weight: f64 = match s {
+            "full" => 1.0,
+            "exclude" => 0.0,
+            weight => weight.parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "unknown compilation weight {s:?}, expected \"full\", \"exclude\", or a fraction"
+                )
+            })?,
+        };
+        if !(0.0..=1.0).contains(&weight) {
+            anyhow::bail!("compilation weight {s:?} out of range, expected 0 to 1");
+        }
+        Ok(Self {
+            per_mille: (weight * Self::FULL as f64).round() as usize,
+        })
+    }
+}
+impl std::fmt::Display for CompilationWeight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.per_mille {
+            Self::FULL => write!(f, "full"),
+            0 => write!(f, "exclude"),
+            per_mille => write!(f, "{}", per_mille as f64 / Self::FULL as f64),
+        }
+    }
+}
+
+/// Handling of entries without a meaningful date (see [`DateEntry::is_undated`])
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UndatedAction {
+    /// Remove the entries, as if not fetched
+    #[default]
+    Exclude,
+    /// Move the entries to the newest date, to be included by every cutoff
+    Include,
+    /// Keep the entries as fetched, older than every dated entry
+    Keep,
+}
+impl UndatedAction {
+    /// Applies the action to the undated `entries` (sorted newest first), returning the number
+    /// of undated entries found
+    pub fn apply(self, entries: &mut Vec<DateEntry>) -> usize {
+        let (mut undated, dated): (Vec<_>, Vec<_>) =
+            entries.drain(..).partition(DateEntry::is_undated);
+        let count = undated.len();
+        *entries = match (self, dated.first()) {
+            (UndatedAction::Exclude, _) => dated,
+            (UndatedAction::Include, Some(newest)) => {
+                for entry in &mut undated {
+                    entry.timestamp = newest.timestamp;
+                }
+                undated.into_iter().chain(dated).collect()
+            }
+            (UndatedAction::Include | UndatedAction::Keep, _) => {
+                dated.into_iter().chain(undated).collect()
+            }
+        };
+        count
+    }
+}
+impl std::str::FromStr for UndatedAction {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "exclude" => Ok(Self::Exclude),
+            "include" => Ok(Self::Include),
+            "keep" => Ok(Self::Keep),
+            _ => anyhow::bail!(
+                "unknown undated action {s:?}, expected \"exclude\", \"include\", or \"keep\""
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TransitionOptions<'a> {
+    /// Unit of the target count
+    pub unit: CountUnit,
+    /// When set, only transitions crossing the start of a period (e.g. a [`date::Snap`]) are
+    /// considered, with the cutoff moved to the period start
+    pub snap: Option<&'a dyn date::Period>,
+    /// Meaning of the displayed and stored cutoff dates (transitions always hold the earliest
+    /// included date)
+    pub boundary: date::Boundary,
+    /// Weight of compilation items toward the target counts
+    pub compilations: CompilationWeight,
+}
+impl TransitionOptions<'_> {
+    /// Returns the number of units counted from the start through each item (see
+    /// [`CountUnit::weighted_running_counts`])
+    pub fn running_counts(&self, items: &[DateEntry]) -> Vec<usize> {
+        self.unit.weighted_running_counts(items, self.compilations)
+    }
+}
+/// Finds the first transition (between dates) that includes more than `target_count` units
+pub fn find_transition_with<'a>(
+    items: &'a [DateEntry],
+    target_count: usize,
+    options: TransitionOptions,
+) -> Option<Transition<'a>> {
+    let running_counts = options.running_counts(items);
+    let first_index = running_counts
+        .iter()
+        .position(|&count| count > target_count)?;
+    transitions_from(items, first_index, options.snap).next()
+}
+/// Finds the `candidate_count` transitions (between dates) whose unit counts are nearest to
+/// `target_count`, ordered from newest to oldest
+pub fn find_transitions_around<'a>(
+    items: &'a [DateEntry],
+    target_count: usize,
+    candidate_count: usize,
+    options: TransitionOptions,
+) -> Vec<Transition<'a>> {
+    let running_counts = options.running_counts(items);
+    let mut transitions: Vec<_> = transitions_from(items, 0, options.snap).collect();
+    transitions.sort_by_key(|transition| {
+        let distance = running_counts[transition.index].abs_diff(target_count);
+        (distance, transition.index)
+    });
+    transitions.truncate(candidate_count);
+    transitions.sort_by_key(|transition| transition.index);
+    transitions
+}
+/// Iterates all transitions (between dates) of the `items`, from newest to oldest
+pub fn transitions<'a, 'p>(
+    items: &'a [DateEntry],
+    options: TransitionOptions<'p>,
+) -> impl Iterator<Item = Transition<'a>> + use<'a, 'p> {
+    transitions_from(items, 0, options.snap)
+}
+/// Iterates all transitions starting at `first_index`
+pub(crate) fn transitions_from<'a, 'p>(
+    items: &'a [DateEntry],
+    first_index: usize,
+    snap: Option<&'p dyn date::Period>,
+) -> impl Iterator<Item = Transition<'a>> + use<'a, 'p> {
     items
         .windows(2)
         .enumerate()
-        .skip(target_count)
-        .find_map(|(index, window)| {
+        .skip(first_index)
+        .filter_map(move |(index, window)| {
             let [first, second] = window else {
                 panic!("windows(2) not yielding two")
             };
-            if first.date != second.date {
-                Some(Transition {
-                    index,
-                    included: first,
-                    excluded: second,
-                })
-            } else {
-                None
-            }
+            boundary_cutoff(first, second, snap).map(|cutoff| Transition {
+                index,
+                included: first,
+                excluded: second,
+                cutoff,
+            })
         })
 }
+/// Returns the cutoff date if a transition occurs between the `first` and `second` entries
+fn boundary_cutoff(
+    first: &DateEntry,
+    second: &DateEntry,
+    snap: Option<&dyn date::Period>,
+) -> Option<Date> {
+    let (first, second) = (first.date(), second.date());
+    match snap {
+        None => (first != second).then_some(first),
+        Some(period) => {
+            let period_start = period.period_start(first)?;
+            (second < period_start).then_some(period_start)
+        }
+    }
+}
+impl Transition<'_> {
+    /// Describes the cutoff relative to `today` and the newest entry, e.g.
+    /// "cutoff 2024-11-03 — 7 weeks ago, spans 54 days of additions"
+    pub fn describe_age(&self, newest: &DateEntry, today: Date) -> String {
+        let cutoff = self.cutoff;
+        let days_ago = cutoff.describe_ago(today);
+        let span_days = cutoff.days_until(newest.date()) + 1;
+        let plural = if span_days == 1 { "" } else { "s" };
+        format!("cutoff {cutoff} — {days_ago}, spans {span_days} day{plural} of additions")
+    }
+}
 impl std::fmt::Display for Transition<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let Transition {
             index,
             included,
             excluded,
+            cutoff,
         } = self;
         let count = index + 1;
-        writeln!(f, "    {}: {} {}", count, included.date, included.entry)?;
-        write!(f, "    {}: {} {}", count + 1, excluded.date, excluded.entry)
+        writeln!(f, "    {}: {} {}", count, included.date(), included)?;
+        write!(f, "    {}: {} {}", count + 1, excluded.date(), excluded)?;
+        if *cutoff != included.date() {
+            write!(f, "\n    cutoff: {cutoff}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(timestamps: &[&str]) -> Vec<DateEntry> {
+        timestamps
+            .iter()
+            .map(|timestamp| {
+                DateEntry::try_from(format!("{timestamp} Artist - Album - Title"))
+                    .expect("valid entry in test case")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transition_counts_events() {
+        let items = entries(&[
+            "2024-03-03 10:00:00",
+            "2024-03-02 09:00:00",
+            "2024-03-02 09:00:00",
+            "2024-03-02 09:00:00",
+            "2024-03-01 08:00:00",
+            "2024-02-01 08:00:00",
+        ]);
+
+        let tracks = find_transition(&items, 2).expect("transition for tracks");
+        assert_eq!(tracks.index, 3);
+        assert_eq!(tracks.cutoff.to_string(), "2024-03-02");
+
+        let options = TransitionOptions {
+            unit: CountUnit::Events,
+            ..TransitionOptions::default()
+        };
+        let events = find_transition_with(&items, 2, options).expect("transition for events");
+        assert_eq!(events.index, 4);
+        assert_eq!(events.cutoff.to_string(), "2024-03-01");
+    }
+
+    #[test]
+    fn entry_lines_skip_blank() {
+        let input = b"2024-03-02 10:00:00 Artist - Album - One\n\n\
+                      2024-03-01 10:00:00 Title Only\n\
+                      2024-02-01 10:00:00 Artist - Album - Three\n";
+
+        let parsed = parse_entry_lines(input, 2, false).expect("valid lines");
+
+        let entries: Vec<_> = parsed.entries.iter().map(ToString::to_string).collect();
+        assert_eq!(entries, ["Artist - Album - One", " -  - Title Only"]);
+    }
+
+    #[test]
+    fn entry_lines_crlf() {
+        let input = b"2024-03-02 10:00:00 Artist - Album - One \r\n\r\n\
+                      2024-03-01 10:00:00 Artist - Album - Two\r\n";
+
+        let parsed = parse_entry_lines(input, 10, false).expect("valid lines");
+
+        let entries: Vec<_> = parsed.entries.iter().map(ToString::to_string).collect();
+        assert_eq!(entries, ["Artist - Album - One", "Artist - Album - Two"]);
+        assert_eq!(normalize_line("a\tb\t \r"), "a\tb\t");
+    }
+
+    #[test]
+    fn entry_lines_lenient_failures() {
+        let input = b"2024-03-02 10:00:00 Artist - Album - One\n\
+                      garbage\n\
+                      2024-03-01 10:00:00 Artist - Album - Two\n";
+
+        let err = parse_entry_lines(input, 10, false).expect_err("strict fails on bad line");
+        assert!(
+            format!("{err:#}").starts_with("line 2 from entries input"),
+            "{err:#}"
+        );
+
+        let parsed = parse_entry_lines(input, 10, true).expect("lenient succeeds");
+        assert_eq!(parsed.entries.len(), 2);
+        let failures: Vec<_> = parsed
+            .failures
+            .iter()
+            .map(|failure| (failure.line_number, failure.content.as_str()))
+            .collect();
+        assert_eq!(failures, [(2, "garbage")]);
+    }
+
+    #[test]
+    fn entry_lines_invalid_timestamps() {
+        for line in [
+            "2024-03-01 10:00:0é Artist - Album - Title",
+            "2024-03-01T10:00:00 Artist - Album - Title",
+            "03/01/2024 10:00:00 Artist - Album - Title",
+            "2024-03-01 10:00 Artist - Album - Title",
+            "2024-03-01 10:00:00 ",
+        ] {
+            assert!(DateEntry::try_from(line.to_owned()).is_err(), "{line}");
+        }
+        let entry =
+            DateEntry::try_from("2024-03-01 10:00:00 Sigur Rós - ( ) - Untitled".to_owned())
+                .expect("valid line");
+        assert_eq!(entry.timestamp.to_string(), "2024-03-01 10:00:00");
+        assert_eq!(
+            (entry.artist.as_str(), entry.title.as_str()),
+            ("Sigur Rós", "Untitled")
+        );
+    }
+
+    #[test]
+    fn transitions_around_target() {
+        let items = entries(&[
+            "2024-03-06 10:00:00",
+            "2024-03-05 10:00:00",
+            "2024-03-04 10:00:00",
+            "2024-03-04 10:00:00",
+            "2024-03-03 10:00:00",
+            "2024-03-02 10:00:00",
+            "2024-03-01 10:00:00",
+        ]);
+        let cutoffs = |target_count, candidate_count| {
+            find_transitions_around(&items, target_count, candidate_count, Default::default())
+                .into_iter()
+                .map(|transition| transition.cutoff.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(cutoffs(4, 3), ["2024-03-05", "2024-03-04", "2024-03-03"]);
+        assert_eq!(cutoffs(1, 2), ["2024-03-06", "2024-03-05"]);
+        assert_eq!(cutoffs(100, 10).len(), 5);
+    }
+
+    #[test]
+    fn count_distinct_items() {
+        let mut items = entries(&[
+            "2024-03-03 10:00:00",
+            "2024-03-02 09:00:00",
+            "2024-03-02 09:00:00",
+            "2024-03-01 08:00:00",
+        ]);
+        assert_eq!(count_items(&items), 4);
+
+        // duplicate ids (e.g. an item matching several filter clauses) count once
+        for (entry, id) in items.iter_mut().zip([1, 2, 2, 3]) {
+            entry.id = Some(id);
+        }
+        assert_eq!(count_items(&items), 3);
+    }
+
+    #[test]
+    fn counts_per_period() {
+        let items = entries(&[
+            "2024-03-11 10:00:00",
+            "2024-03-10 09:00:00",
+            "2024-03-04 09:00:00",
+            "2024-02-20 08:00:00",
+        ]);
+        let date = |s: &str| s.parse::<Date>().expect("valid date");
+
+        let weekly = count_per_period(&items, &date::Snap::Week);
+        assert_eq!(
+            weekly,
+            [
+                (date("2024-03-11"), 1),
+                (date("2024-03-04"), 2),
+                (date("2024-02-19"), 1)
+            ]
+        );
+
+        let anchors = date::Anchors::new(vec![date("2024-03-01")]);
+        let since_anchor = count_per_period(&items, &anchors);
+        assert_eq!(since_anchor, [(date("2024-03-01"), 3)]);
+    }
+
+    #[test]
+    fn entry_lines_truncated() {
+        let input = b"2024-03-02 10:00:00 Artist - Album - One\n\n\
+                      2024-03-01 10:00:00 Artist - Album - Two\n\n";
+
+        let parsed = parse_entry_lines(input, 2, false).expect("valid lines");
+        assert!(!parsed.truncated);
+
+        let parsed = parse_entry_lines(input, 1, false).expect("valid lines");
+        assert!(parsed.truncated);
+        assert_eq!(parsed.entries.len(), 1);
+    }
+
+    #[test]
+    fn undated_actions() {
+        let apply = |action: UndatedAction| {
+            let mut items = entries(&[
+                "2024-03-02 10:00:00",
+                "2024-03-01 09:00:00",
+                "1970-01-01 00:00:00",
+                "1969-12-31 19:00:00",
+            ]);
+            let count = action.apply(&mut items);
+            let dates: Vec<_> = items.iter().map(|item| item.date().to_string()).collect();
+            (count, dates)
+        };
+
+        assert_eq!(
+            apply(UndatedAction::Exclude),
+            (2, vec!["2024-03-02".to_owned(), "2024-03-01".to_owned()])
+        );
+        let (_, included) = apply(UndatedAction::Include);
+        assert_eq!(
+            included,
+            ["2024-03-02", "2024-03-02", "2024-03-02", "2024-03-01"]
+        );
+        let (_, kept) = apply(UndatedAction::Keep);
+        assert_eq!(
+            kept,
+            ["2024-03-02", "2024-03-01", "1970-01-01", "1969-12-31"]
+        );
+
+        let line = "5\t\tArtist\tAlbum\tArtist\tTitle\t\t";
+        let entry = DateEntry::parse_fields(line).expect("empty date");
+        assert!(entry.is_undated());
+    }
+
+    #[test]
+    fn compilation_weights() {
+        let line =
+            |comp: &str| format!("1\t2024-03-01 10:00:00\tVarious\tHits\tA\tT\t\t\t\t\t{comp}");
+        let parse =
+            |comp: &str| DateEntry::parse_fields(&line(comp)).map(|entry| entry.compilation);
+        assert_eq!(parse("True").ok(), Some(true));
+        assert_eq!(parse("0").ok(), Some(false));
+        assert!(parse("maybe").is_err());
+        let without_comp = DateEntry::parse_fields("1\t2024-03-01 10:00:00\tA\tB\tA\tT\t\t")
+            .expect("comp field omitted");
+        assert!(!without_comp.compilation);
+
+        let mut items = entries(&[
+            "2024-03-05 10:00:00",
+            "2024-03-04 10:00:00",
+            "2024-03-04 10:00:00",
+            "2024-03-04 10:00:00",
+            "2024-03-03 10:00:00",
+        ]);
+        for item in &mut items[1..4] {
+            item.compilation = true;
+        }
+        let counts = |weight: &str| {
+            let weight: CompilationWeight = weight.parse().expect("valid weight");
+            CountUnit::Tracks.weighted_running_counts(&items, weight)
+        };
+        assert_eq!(counts("full"), [1, 2, 3, 4, 5]);
+        assert_eq!(counts("exclude"), [1, 1, 1, 1, 2]);
+        assert_eq!(counts("0.5"), [1, 1, 2, 2, 3]);
+        assert!("1.5".parse::<CompilationWeight>().is_err());
+        assert_eq!(
+            "0.25"
+                .parse::<CompilationWeight>()
+                .expect("weight")
+                .to_string(),
+            "0.25"
+        );
     }
 }
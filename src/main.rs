@@ -1,48 +1,1170 @@
 use anyhow::Context as _;
+#[cfg(feature = "mqtt")]
+use beet_smart_cutoff::sink::MqttSink;
+#[cfg(feature = "webhook")]
+use beet_smart_cutoff::sink::WebhookSink;
 use beet_smart_cutoff::{
-    beet_command::BeetCommand, find_transition, json, prompt::Prompt, DateEntry, Transition,
+    artist, audit,
+    beet_command::{self, parse_timeless, BeetCommand, CommandError, FilterSetShare, Sentinel},
+    config,
+    constraint::{self, Constraints, MinCountAction},
+    count_cache::CountCache,
+    count_items,
+    date::{Anchors, Boundary, Date, Period, Snap, Window},
+    dirs,
+    json::{self, OnExisting},
+    parallel, parse_entry_lines,
+    patch::{diff_lines, PatchFile},
+    progress,
+    prompt::Prompt,
+    render::{additions_sparkline, delimited_line, genre_format_summary, Align, Grouped, Table},
+    report::{CandidateReport, CountMismatch, RunReport, RunSummary},
+    selection::{self, adjust_transition, Adjustable, Bookmarks, Selection, SelectionStateMachine},
+    session::Session,
+    shuffle, simulate,
+    sink::{CutoffOutput, JsonFileSink, PatchFileSink, ScriptSink, Sinks, TemplateSink},
+    strategy::{AutoSelect, Candidate, Outcome, StrategySpec},
+    template::Template,
+    transcript::{self, Replay, Transcript},
+    watch::{Debouncer, FileWatcher},
+    CompilationWeight, CountUnit, DateEntry, ItemId, ParsedEntries, Transition, TransitionOptions,
+    UndatedAction,
 };
 use clap::Parser;
-use std::{num::NonZeroUsize, str::FromStr};
+use std::{
+    io::{IsTerminal as _, Write as _},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 #[derive(clap::Parser)]
+#[clap(after_help = Failure::HELP)]
 struct Args {
-    /// Path to the `beet` command from the package `beets`
+    /// TOML file with argument values (e.g. `output_key = "rock"`), overridden by the environment
+    /// and the command line [default: `config.toml` in the user config directory, e.g.
+    /// `~/.config/beet_smart_cutoff`, if it exists]
+    #[clap(env = CONFIG_ENV, long, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+    /// Profile of the config file to run (defined as `[profiles.NAME]`), instead of running each
+    /// profile in turn
+    #[clap(env = PROFILE_ENV, long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Path to the `beet` command from the package `beets`, optionally followed by arguments
+    /// (e.g. `python3 -m beets`, or `nix run nixpkgs#beets --`), quoted as for a shell
+    ///
+    /// Defaults to the first `beet` in the PATH or common install locations (e.g. `~/.local/bin`),
+    /// unless reading --entries-from.
     #[clap(env, long)]
-    beet_command: std::path::PathBuf,
+    beet_command: Option<String>,
     /// Newline separated list of filter arguments to `beet list` (excluding the date "added" filter)
+    #[clap(
+        env,
+        long,
+        required_unless_present_any = ["entries_from", "timeless_args_file"]
+    )]
+    timeless_args: Option<String>,
+    /// File with the filter arguments to `beet list`, one per line, with a blank line between
+    /// alternative filter sets (lines starting with `#` are ignored), instead of --timeless-args
+    #[clap(env, long, value_name = "PATH", conflicts_with = "timeless_args")]
+    timeless_args_file: Option<std::path::PathBuf>,
+    /// Filter arguments (formatted as `timeless_args`) for items to exclude, e.g. the
+    /// `timeless_args` of another playlist, for an "everything except" playlist
     #[clap(env, long)]
-    timeless_args: String,
+    exclude_args: Option<String>,
+    /// Only include items rated at least this value (the `rating` field, e.g. from 0 to 1 with
+    /// the `mpdstats` plugin), in both the fetched entries and the counts
+    ///
+    /// Unrated items are excluded too. The final count notes how many items after the cutoff are
+    /// excluded by the rating.
+    #[clap(env, long, value_name = "RATING", conflicts_with = "entries_from")]
+    min_rating: Option<f64>,
+    /// Only fetch items added on or after this date (YYYY-MM-DD), to reduce the fetch size on
+    /// large libraries when the cutoff is known to be more recent
+    #[clap(env, long)]
+    since: Option<Date>,
+    /// Only fetch (and count) items added on or before this date (YYYY-MM-DD)
+    #[clap(env, long)]
+    until: Option<Date>,
+    /// Leave out the newest additions (still being tagged or re-imported) from the candidates and
+    /// counts, e.g. `3d` for items added after the date 3 days ago (as --until)
+    #[clap(env, long, value_name = "WINDOW")]
+    burn_in: Option<Window>,
+    /// Newline separated query matching a single "sentinel" item, which stores the cutoff in the
+    /// `--sentinel-field` flexible attribute (in addition to, or instead of, the output file)
+    #[clap(env, long)]
+    sentinel_query: Option<String>,
+    /// Flexible attribute of the sentinel item storing the cutoff
+    #[clap(env, long, default_value = "smart_cutoff")]
+    sentinel_field: String,
+    /// Read the entries from a file (or `-` for stdin) instead of querying beets, formatted as
+    /// `$added $artist - $album - $title` lines sorted newest first
+    #[clap(long, value_name = "PATH")]
+    entries_from: Option<std::path::PathBuf>,
     #[clap(long, default_value_t = 400)]
     max_entries: usize,
+    /// Upper bound for automatically re-fetching more than `max_entries` (for larger targets)
+    #[clap(long, default_value_t = 10_000)]
+    max_entries_hard_limit: usize,
+    /// For interactive runs, fetch twice the largest target count at first (at most
+    /// `max_entries`), fetching more only while targets are out of range
+    #[clap(env, long)]
+    adaptive_fetch: bool,
     /// Output JSON file
     #[clap(env, long)]
     output_file: Option<std::path::PathBuf>,
     /// Key for the output file date
     #[clap(env, long)]
     output_key: Option<String>,
+    /// Store the output key in the object matching FIELD=VALUE within an output file holding a
+    /// JSON array (e.g. `name=recent` for `[{"name": "recent", "cutoff": ...}, ...]`), adding
+    /// the object if none match
+    #[clap(env, long, value_name = "FIELD=VALUE", requires = "output_file")]
+    output_array_match: Option<json::ArrayMatch>,
+    /// Accept comments (`//` and `/* */`) and trailing commas in the output file, e.g. when also
+    /// edited by hand (the comments are not kept when writing)
+    #[clap(env, long, requires = "output_file")]
+    output_comments: bool,
+    /// Key of another cutoff in the output file (e.g. of an enclosing playlist tier), compared
+    /// with each candidate as newer or older, with the count included by both
+    #[clap(env, long, value_name = "KEY", requires = "output_file")]
+    compare_with: Option<String>,
+    /// Show the additions per day within two weeks of each candidate cutoff, to spot cutoffs in
+    /// the middle of a busy period
+    #[clap(env, long)]
+    sparklines: bool,
+    /// Show the top genres and the mix of formats of the items included by each candidate cutoff,
+    /// e.g. to pick the most varied playlist
+    #[clap(env, long)]
+    genre_mix: bool,
+    /// Comma-separated output keys whose cutoffs must nest, innermost first (e.g.
+    /// `fresh,recent,archive`), checked against the cutoffs stored in the output file when writing
+    #[clap(env, long, value_name = "KEYS", requires = "output_file")]
+    nesting: Option<json::Nesting>,
+    /// Handling of stored cutoffs that would not nest with the written cutoff [error, warn, fix]
+    ///
+    /// Fixing adjusts the stored cutoffs of the other keys to the nearest nesting cutoff.
+    #[clap(env, long, default_value = "error")]
+    on_nesting_violation: json::OnNestingViolation,
+    /// Handling of an existing value at the output key [replace, error, append-history]
+    ///
+    /// Existing string values are replaced unless appending history, other values are an error
+    /// unless replacing.
+    #[clap(env, long, default_value = "error")]
+    on_existing: OnExisting,
+    /// Date-like field used in place of `added`, e.g. `mtime` or a flexible attribute
+    /// (must be formatted by beets as `YYYY-MM-DD HH:MM:SS`)
+    #[clap(env, long, default_value = beet_command::DEFAULT_DATE_FIELD)]
+    date_field: String,
+    /// Additional fields shown for the boundary item of each candidate, e.g. `play_count` from the
+    /// mpdstats plugin (fields missing from every item are left out with a warning)
+    #[clap(env, long, value_name = "FIELD", value_delimiter = ',')]
+    extra_fields: Vec<String>,
+    /// Move candidate cutoffs to the start of a calendar period [month, week, fiscal-month:DAY]
+    #[clap(env, long)]
+    snap: Option<Snap>,
+    /// Move candidate cutoffs to the latest of the dates listed in the file (one per line),
+    /// e.g. the dates of past DJ sets
+    #[clap(env, long, value_name = "PATH", conflicts_with = "snap")]
+    snap_anchors: Option<std::path::PathBuf>,
+    /// Whether items added on the stored cutoff date are included [inclusive, exclusive]
+    ///
+    /// An exclusive cutoff stores the day before the earliest included date, for consumers
+    /// querying only the items added after the stored date.
+    #[clap(env, long, default_value = "inclusive")]
+    boundary: Boundary,
+    /// Count target numbers in import events (entries sharing an identical timestamp), instead
+    /// of tracks
+    #[clap(env, long)]
+    group_events: bool,
+    /// Weight of each compilation (various artists) item toward the target counts [full,
+    /// exclude, or a fraction such as 0.25], so a large compilation takes less of the playlist
+    #[clap(env, long, value_name = "WEIGHT", default_value = "full")]
+    compilations: CompilationWeight,
+    /// Target counts, each shown with the first breakpoint exceeding it
+    ///
+    /// Defaults to the strategy of the previous run stored in the output file, or else 30,50,70
+    #[clap(env, long = "targets", value_delimiter = ',')]
+    target_counts: Option<Vec<usize>>,
+    /// Show the N breakpoints nearest to a single target (given by --targets), instead of one
+    /// breakpoint per target
+    #[clap(env, long, value_name = "N")]
+    candidates: Option<NonZeroUsize>,
+    /// Strategy proposing the candidate breakpoints, in place of --targets, one of
+    /// `targets:COUNT,...`, `around:COUNT:N`, `percent:PERCENT,...`, `size:SIZE,...` (e.g.
+    /// `4G`), `window:WINDOW,...` (e.g. `90d`), `album-aligned:COUNT,...`, or `plays:COUNT,...`
+    /// (each item weighted by 1/(plays+1), keeping unplayed items longer)
+    #[clap(env, long, conflicts_with_all = ["target_counts", "candidates"])]
+    strategy: Option<StrategySpec>,
+    /// Use the start of a rolling window ending today as the cutoff (e.g. `90d`, `12w`, `6m`,
+    /// `1y`), instead of interactively selecting a breakpoint for a count
+    #[clap(env, long, conflicts_with = "candidates")]
+    window: Option<Window>,
+    /// Choose the oldest breakpoint including at most COUNT units (and within --window, if
+    /// specified), instead of interactively selecting a breakpoint
+    #[clap(env, long, value_name = "COUNT", conflicts_with = "candidates")]
+    max_count: Option<usize>,
+    /// Choose a candidate without prompting (e.g. for a systemd timer), by the policy `first`,
+    /// `closest` (count nearest to its target), or `largest-under-max` (largest count within the
+    /// largest target)
+    #[clap(env, long, value_name = "POLICY", conflicts_with_all = ["window", "max_count"])]
+    auto_select: Option<AutoSelect>,
+    /// Minimum number of entries after the chosen cutoff (only a warning for interactive
+    /// selections)
+    #[clap(env, long, value_name = "COUNT")]
+    min_count: Option<usize>,
+    /// Action when the --window or --max-count cutoff is below --min-count [error, warn, widen]
+    #[clap(env, long, default_value = "error", requires = "min_count")]
+    on_min_count: MinCountAction,
+    /// Keep running, re-evaluating the --max-count constraint when the --library-db changes, and
+    /// updating the stored cutoff when violated
+    ///
+    /// SIGINT and SIGTERM stop after any in-progress evaluation (including its writes), and a
+    /// second signal exits immediately.
+    #[clap(
+        long,
+        requires_all = ["max_count", "library_db"],
+        conflicts_with = "entries_from"
+    )]
+    watch: bool,
+    /// Path to the beets library database, watched for changes by --watch (and invalidating the
+    /// cached counts)
+    #[clap(env = "BEETS_LIBRARY_DB", long, value_name = "PATH")]
+    library_db: Option<std::path::PathBuf>,
+    /// Read the items and counts from the --library-db directly instead of spawning `beet list`,
+    /// e.g. for large libraries (for a --date-field stored as a column, and querying `beet` instead
+    /// when filtered)
+    #[cfg(feature = "db")]
+    #[clap(env, long, requires = "library_db", conflicts_with = "entries_from")]
+    direct_db: bool,
+    /// File keeping the results of count queries across runs, discarded when the --library-db
+    /// changes [default: `counts.tsv` in the user cache directory, e.g.
+    /// `~/.cache/beet_smart_cutoff`]
+    #[clap(env, long, value_name = "PATH", num_args = 0..=1, requires = "library_db")]
+    count_cache: Option<Option<std::path::PathBuf>>,
+    /// Seconds between checks for changes of the --library-db
+    #[clap(env, long, value_name = "SECONDS", default_value_t = 30)]
+    poll_interval: u64,
+    /// Seconds without further triggers (library changes or reevaluate requests) to wait before
+    /// re-evaluating in --watch and serve modes
+    #[clap(env, long, value_name = "SECONDS", default_value_t = 0)]
+    debounce: u64,
+    /// Assignment `FIELD=VALUE` to set on the items after the chosen cutoff, using `beet modify`
+    /// (may be repeated)
+    #[clap(
+        long = "modify",
+        value_name = "FIELD=VALUE",
+        value_parser = parse_assignment
+    )]
+    modify_assignments: Vec<String>,
+    /// Before applying --modify, clear those values from items before the chosen cutoff
+    /// (e.g. items tagged by a previous run, which are no longer after the cutoff)
+    #[clap(long, requires = "modify_assignments")]
+    clear_previous: bool,
+    /// Text file (e.g. a playlist template) in which to replace the date token matched by
+    /// --pattern with the chosen cutoff
+    #[clap(env, long, value_name = "FILE", requires = "pattern")]
+    patch_file: Option<std::path::PathBuf>,
+    /// Regular expression matching the date token exactly once in the --patch-file, replacing
+    /// its first capture group (if any), or else the whole match
+    #[clap(env, long, value_name = "REGEX", requires = "patch_file")]
+    pattern: Option<regex_lite::Regex>,
+    /// Template file rendered to the --render file after the selection, substituting the
+    /// placeholders `{{cutoff_date}}`, `{{final_count}}`, `{{query}}` (the `beet list` command
+    /// line), `{{choice}}`, and `{{generated_date}}`
+    #[clap(env, long, value_name = "PATH", requires = "render")]
+    template: Option<std::path::PathBuf>,
+    /// Destination of the rendered --template
+    #[clap(env, long, value_name = "PATH", requires = "template")]
+    render: Option<std::path::PathBuf>,
+    /// Write a shell script running the final `beet list` query (passing along its arguments,
+    /// e.g. `--format`), with the chosen values in comments, as a record of the selection
+    #[clap(env, long, value_name = "PATH")]
+    emit_script: Option<std::path::PathBuf>,
+    /// Ensure at least COUNT entries after the cutoff from each of the comma-separated filter sets
+    /// of the timeless args, storing a cutoff for each filter set in the keys `OUTPUT_KEY.1`,
+    /// `OUTPUT_KEY.2`, etc. (older than the chosen cutoff for filter sets with fewer entries)
+    #[clap(env, long, value_name = "COUNT")]
+    balance: Option<usize>,
+    /// Show the count of items after the chosen cutoff matched by each of the comma-separated
+    /// filter sets of the timeless args (e.g. the genre balance of the playlist)
+    #[clap(long)]
+    composition: bool,
+    /// Show the tracks after the chosen cutoff per artist and album, most tracks first (the shape
+    /// of the playlist)
+    #[clap(env, long)]
+    breakdown: bool,
+    /// Keep at most N tracks per artist after the chosen cutoff (the newest of each, grouping
+    /// variants of the artist name), with a query excluding the surplus tracks
+    ///
+    /// The exclusion query is stored at the `--exclusion-key` of the output file, or else printed.
+    #[clap(env, long, value_name = "N")]
+    max_per_artist: Option<NonZeroUsize>,
+    /// Output key storing the query excluding the surplus tracks of `--max-per-artist`
+    /// [default: `OUTPUT_KEY.exclude`]
+    #[clap(env, long, value_name = "KEY", requires = "max_per_artist")]
+    exclusion_key: Option<String>,
+    /// Preview the changes (state file writes, patches, and `beet modify` commands) without
+    /// applying them
+    #[clap(long)]
+    dry_run: bool,
+    /// Never write the outputs (as for --dry-run) nor modify the beets library, e.g. to explore
+    /// a production library safely, also applied when the output file is on a read-only
+    /// filesystem
+    #[clap(env, long)]
+    read_only: bool,
+    /// Skip entry lines that fail to parse (listed in the --report file), instead of aborting
+    #[clap(long)]
+    lenient: bool,
+    /// Handling of entries without a date (empty, or the Unix epoch from `added: 0`)
+    /// [exclude, include, keep]
+    ///
+    /// Included entries count toward every cutoff (though `beet` queries of the cutoff do not
+    /// match them), kept entries remain the oldest entries as fetched.
+    #[clap(env, long, default_value = "exclude")]
+    undated: UndatedAction,
+    /// Refuse a cutoff that skipped lines (with --lenient) or entries beyond --max-entries may
+    /// have affected, or with a count query disagreeing with the fetched entries
+    ///
+    /// Output on the `beet` standard error is always an error.
+    #[clap(long)]
+    strict: bool,
+    /// Save the fetched entries of an interactive run to the file, until a cutoff is chosen
+    /// [default: `session.json` in the user state directory, e.g.
+    /// `~/.local/state/beet_smart_cutoff`]
+    #[clap(env, long, value_name = "PATH", num_args = 0..=1)]
+    session_file: Option<Option<std::path::PathBuf>>,
+    /// Resume an interrupted interactive run from the --session-file, instead of re-querying
+    #[clap(long, requires = "session_file")]
+    resume: bool,
+    /// Maximum number of concurrent `beet` queries (e.g. counts for `show --all`), or of config
+    /// file profiles run at once when not interactive (stdin is not a terminal, e.g. from cron)
+    #[clap(env, long, default_value = DEFAULT_JOBS)]
+    jobs: NonZeroUsize,
+    /// Show additional details, e.g. the duration of each phase
+    #[clap(short, long)]
+    verbose: bool,
+    /// Write progress events of long operations (fetching and parsing the entries) to stderr, as
+    /// JSON lines with the phase, items processed, and elapsed milliseconds, e.g. for a progress
+    /// bar in a wrapping interface
+    #[clap(env, long)]
+    progress_events: bool,
+    /// Ring the terminal bell when the candidates are ready (e.g. after a slow query)
+    #[clap(env, long)]
+    bell: bool,
+    /// Send a desktop notification (via `notify-send`) when the candidates are ready
+    #[clap(env, long)]
+    notify_done: bool,
+    /// Append a JSON line to the file for each value written to the output file (with the old
+    /// and new value, time, user, and host), e.g. to reconcile runs from several machines
+    /// [default: `audit.jsonl` in the user state directory]
+    #[clap(env, long, value_name = "PATH", num_args = 0..=1)]
+    audit_log: Option<Option<std::path::PathBuf>>,
+    /// Also write the count after the cutoff at `OUTPUT_KEY_count` and the time of the update
+    /// (UTC) at `OUTPUT_KEY_updated` of the output file, e.g. for templates showing the count
+    #[clap(env, long, requires = "output_key")]
+    metadata_keys: bool,
+    /// Post the new cutoff as JSON `{"key", "date", "count"}` to the URL (plain http only), e.g.
+    /// for home automation reacting to playlist changes
+    #[cfg(feature = "webhook")]
+    #[clap(env, long, value_name = "URL", requires = "output_key")]
+    webhook_url: Option<beet_smart_cutoff::http::Url>,
+    /// Bearer token authenticating the webhook request
+    #[cfg(feature = "webhook")]
+    #[clap(env, long, hide_env_values = true, requires = "webhook_url")]
+    webhook_token: Option<String>,
+    /// Retries of a failed webhook request, first after a second (doubling for each retry)
+    #[cfg(feature = "webhook")]
+    #[clap(env, long, default_value_t = 3)]
+    webhook_retries: u32,
+    /// Publish the new cutoff to the MQTT broker at HOST[:PORT] as a retained JSON message
+    /// (`{"key", "date", "count", "unit", "summary"}`), e.g. for a Home Assistant dashboard
+    #[cfg(feature = "mqtt")]
+    #[clap(env, long, value_name = "HOST[:PORT]", requires = "output_key")]
+    mqtt_broker: Option<beet_smart_cutoff::mqtt::Broker>,
+    /// Topic of the MQTT message [default: beet_smart_cutoff/OUTPUT_KEY]
+    #[cfg(feature = "mqtt")]
+    #[clap(env, long, requires = "mqtt_broker")]
+    mqtt_topic: Option<String>,
+    /// Username authenticating with the MQTT broker
+    #[cfg(feature = "mqtt")]
+    #[clap(env, long, requires = "mqtt_broker")]
+    mqtt_username: Option<String>,
+    /// Password authenticating with the MQTT broker
+    #[cfg(feature = "mqtt")]
+    #[clap(env, long, hide_env_values = true, requires = "mqtt_username")]
+    mqtt_password: Option<String>,
+    /// Record the interactive session (the candidates shown, each response, and the decision) as
+    /// JSON lines in the file, e.g. to review later why a cutoff was chosen
+    #[clap(env, long, value_name = "PATH")]
+    transcript: Option<std::path::PathBuf>,
+    /// Replay the responses recorded by --transcript in the file against the current library,
+    /// showing how the candidates changed, and stopping if the result differs from the
+    /// recorded decision
+    #[clap(env, long, value_name = "PATH")]
+    replay: Option<std::path::PathBuf>,
+    /// Write a JSON report of the run (e.g. phase durations) to the file
+    #[clap(env, long)]
+    report: Option<std::path::PathBuf>,
+    /// Write a single-line JSON summary of the result (cutoff, count, files written) to the
+    /// inherited file descriptor, e.g. `--summary-fd 3 3>summary.json`
+    #[clap(long, value_name = "FD")]
+    summary_fd: Option<i32>,
+    #[clap(subcommand)]
+    command: Option<Subcommand>,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    let ParsedArgs {
-        beets,
-        max_entries,
-        output_file_key,
-    } = {
-        let beets = BeetCommand::new(args.beet_command, &args.timeless_args, args.max_entries);
-        let output_file_key = match (args.output_file, args.output_key) {
-            (Some(file), Some(key)) => Some((file, key)),
-            (None, None) => None,
-            (Some(_), None) => anyhow::bail!("missing output_key for provided output_file"),
-            (None, Some(_)) => anyhow::bail!("missing output_file for provided output_key"),
-        };
-        ParsedArgs {
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Write the items after the cutoff in a randomized order (e.g. a static playlist file)
+    ExportShuffled(ExportShuffledArgs),
+    /// Write the fetched entries to a CSV file (TSV for a `.tsv` path), flagging the entries
+    /// included by the cutoff
+    ExportCsv(ExportCsvArgs),
+    /// Print the stored cutoff with the current count of items after it
+    Show(ShowArgs),
+    /// Project the growth of the count after a cutoff, from the recent rate of additions
+    Simulate(SimulateArgs),
+    /// Listen for HTTP requests to re-evaluate the --max-count constraint (e.g. from a beets
+    /// import hook): `POST /reevaluate` and `GET /status`
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+    /// Run a `beet` command with the arguments after `--` (following any arguments in
+    /// --beet-command), checking the exit status and stderr, and printing its output
+    Raw(RawArgs),
+    /// Count the items added between two dates (inclusive)
+    Count(CountArgs),
+    /// Print the structure of the filter sets in the timeless args (and --exclude-args), the
+    /// resulting `beet` command, and any suspicious atoms
+    LintFilters,
+}
+
+#[derive(clap::Args)]
+struct CountArgs {
+    /// First date of the range (YYYY-MM-DD)
+    #[clap(long)]
+    from: Date,
+    /// Last date of the range (YYYY-MM-DD), defaults to today
+    #[clap(long)]
+    to: Option<Date>,
+}
+
+#[derive(clap::Args)]
+struct RawArgs {
+    /// Seconds to wait for `beet` before killing it (with any processes it started), counted as a
+    /// failure to run it
+    #[clap(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+    /// Retries when `beet` fails to run (or times out) or exits unsuccessfully, first after a
+    /// second (doubling for each retry), but not for errors on stderr (e.g. an invalid query)
+    ///
+    /// Only read-only commands (e.g. `list`) are retried, as the others may have partially
+    /// modified the library.
+    #[clap(long, default_value_t = 0)]
+    retries: u32,
+    /// Arguments to `beet`
+    #[clap(last = true, required = true)]
+    args: Vec<String>,
+}
+
+#[cfg(feature = "serve")]
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[clap(long, default_value = "127.0.0.1:9184")]
+    listen: std::net::SocketAddr,
+}
+
+#[derive(clap::Args)]
+struct SimulateArgs {
+    /// Cutoff date (YYYY-MM-DD), defaults to the date stored in the output file key
+    #[clap(long)]
+    cutoff: Option<Date>,
+    /// Number of weeks to project
+    #[clap(long, default_value_t = 8)]
+    weeks: u32,
+    /// Count at which to review the cutoff (defaults to twice the current count)
+    #[clap(long, value_name = "COUNT")]
+    review_count: Option<usize>,
+}
+
+#[derive(clap::Args)]
+struct ShowArgs {
+    /// Show every key in the output file with a date value, instead of only the output key
+    ///
+    /// The keys of config file profiles (by their `output_key`) are counted with the
+    /// `timeless_args`, `exclude_args` and `min_rating` of the profile, in one table instead of
+    /// once per profile.
+    #[clap(long)]
+    all: bool,
+}
+
+#[derive(clap::Args)]
+struct ExportCsvArgs {
+    /// Destination file, with a header line
+    path: std::path::PathBuf,
+    /// Cutoff date (YYYY-MM-DD), defaults to the date stored in the output file key
+    #[clap(long)]
+    cutoff: Option<Date>,
+}
+
+#[derive(clap::Args)]
+struct ExportShuffledArgs {
+    /// Destination file, one formatted item per line
+    path: std::path::PathBuf,
+    /// Cutoff date (YYYY-MM-DD), defaults to the date stored in the output file key
+    #[clap(long)]
+    cutoff: Option<Date>,
+    /// Format of each item line, passed to `beet list --format`
+    #[clap(long, default_value = "$path")]
+    format: String,
+    /// Seed for a reproducible order (random if omitted)
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Bias toward placing the newest items first, where the newest item is weighted
+    /// `1 + NEWEST_BIAS` times the oldest item (0 for a uniform shuffle)
+    #[clap(long, default_value_t = 0.0)]
+    newest_bias: f64,
+}
+
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(err) => {
+            let failure = Failure::of(&err);
+            if failure != Failure::Quit {
+                eprintln!("Error: {err:?}");
+            }
+            std::process::ExitCode::from(failure as u8)
+        }
+    }
+}
+
+/// Class of error ending the run, distinguished by the exit code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Failure {
+    Other = 1,
+    /// Invalid arguments (including those rejected by clap) or input files
+    Config = 2,
+    /// Running the `beet` command failed
+    BeetSpawn = 3,
+    /// The `beet` command reported an error
+    BeetQuery = 4,
+    /// Invalid output from `beet` (or lines read from --entries-from)
+    Parse = 5,
+    /// The interactive selection was abandoned
+    Quit = 6,
+    /// Writing an output file failed
+    Write = 7,
+    /// The result differs from the expected one (the decision recorded in the --replay session)
+    Changed = 8,
+}
+impl Failure {
+    const HELP: &'static str = "\
+Exit codes:
+  0  success
+  1  other error
+  2  invalid arguments or input files
+  3  failed to run the beet command
+  4  the beet command reported an error
+  5  invalid beet output (or --entries-from lines)
+  6  quit without selecting a cutoff
+  7  failed to write an output file
+  8  the result differs from the --replay decision";
+
+    /// Class of the innermost classified cause of the `err`
+    fn of(err: &anyhow::Error) -> Self {
+        err.chain()
+            .rev()
+            .find_map(|cause| {
+                if let Some(classified) = cause.downcast_ref::<Classified>() {
+                    match Self::of(&classified.err) {
+                        Self::Other => Some(classified.failure),
+                        inner => Some(inner),
+                    }
+                } else {
+                    match cause.downcast_ref::<CommandError>()? {
+                        CommandError::Spawn(_) => Some(Self::BeetSpawn),
+                        CommandError::Stderr(_) | CommandError::Status(_) => Some(Self::BeetQuery),
+                    }
+                }
+            })
+            .unwrap_or(Self::Other)
+    }
+}
+
+/// Error marked with the [`Failure`] class, displayed as the inner error
+#[derive(Debug)]
+struct Classified {
+    failure: Failure,
+    err: anyhow::Error,
+}
+impl std::fmt::Display for Classified {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.err)
+    }
+}
+impl std::error::Error for Classified {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.err.source()
+    }
+}
+
+trait ClassifyFailure<T> {
+    /// Marks the error with the `failure` class, for the exit code
+    fn failure(self, failure: Failure) -> anyhow::Result<T>;
+}
+impl<T> ClassifyFailure<T> for anyhow::Result<T> {
+    fn failure(self, failure: Failure) -> anyhow::Result<T> {
+        self.map_err(|err| Classified { failure, err }.into())
+    }
+}
+
+/// Environment variable of the --config path
+const CONFIG_ENV: &str = "BEET_SMART_CUTOFF_CONFIG";
+/// Environment variable of the --profile name
+const PROFILE_ENV: &str = "BEET_SMART_CUTOFF_PROFILE";
+/// Environment variable set for the profiles run concurrently, to lock the output file writes
+const CONCURRENT_ENV: &str = "BEET_SMART_CUTOFF_CONCURRENT";
+/// Default of --jobs
+const DEFAULT_JOBS: &str = "4";
+
+/// Profiles of the config file to run, when no --profile is selected
+struct ProfileBatch {
+    profiles: Vec<String>,
+    /// Maximum number of profiles run at once (--jobs, from the command line, the environment, or
+    /// the top-level config values)
+    jobs: NonZeroUsize,
+}
+
+/// Returns the value of the option `name` (e.g. `--config`) in the raw command line arguments, for
+/// options needed before parsing
+fn raw_option(name: &str) -> Option<std::ffi::OsString> {
+    let mut raw_args = std::env::args_os().skip(1);
+    let mut value = None;
+    while let Some(arg) = raw_args.next() {
+        if arg == name {
+            value = raw_args.next();
+        } else if let Some(inline) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix(name)?.strip_prefix('='))
+        {
+            value = Some(inline.into());
+        } else if arg == "--" {
+            break;
+        }
+    }
+    value
+}
+
+/// Sets the environment variable of each argument in the config file (from --config, or else in
+/// the default location), unless already set
+///
+/// Values from the environment and then the command line take precedence, as when parsing. For a
+/// config file with profiles, the values of the --profile are applied, or else all profiles are
+/// returned (to run each, see [`run_profiles`]).
+fn apply_config() -> anyhow::Result<Option<ProfileBatch>> {
+    let Some(path) = config_path() else {
+        return Ok(None);
+    };
+    let config = config::Config::read(&path)?;
+    let profile = raw_option("--profile").or_else(|| std::env::var_os(PROFILE_ENV));
+    let values = match profile {
+        Some(profile) => {
+            let profile = profile.to_str().context("invalid --profile")?;
+            config
+                .profile_values(profile)
+                .with_context(|| format!("in {path:?}"))?
+        }
+        None if !config.profiles.is_empty() && !is_help_requested() && !is_show_all_requested() => {
+            let config_jobs = config
+                .values
+                .iter()
+                .find(|(key, _)| key == "jobs")
+                .map(|(_, jobs)| jobs.into());
+            let jobs = raw_option("--jobs")
+                .or_else(|| std::env::var_os("JOBS"))
+                .or(config_jobs)
+                .unwrap_or_else(|| DEFAULT_JOBS.into());
+            let jobs = jobs
+                .to_str()
+                .and_then(|jobs| jobs.parse().ok())
+                .with_context(|| format!("invalid --jobs {jobs:?}"))?;
+            return Ok(Some(ProfileBatch {
+                profiles: config.profiles.into_iter().map(|(name, _)| name).collect(),
+                jobs,
+            }));
+        }
+        None => config.values,
+    };
+    let command = <Args as clap::CommandFactory>::command();
+    for (key, value) in values {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str() || arg.get_long() == Some(long.as_str()))
+            .with_context(|| format!("unknown argument {key:?} in {path:?}"))?;
+        let env = arg.get_env().with_context(|| {
+            format!("argument {key:?} in {path:?} is only accepted on the command line")
+        })?;
+        if std::env::var_os(env).is_none() {
+            std::env::set_var(env, value);
+        }
+    }
+    Ok(None)
+}
+/// Path of the config file, from the command line, the environment, or the default location
+fn config_path() -> Option<std::path::PathBuf> {
+    raw_option("--config")
+        .or_else(|| std::env::var_os(CONFIG_ENV))
+        .map(std::path::PathBuf::from)
+        .or_else(|| dirs::existing(dirs::Kind::Config, config::FILE_NAME))
+}
+/// Whether the subcommand is `show --all`, counting the keys of all profiles in one table (see
+/// [`read_profile_filters`])
+fn is_show_all_requested() -> bool {
+    <Args as clap::CommandFactory>::command()
+        .ignore_errors(true)
+        .try_get_matches()
+        .is_ok_and(|matches| {
+            matches
+                .subcommand_matches("show")
+                .is_some_and(|show| show.get_flag("all"))
+        })
+}
+fn is_help_requested() -> bool {
+    std::env::args_os()
+        .skip(1)
+        .take_while(|arg| arg != "--")
+        .any(|arg| {
+            ["-h", "--help", "-V", "--version", "help"]
+                .map(Some)
+                .contains(&arg.to_str())
+        })
+}
+
+/// Runs each of the config file profiles as a process with the same arguments
+///
+/// From a terminal, the profiles run in turn (e.g. prompting for each). Otherwise up to `jobs`
+/// profiles run at once without input, showing the output of each when finished, and locking the
+/// writes of the output file (see [`json::lock_writes`]).
+fn run_profiles(batch: ProfileBatch) -> anyhow::Result<()> {
+    let ProfileBatch { profiles, jobs } = batch;
+    let executable = std::env::current_exe().context("locating the executable for the profiles")?;
+    let concurrent = jobs.get() > 1 && !std::io::stdin().is_terminal();
+    let print_header = |index: usize, profile: &str| {
+        println!("#### ");
+        println!(
+            "#### profile {profile:?} ({} of {})",
+            index + 1,
+            profiles.len()
+        );
+        println!("#### ");
+    };
+    // NOTE: hold the lock to print the output of each profile without interleaving
+    let stdout = std::sync::Mutex::new(());
+    let run_profile = |(index, profile): (usize, &String)| -> anyhow::Result<bool> {
+        let mut command = std::process::Command::new(&executable);
+        command
+            .args(std::env::args_os().skip(1))
+            .env(PROFILE_ENV, profile);
+        let context = || format!("running profile {profile:?}");
+        let status = if concurrent {
+            let output = command
+                .env(CONCURRENT_ENV, "1")
+                .stdin(std::process::Stdio::null())
+                .output()
+                .with_context(context)?;
+            let _stdout = stdout
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            print_header(index, profile);
+            let _ = std::io::stdout().write_all(&output.stdout);
+            let _ = std::io::stderr().write_all(&output.stderr);
+            output.status
+        } else {
+            print_header(index, profile);
+            command.status().with_context(context)?
+        };
+        if !status.success() {
+            println!("[profile {profile:?} failed: {status}]");
+        }
+        Ok(status.success())
+    };
+    let jobs = if concurrent { jobs } else { NonZeroUsize::MIN };
+    let results = parallel::map_bounded(profiles.iter().enumerate().collect(), jobs, run_profile);
+    let mut failed = vec![];
+    for (profile, succeeded) in profiles.iter().zip(results) {
+        if !succeeded? {
+            failed.push(profile.as_str());
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} profiles failed: {}",
+            failed.len(),
+            profiles.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn run() -> anyhow::Result<()> {
+    reset_sigpipe();
+
+    if let Some(batch) = apply_config().failure(Failure::Config)? {
+        return run_profiles(batch);
+    }
+    let mut args = Args::parse();
+    let command = args.command.take();
+    if let Some(path) = &args.timeless_args_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading --timeless-args-file {path:?}"))
+            .failure(Failure::Config)?;
+        args.timeless_args = Some(beet_command::filter_file_args(&text));
+    }
+    let snap_anchors = args
+        .snap_anchors
+        .as_ref()
+        .map(|path| {
+            std::fs::read_to_string(path)?
+                .parse::<Anchors>()
+                .with_context(|| format!("parsing {path:?}"))
+        })
+        .transpose()
+        .context("reading --snap-anchors")
+        .failure(Failure::Config)?;
+    let default_path = |path: Option<Option<std::path::PathBuf>>, kind, file_name| {
+        path.map(|path| dirs::or_default(path, kind, file_name))
+            .transpose()
+            .failure(Failure::Config)
+    };
+    let count_cache_path = default_path(args.count_cache.clone(), dirs::Kind::Cache, "counts.tsv")?;
+    let session_file = default_path(args.session_file.clone(), dirs::Kind::State, "session.json")?;
+    let audit_log = default_path(args.audit_log.clone(), dirs::Kind::State, "audit.jsonl")?;
+    // only cache when changes of the library are detected, e.g. across `serve` re-evaluations
+    let count_cache = match (&count_cache_path, &args.library_db) {
+        (Some(path), _) => Some(
+            CountCache::load(path, args.library_db.clone())
+                .context("reading --count-cache")
+                .failure(Failure::Config)?,
+        ),
+        (None, Some(library_db)) => Some(CountCache::new(Some(library_db.clone()))),
+        (None, None) => None,
+    }
+    .map(Arc::new);
+    if args.progress_events {
+        progress::enable();
+    }
+    if let Some(path) = &args.transcript {
+        let transcript = Transcript::create(path)
+            .context("opening --transcript")
+            .failure(Failure::Write)?;
+        TRANSCRIPT.set(transcript).expect("transcript opened once");
+    }
+    if let Some(path) = &args.replay {
+        let replay = std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Replay::parse(&text))
+            .with_context(|| format!("reading --replay {path:?}"))
+            .failure(Failure::Config)?;
+        REPLAY.set(Mutex::new(replay)).expect("replay read once");
+    }
+    let parsed_args = (|| {
+        let timeless_args = args.timeless_args.as_deref().unwrap_or_default();
+        let beet_command = match (&args.beet_command, &args.entries_from) {
+            (Some(beet_command), _) => Some(
+                beet_command::resolve_command_line(beet_command)
+                    .context("invalid --beet-command")?,
+            ),
+            (None, Some(_)) => None,
+            (None, None) => {
+                let beet_command = beet_command::find_beet_command()?;
+                println!("[using beet command {}]", beet_command.display());
+                Some((beet_command, vec![]))
+            }
+        };
+        let read_only = args.read_only
+            || args.output_file.as_deref().is_some_and(|path| {
+                let read_only = on_read_only_filesystem(path);
+                if read_only {
+                    println!(
+                        "[{} is on a read-only filesystem, continuing read-only]",
+                        path.display()
+                    );
+                }
+                read_only
+            });
+        let burn_in_end = args.burn_in.map(|burn_in| burn_in.start(Date::today()));
+        let until = args.until.into_iter().chain(burn_in_end).min();
+        if let (Some(since), Some(until)) = (args.since, until) {
+            if since > until {
+                anyhow::bail!("--since {since} is after {until} (from --until or --burn-in)");
+            }
+        }
+        if args.entries_from.is_some() && (args.since.is_some() || until.is_some()) {
+            anyhow::bail!(
+                "--since, --until, and --burn-in bound the beets queries, not --entries-from"
+            );
+        }
+        let beets = beet_command.map(|(beet_command, command_args)| {
+            BeetCommand::new(beet_command, timeless_args, args.max_entries)
+                .with_command_args(command_args)
+                .with_date_field(args.date_field)
+                .with_extra_fields(args.extra_fields)
+                .with_excluded(args.exclude_args.as_deref().unwrap_or_default())
+                .with_min_rating(args.min_rating)
+                .with_date_bounds(args.since, until)
+                .with_count_cache(count_cache.clone())
+        });
+        #[cfg(feature = "db")]
+        let beets = match (beets, &args.library_db) {
+            (Some(beets), Some(library_db)) if args.direct_db => {
+                let library_db = beet_smart_cutoff::db::LibraryDb::open(library_db)?;
+                Some(beets.with_library_db(Arc::new(library_db)))
+            }
+            (beets, _) => beets,
+        };
+        let entries_input = args
+            .entries_from
+            .map(|path| read_entries_input(&path).with_context(|| format!("reading {path:?}")))
+            .transpose()?;
+        let single_target = args
+            .target_counts
+            .as_ref()
+            .is_some_and(|target_counts| target_counts.len() == 1);
+        if args.candidates.is_some() && !single_target {
+            anyhow::bail!("--candidates requires a single target in --targets");
+        }
+        Ok(ParsedArgs {
             beets,
+            timeless_args,
+            entries_input,
+            lenient: args.lenient,
+            undated: args.undated,
+            strict: args.strict,
+            session_file,
+            resume: args.resume,
+            on_existing: args.on_existing,
+            jobs: args.jobs,
             max_entries: args.max_entries,
-            output_file_key,
-        }
+            max_entries_hard_limit: args.max_entries_hard_limit.max(args.max_entries),
+            adaptive_fetch: args.adaptive_fetch,
+            output_file: args.output_file.map(|path| json::OutputFile {
+                path,
+                array_match: args.output_array_match,
+                comments: args.output_comments,
+            }),
+            #[cfg(feature = "webhook")]
+            webhook: args.webhook_url.map(|url| {
+                let key = args.output_key.clone().unwrap_or_default();
+                WebhookSink::new(url, key)
+                    .with_bearer_token(args.webhook_token)
+                    .with_retries(args.webhook_retries, Duration::from_secs(1))
+            }),
+            #[cfg(feature = "mqtt")]
+            mqtt: args.mqtt_broker.map(|broker| {
+                let key = args.output_key.clone().unwrap_or_default();
+                let topic = args
+                    .mqtt_topic
+                    .unwrap_or_else(|| format!("beet_smart_cutoff/{key}"));
+                let credentials =
+                    args.mqtt_username
+                        .map(|username| beet_smart_cutoff::mqtt::Credentials {
+                            username,
+                            password: args.mqtt_password,
+                        });
+                MqttSink::new(broker, topic, key).with_credentials(credentials)
+            }),
+            output_key: args.output_key,
+            audit_log,
+            metadata_keys: args.metadata_keys,
+            transition_options: TransitionOptions {
+                unit: if args.group_events {
+                    CountUnit::Events
+                } else {
+                    CountUnit::Tracks
+                },
+                snap: match &snap_anchors {
+                    Some(anchors) => Some(anchors),
+                    None => args.snap.as_ref().map(|snap| snap as &dyn Period),
+                },
+                boundary: args.boundary,
+                compilations: args.compilations,
+            },
+            targets: Targets {
+                strategy: args.strategy.or_else(|| {
+                    let target_counts = args.target_counts?;
+                    Some(Targets::custom_strategy(target_counts, args.candidates))
+                }),
+                candidates: args.candidates,
+                last_run: None,
+                compare_with: None,
+                output: None,
+                sparklines: args.sparklines,
+                genre_mix: args.genre_mix,
+                auto_select: args.auto_select,
+            },
+            compare_with: args.compare_with,
+            nesting: args
+                .nesting
+                .map(|nesting| (nesting, args.on_nesting_violation)),
+            window: args.window,
+            max_count: args.max_count,
+            min_count: args.min_count,
+            on_min_count: args.on_min_count,
+            sentinel: args.sentinel_query.map(|query| Sentinel {
+                query: query.lines().map(str::to_owned).collect(),
+                field: args.sentinel_field,
+            }),
+            watch: args
+                .watch
+                .then(|| {
+                    let library_db = args.library_db.context("missing --library-db to watch")?;
+                    anyhow::Ok(Watch {
+                        library_db,
+                        poll_interval: Duration::from_secs(args.poll_interval),
+                    })
+                })
+                .transpose()?,
+            debounce: Duration::from_secs(args.debounce),
+            patch_file: args.patch_file.zip(args.pattern),
+            template: args.template.zip(args.render),
+            emit_script: args.emit_script,
+            composition: args.composition,
+            breakdown: args.breakdown,
+            balance: args.balance,
+            max_per_artist: args.max_per_artist,
+            exclusion_key: args.exclusion_key,
+            modify_assignments: args.modify_assignments,
+            clear_previous: args.clear_previous,
+            dry_run: args.dry_run || read_only,
+            read_only,
+            verbose: args.verbose,
+            ready_notice: ReadyNotice {
+                bell: args.bell,
+                desktop: args.notify_done,
+            },
+            report_file: args.report,
+            summary_file: args.summary_fd.map(open_summary_fd).transpose()?,
+        })
+    })()
+    .failure(Failure::Config)?;
+
+    let result = match command {
+        None if parsed_args.watch.is_some() => watch(parsed_args),
+        None => select_cutoff(parsed_args),
+        Some(Subcommand::ExportShuffled(export_args)) => export_shuffled(parsed_args, export_args),
+        Some(Subcommand::ExportCsv(export_args)) => export_csv(parsed_args, export_args),
+        Some(Subcommand::Show(show_args)) => show(parsed_args, show_args),
+        Some(Subcommand::Simulate(simulate_args)) => simulate(parsed_args, simulate_args),
+        #[cfg(feature = "serve")]
+        Some(Subcommand::Serve(serve_args)) => serve(parsed_args, serve_args),
+        Some(Subcommand::Raw(raw_args)) => raw(parsed_args, raw_args),
+        Some(Subcommand::Count(count_args)) => count(parsed_args, count_args),
+        Some(Subcommand::LintFilters) => lint_filters(parsed_args),
     };
+    if let (Some(path), Some(count_cache)) = (count_cache_path, count_cache) {
+        count_cache
+            .save(&path)
+            .context("writing --count-cache")
+            .failure(Failure::Write)?;
+    }
+    result
+}
+
+/// Offers to set the missing date of the `undated` items from their file modification time
+///
+/// Prints the `beet modify` commands, running them once confirmed. Returns whether the dates
+/// were modified, so the entries need to be fetched again.
+fn offer_undated_repair(
+    beets: &BeetCommand,
+    undated: &[ItemId],
+    dry_run: bool,
+) -> anyhow::Result<bool> {
+    if undated.is_empty() {
+        return Ok(false);
+    }
+    let repairs: Vec<_> = beets
+        .query_mtimes(undated)
+        .context("querying file modification times of undated items")?
+        .into_iter()
+        .filter(|(_, mtime)| !mtime.is_epoch())
+        .collect();
+    if repairs.is_empty() {
+        println!("[undated items have no file modification time to repair their dates]");
+        return Ok(false);
+    }
+    println!("[repair the dates of undated items from their file modification time with:]");
+    for command_line in beets.describe_set_dates(&repairs) {
+        println!("    {command_line}");
+    }
+    let mut prompt = terminal_prompt();
+    let answer = prompt.read_line(&format!("Run the {} commands? [y/N]", repairs.len()))?;
+    if !answer.eq_ignore_ascii_case("y") {
+        return Ok(false);
+    }
+    beets.set_dates(&repairs, dry_run)?;
+    Ok(!dry_run)
+}
+
+fn select_cutoff(parsed_args: ParsedArgs) -> anyhow::Result<()> {
+    let ParsedArgs {
+        beets,
+        timeless_args: _,
+        entries_input,
+        lenient,
+        undated,
+        strict,
+        session_file,
+        resume,
+        on_existing,
+        jobs: _,
+        max_entries,
+        max_entries_hard_limit,
+        adaptive_fetch,
+        output_file,
+        output_key,
+        compare_with,
+        nesting,
+        audit_log,
+        metadata_keys,
+        #[cfg(feature = "webhook")]
+        webhook,
+        #[cfg(feature = "mqtt")]
+        mqtt,
+        transition_options,
+        targets,
+        window,
+        max_count,
+        min_count,
+        on_min_count,
+        sentinel,
+        watch: _,
+        debounce: _,
+        patch_file,
+        template,
+        emit_script,
+        composition,
+        breakdown,
+        balance,
+        max_per_artist,
+        exclusion_key,
+        modify_assignments,
+        clear_previous,
+        dry_run,
+        read_only: _,
+        verbose,
+        ready_notice,
+        report_file,
+        summary_file,
+    } = parsed_args;
+    let output_file_key = output_file_key(output_file, output_key)?;
+    let mut report = RunReport::default();
 
     let subtitle = output_file_key
         .as_ref()
@@ -55,159 +1177,2553 @@ fn main() -> anyhow::Result<()> {
     let json_file_key = if let Some((output_file, output_key)) = output_file_key {
         // fail-fast if file cannot be read
         let json_file = json::read_json_file(output_file).context("reading json file")?;
+        if let Some(map) = &json_file.map {
+            json::check_existing(map, &output_key, on_existing)?;
+        }
         Some((json_file, output_key))
     } else {
         None
     };
+    let compare_with = compare_with
+        .map(|key| {
+            let (json_file, _) = json_file_key
+                .as_ref()
+                .context("missing output_key for --compare-with")?;
+            let Some(map) = &json_file.map else {
+                anyhow::bail!("missing json file {:?}", json_file.path)
+            };
+            let cutoff = read_stored_cutoff_from(map, &key, &json_file.path)?;
+            let earliest = transition_options.boundary.earliest(cutoff);
+            Ok(Comparison { key, earliest })
+        })
+        .transpose()?;
+    let sentinel = match sentinel {
+        Some(sentinel) => {
+            let beets = beets
+                .as_ref()
+                .context("missing beet_command for the sentinel item")?;
+            // fail-fast if the sentinel item is not unique
+            if let Some(previous) = beets.read_sentinel_cutoff(&sentinel)? {
+                println!("[sentinel item has previous cutoff {previous}]");
+            }
+            Some((beets, sentinel))
+        }
+        None => None,
+    };
+    let patch_file = patch_file
+        .map(|(path, pattern)| {
+            // fail-fast if the pattern does not match exactly once
+            PatchFile::read(path.clone(), pattern)
+                .with_context(|| format!("reading patch file {path:?}"))
+        })
+        .transpose()?;
+    let template = template
+        .map(|(template_path, render_path)| {
+            // fail-fast for unknown placeholders
+            let text = std::fs::read_to_string(&template_path)
+                .with_context(|| format!("reading template {template_path:?}"))?;
+            let template = Template::new(text, TemplateSink::PLACEHOLDERS)
+                .with_context(|| format!("parsing template {template_path:?}"))?;
+            anyhow::Ok((template, render_path))
+        })
+        .transpose()?;
+
+    let is_automatic = max_count.is_some() || window.is_some() || targets.auto_select.is_some();
+    // sessions save the fetched entries for interactive runs only
+    let session_file = session_file.filter(|_| !is_automatic);
+    let resumed = match &session_file {
+        Some(path) if resume => {
+            let session = Session::load(path)
+                .with_context(|| format!("loading session file {path:?}"))?
+                .with_context(|| format!("no session to resume in {path:?}"))?;
+            println!(
+                "[resumed {} entries fetched on {}]",
+                session.entries.len(),
+                session.fetched
+            );
+            Some(session)
+        }
+        Some(path) if path.exists() => {
+            println!(
+                "[found an interrupted session in {path:?}, use --resume to skip re-querying]"
+            );
+            None
+        }
+        _ => None,
+    };
 
-    let entries = beets.query_timeless().context("query current items")?;
+    // automatic runs require all entries up to the limit
+    let adaptive_limit = (adaptive_fetch && !is_automatic).then_some(max_entries);
+    let mut fetch_limits = FetchLimits {
+        max_entries: match (adaptive_limit, targets.largest_count()) {
+            (Some(limit), Some(largest_target)) => largest_target.saturating_mul(2).min(limit),
+            _ => max_entries,
+        },
+        hard_limit: max_entries_hard_limit,
+        lenient,
+        undated,
+        adaptive_limit,
+    };
+    let source = EntrySource::new(&entries_input, &beets)?;
+    let mut entries = match resumed {
+        Some(Session {
+            max_entries,
+            entries,
+            ..
+        }) => {
+            fetch_limits.max_entries = max_entries;
+            entries
+        }
+        None => {
+            let entries = fetch_limits
+                .fetch(&source, &mut report)
+                .context("query current items")?;
+            let entries = match &source {
+                EntrySource::Beets(beets)
+                    if !is_automatic
+                        && offer_undated_repair(beets, &report.undated_ids, dry_run)? =>
+                {
+                    // fetch again, for the repaired dates
+                    fetch_limits
+                        .fetch(&source, &mut report)
+                        .context("query current items")?
+                }
+                _ => entries,
+            };
+            let session = Session {
+                fetched: Date::today(),
+                max_entries: fetch_limits.max_entries,
+                entries,
+            };
+            if let Some(path) = &session_file {
+                session
+                    .save(path)
+                    .with_context(|| format!("saving session file {path:?}"))?;
+            }
+            session.entries
+        }
+    };
 
-    let date_entry = select_end(&entries, max_entries)?;
+    let mut last_run = None;
+    // whether entries beyond those fetched may have moved the cutoff
+    let mut fetch_limited = false;
+    let (mut cutoff, mut choice) = if max_count.is_some() {
+        let constraints = Constraints {
+            max_count,
+            not_before: window.map(|window| window.start(Date::today())),
+        };
+        let solution = constraint::solve(&entries, constraints, transition_options)?;
+        let constraint::Solution {
+            transition,
+            count,
+            limited_by,
+        } = solution;
+        println!("{transition}");
+        let unit = transition_options.unit.name();
+        fetch_limited = limited_by.is_none();
+        match limited_by {
+            Some(violation) => {
+                println!(
+                    "[{} {unit}, limited by the next breakpoint: {violation}]",
+                    Grouped(count)
+                )
+            }
+            None => println!(
+                "[{} {unit}, limited by the fetched entries]",
+                Grouped(count)
+            ),
+        }
+        let choice = format!(
+            "constraints, cutoff {}",
+            transition_options.boundary.describe(transition.cutoff)
+        );
+        (transition.cutoff, choice)
+    } else if let Some(window) = window {
+        let cutoff = window.start(Date::today());
+        let fetched_count = entries
+            .iter()
+            .take_while(|entry| entry.date() >= cutoff)
+            .count();
+        if fetched_count == 0 {
+            println!("[warning: no entries within the {window} window, after {cutoff}]");
+        } else if fetched_count == entries.len() {
+            fetch_limited = true;
+            println!(
+                "[all {} fetched entries are within the {window} window]",
+                Grouped(fetched_count)
+            );
+        }
+        (
+            cutoff,
+            format!(
+                "window {window}, cutoff {}",
+                transition_options.boundary.describe(cutoff)
+            ),
+        )
+    } else {
+        let stored_last_run = json_file_key
+            .as_ref()
+            .and_then(|(json_file, key)| json::read_last_run(json_file.map.as_ref()?, key));
+        let output = json_file_key.as_ref().map(|(json_file, key)| OutputValue {
+            path: json_file.path.clone(),
+            key: key.clone(),
+            old: json_file
+                .map
+                .as_ref()
+                .and_then(|map| json::latest_value(map, key))
+                .cloned(),
+        });
+        let selection = select_end(
+            &mut entries,
+            &mut fetch_limits,
+            &source,
+            &mut report,
+            transition_options,
+            Targets {
+                last_run: stored_last_run,
+                compare_with,
+                output,
+                ..targets
+            },
+            ready_notice,
+        )?;
+        let Some((transition, selected_last_run)) = selection else {
+            if let Some(path) = &session_file {
+                println!("[session saved in {path:?}, use --resume to continue]");
+            }
+            let summary = RunSummary {
+                dry_run,
+                ..RunSummary::default()
+            };
+            finish_selection(&report, verbose, report_file, summary_file, summary)?;
+            record_transcript(transcript::Event::Quit);
+            return Err(anyhow::anyhow!("no cutoff selected")).failure(Failure::Quit);
+        };
+        let choice = format!(
+            "choice {} ({} {})",
+            selected_last_run.choice, transition.included.timestamp, transition.included
+        );
+        last_run = Some(selected_last_run);
+        (transition.cutoff, choice)
+    };
 
-    let Some(date_entry) = date_entry else {
-        return Ok(());
+    let mut final_count = source.count_after(cutoff, &entries, &mut report)?;
+    if let Some(min_count) = min_count.filter(|&min_count| final_count < min_count) {
+        let shortfall = format!(
+            "{} entries is fewer than the minimum {}",
+            Grouped(final_count),
+            Grouped(min_count)
+        );
+        match (is_automatic, on_min_count) {
+            (false, _) | (true, MinCountAction::Warn) => println!("[warning: {shortfall}]"),
+            (true, MinCountAction::Error) => {
+                let cutoff = transition_options.boundary.describe(cutoff);
+                anyhow::bail!("{shortfall}, refusing cutoff {cutoff}")
+            }
+            (true, MinCountAction::Widen) => {
+                println!("[{shortfall}, widening]");
+                let constraints = Constraints {
+                    max_count,
+                    not_before: None,
+                };
+                let solution =
+                    constraint::widen(&entries, min_count, constraints, transition_options)?;
+                let transition = solution.transition;
+                println!("{transition}");
+                cutoff = transition.cutoff;
+                choice = format!(
+                    "widened to the minimum count, cutoff {}",
+                    transition_options.boundary.describe(cutoff)
+                );
+                final_count = source.count_after(cutoff, &entries, &mut report)?;
+            }
+        }
+    }
+    println!("Final {} entries, from {choice}", Grouped(final_count));
+    if let EntrySource::Beets(beets) = &source {
+        print_below_min_rating(beets, cutoff)?;
+    }
+    record_transcript(transcript::Event::Decision {
+        cutoff: transition_options.boundary.cutoff(cutoff),
+        count: final_count,
+        choice: choice.clone(),
+    });
+    if let Some(replay) = lock_replay() {
+        replay
+            .check_decision(transition_options.boundary.cutoff(cutoff), final_count)
+            .failure(Failure::Changed)?;
+    }
+    if breakdown {
+        print_breakdown(&entries, cutoff);
+    }
+    if strict {
+        check_strict(&report, fetch_limited, fetch_limits.max_entries)?;
+    }
+    if composition {
+        let beets = beets
+            .as_ref()
+            .context("missing beet_command for the filter set composition")?;
+        print_composition(beets, cutoff, &entries, &mut report)?;
+    }
+    let balanced = match balance {
+        Some(min_count) => {
+            let beets = beets
+                .as_ref()
+                .context("missing beet_command to balance the filter sets")?;
+            balance_filter_sets(beets, cutoff, min_count, transition_options, &mut report)?
+        }
+        None => vec![],
     };
+    if let Some(path) = &session_file {
+        Session::remove(path).with_context(|| format!("removing session file {path:?}"))?;
+    }
+    // NOTE: queries below use the earliest included date, outputs use the boundary semantics
+    let stored_cutoff = transition_options.boundary.cutoff(cutoff);
 
-    let final_count = beets
-        .count_entries_after(date_entry)
-        .context("counting entries with chosen date bound")?;
-    // FIXME debug format is tacky
-    println!("Final {final_count} entries, from choice {date_entry:?}");
+    let exclusion = max_per_artist
+        .map(|max_per_artist| artist_exclusion(&entries, cutoff, max_per_artist))
+        .transpose()?;
 
+    let mut sinks = Sinks::default();
     if let Some((json_file, key)) = json_file_key {
-        let json::JsonFile { map, path } = json_file;
-        let path = &path;
-        let mut map = map.unwrap_or_default();
+        let balanced = balanced
+            .into_iter()
+            .map(|(number, filter_set_cutoff)| (format!("{key}.{number}"), filter_set_cutoff))
+            .collect();
+        let exclusion = exclusion
+            .map(|query| {
+                let exclusion_key = exclusion_key.unwrap_or_else(|| format!("{key}.exclude"));
+                (exclusion_key, query)
+            })
+            .into_iter()
+            .collect();
+        sinks.push(
+            JsonFileSink::new(json_file, key, on_existing)
+                .with_last_run(last_run)
+                .with_extra(balanced)
+                .with_text(exclusion)
+                .with_metadata(metadata_keys)
+                .with_nesting(nesting)
+                .with_audit_log(audit_log)
+                .with_write_lock(std::env::var_os(CONCURRENT_ENV).is_some()),
+        );
+    }
+    if let Some(patch_file) = patch_file {
+        sinks.push(PatchFileSink::new(patch_file));
+    }
+    if let Some((template, path)) = template {
+        sinks.push(TemplateSink::new(template, path));
+    }
+    if let Some(path) = emit_script {
+        sinks.push(ScriptSink::new(path));
+    }
+    #[cfg(feature = "webhook")]
+    if let Some(webhook) = webhook {
+        sinks.push(webhook);
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = mqtt {
+        sinks.push(mqtt);
+    }
+    let output = CutoffOutput {
+        earliest: cutoff,
+        boundary: transition_options.boundary,
+        count: final_count,
+        unit: transition_options.unit,
+        choice: &choice,
+        query: beets
+            .as_ref()
+            .map(|beets| beets.describe_list_after(cutoff)),
+    };
+    let files = sinks
+        .prepare(&output)?
+        .write(dry_run)
+        .failure(Failure::Write)?;
 
-        map.insert(key, date_entry.date.clone().into());
-        json::write_json_file(path, map).with_context(|| format!("writing json file {path:?}"))?;
+    if let Some((beets, sentinel)) = &sentinel {
+        beets
+            .write_sentinel_cutoff(sentinel, stored_cutoff, dry_run)
+            .context("storing cutoff on the sentinel item")?;
     }
 
-    Ok(())
-}
+    if !modify_assignments.is_empty() {
+        let beets = beets
+            .as_ref()
+            .context("missing beet_command for modify assignments")?;
+        if clear_previous {
+            beets
+                .clear_entries_before(cutoff, &modify_assignments, dry_run)
+                .context("clearing modified values before chosen date bound")?;
+        }
+        beets
+            .modify_entries_after(cutoff, &modify_assignments, dry_run)
+            .context("modifying entries with chosen date bound")?;
+    }
 
-struct ParsedArgs<'a> {
-    beets: BeetCommand<'a>,
-    max_entries: usize,
-    output_file_key: Option<(std::path::PathBuf, String)>,
+    let summary = RunSummary {
+        cutoff: Some(stored_cutoff),
+        count: Some(final_count),
+        choice: Some(choice),
+        files,
+        dry_run,
+    };
+    finish_selection(&report, verbose, report_file, summary_file, summary)
 }
 
-fn select_end(entries: &[DateEntry], max_entries: usize) -> anyhow::Result<Option<&DateEntry>> {
-    const TARGET_COUNTS: &[usize] = &[30, 50, 70];
+/// Prints the fetched `entries` on or after the `cutoff` per artist and album
+fn print_breakdown(entries: &[DateEntry], cutoff: Date) {
+    let included = entries.partition_point(|entry| entry.date() >= cutoff);
+    let mut table = Table::new([
+        ("artist", Align::Left),
+        ("album", Align::Left),
+        ("tracks", Align::Right),
+    ]);
+    for artist::ArtistAlbums {
+        artist,
+        count,
+        albums,
+    } in artist::album_breakdown(&entries[..included])
+    {
+        table.push_row([artist, String::new(), Grouped(count).to_string()]);
+        for (album, count) in albums {
+            let album = if album.is_empty() {
+                "(singles)".to_owned()
+            } else {
+                album
+            };
+            table.push_row([String::new(), album, Grouped(count).to_string()]);
+        }
+    }
+    print!("{table}");
+}
 
-    let mut target_counts = TARGET_COUNTS.to_vec();
-    loop {
-        let mut prev_index = None;
-        let mut choice_index = 1;
-        let transitions: Vec<_> = target_counts
+/// Returns the query excluding the tracks after the `cutoff` beyond the `max_per_artist` newest of
+/// each artist, printing the artists over the cap
+fn artist_exclusion(
+    entries: &[DateEntry],
+    cutoff: Date,
+    max_per_artist: NonZeroUsize,
+) -> anyhow::Result<String> {
+    let surplus = constraint::artist_surplus(entries, cutoff, max_per_artist.get());
+    let ids = surplus
+        .iter()
+        .map(|entry| entry.id)
+        .collect::<Option<Vec<_>>>()
+        .context(
+            "excluding the surplus tracks per artist requires item ids (fetched from beets)",
+        )?;
+    let mut artists: Vec<(String, usize)> = vec![];
+    for entry in &surplus {
+        let artist = artist::primary(&entry.artist);
+        match artists
+            .iter_mut()
+            .find(|(existing, _)| artist::normalize(existing) == artist::normalize(artist))
+        {
+            Some((_, count)) => *count += 1,
+            None => artists.push((artist.to_owned(), 1)),
+        }
+    }
+    if artists.is_empty() {
+        println!("[no artist over the cap of {max_per_artist} tracks]");
+    } else {
+        let artists: Vec<_> = artists
             .iter()
-            .cloned()
-            .filter_map(|target_count| {
-                if prev_index.is_some_and(|prev_index| prev_index >= target_count) {
-                    println!("[skipping target: {target_count}]");
-                    None
-                } else {
-                    let transition = find_transition(entries, target_count);
-                    if let Some(transition) = transition {
-                        println!("[#{choice_index}] Breakpoint for {target_count}:");
-                        choice_index += 1;
+            .map(|(artist, count)| format!("{artist} (-{count})"))
+            .collect();
+        println!(
+            "[excluding {} surplus tracks over the cap of {max_per_artist} per artist: {}]",
+            Grouped(surplus.len()),
+            artists.join(", ")
+        );
+    }
+    let query = beet_command::exclude_ids_query(ids);
+    println!("Exclusion query: {query}");
+    Ok(query)
+}
 
-                        println!("{transition}");
+/// Fails if incomplete input may have affected the cutoff (for `--strict`)
+fn check_strict(report: &RunReport, fetch_limited: bool, max_entries: usize) -> anyhow::Result<()> {
+    if let Some(first) = report.parse_failures.first() {
+        anyhow::bail!(
+            "strict: skipped {} lines that failed to parse, first on line {}: {}",
+            report.parse_failures.len(),
+            first.line_number,
+            first.error
+        );
+    }
+    if report.truncated && fetch_limited {
+        anyhow::bail!(
+            "strict: the cutoff depends on entries beyond the {} fetched, increase --max-entries",
+            Grouped(max_entries)
+        );
+    }
+    if let Some(mismatch) = &report.count_mismatch {
+        anyhow::bail!("strict: {mismatch}");
+    }
+    Ok(())
+}
 
-                        prev_index = Some(transition.index);
-                        Some(transition)
-                    } else {
-                        println!("[out of range: {target_count}]");
-                        None
-                    }
-                }
-            })
-            .collect();
+/// Prints the top artists of the fetched `entries` on or after the `cutoff`, then the count of
+/// items matched by each filter set, with the compilation items among the `entries`
+fn print_composition(
+    beets: &BeetCommand<'_>,
+    cutoff: Date,
+    entries: &[DateEntry],
+    report: &mut RunReport,
+) -> anyhow::Result<()> {
+    const TOP_ARTISTS: usize = 10;
 
-        match prompt_user_selection(&transitions, max_entries)? {
-            Some(UserSelection::NewCounts(new_counts)) => {
-                target_counts = new_counts;
-            }
-            Some(UserSelection::Entry(entry)) => return Ok(Some(entry)),
-            None => return Ok(None),
+    let included = entries.partition_point(|entry| entry.date() >= cutoff);
+    let included = &entries[..included];
+    let mut artists = Table::new([
+        ("artist", Align::Left),
+        ("items", Align::Right),
+        ("share", Align::Right),
+    ]);
+    for (artist, count) in artist::artist_counts(included)
+        .into_iter()
+        .take(TOP_ARTISTS)
+    {
+        let share = count as f64 / included.len() as f64 * 100.0;
+        artists.push_row([artist, Grouped(count).to_string(), format!("{share:.0}%")]);
+    }
+    if !artists.is_empty() {
+        print!("{artists}");
+    }
+    let filter_sets = report
+        .timings
+        .record("composition", || beets.query_filter_set_items_after(cutoff))
+        .context("querying items of each filter set")?;
+    if filter_sets.is_empty() {
+        println!("[single filter set, no composition to show]");
+        return Ok(());
+    }
+    let (shares, total) = beet_command::filter_set_composition(&filter_sets);
+    let compilation_ids: std::collections::HashSet<ItemId> = entries
+        .iter()
+        .filter(|entry| entry.compilation)
+        .filter_map(|entry| entry.id)
+        .collect();
+    let mut columns = vec![
+        ("filter set", Align::Left),
+        ("items", Align::Right),
+        ("share", Align::Right),
+        ("only this set", Align::Right),
+    ];
+    if !compilation_ids.is_empty() {
+        columns.push(("compilations", Align::Right));
+    }
+    let mut table = Table::new(columns);
+    for (
+        FilterSetShare {
+            label,
+            count,
+            exclusive,
+        },
+        filter_set,
+    ) in shares.into_iter().zip(&filter_sets)
+    {
+        let share = count as f64 / total.max(1) as f64 * 100.0;
+        let mut cells = vec![
+            label,
+            Grouped(count).to_string(),
+            format!("{share:.0}%"),
+            Grouped(exclusive).to_string(),
+        ];
+        if !compilation_ids.is_empty() {
+            let compilations = filter_set
+                .ids
+                .iter()
+                .filter(|id| compilation_ids.contains(id))
+                .count();
+            cells.push(Grouped(compilations).to_string());
         }
+        table.push_row(cells);
     }
+    print!("{table}");
+    Ok(())
 }
 
-enum UserSelection<'a> {
-    Entry(&'a DateEntry),
-    NewCounts(Vec<usize>),
+/// Finds a cutoff for each filter set including at least `min_count` of its entries, keeping the
+/// chosen `cutoff` where possible
+///
+/// Returns the number of each filter set (from 1) with its cutoff.
+fn balance_filter_sets(
+    beets: &BeetCommand<'_>,
+    cutoff: Date,
+    min_count: usize,
+    transition_options: TransitionOptions,
+    report: &mut RunReport,
+) -> anyhow::Result<Vec<(usize, Date)>> {
+    let filter_sets = beets.split_filter_sets();
+    if filter_sets.len() < 2 {
+        println!("[single filter set, nothing to balance]");
+        return Ok(vec![]);
+    }
+    let mut table = Table::new([
+        ("#", Align::Right),
+        ("filter set", Align::Left),
+        ("cutoff", Align::Left),
+    ]);
+    let mut balanced = vec![];
+    for (index, filter_set) in filter_sets.iter().enumerate() {
+        let number = index + 1;
+        let label = filter_set.filter_label();
+        let entries = report
+            .timings
+            .record("balance", || filter_set.query_timeless())
+            .with_context(|| format!("query items of filter set {label:?}"))?;
+        let filter_set_cutoff =
+            constraint::balance_cutoff(&entries, cutoff, min_count, transition_options)
+                .unwrap_or_else(|| {
+                    println!(
+                        "[warning: fewer than {} entries fetched for filter set {label:?}, including all {}]",
+                        Grouped(min_count),
+                        Grouped(entries.len())
+                    );
+                    entries.last().map_or(cutoff, |oldest| oldest.date().min(cutoff))
+                });
+        table.push_row([
+            number.to_string(),
+            label,
+            transition_options.boundary.describe(filter_set_cutoff),
+        ]);
+        balanced.push((number, filter_set_cutoff));
+    }
+    print!("{table}");
+    Ok(balanced)
 }
-fn prompt_user_selection<'a>(
-    transitions: &[Transition<'a>],
-    max_entries: usize,
-) -> anyhow::Result<Option<UserSelection<'a>>> {
-    let mut prompt = Prompt::default();
-    loop {
-        let input = prompt.read_line(Command::PROMPT)?;
-
-        match Command::from_str(input)? {
-            Command::Quit => return Ok(None),
-            Command::Custom => {
-                let target_str =
-                    prompt.read_line("Enter custom target numbers (space separated):")?;
-                match target_str
-                    .split_whitespace()
-                    .map(|token| {
-                        let number = token.parse()?;
-                        if number > max_entries {
-                            anyhow::bail!("{number} exceeds max_entries ({max_entries}) command-line argument")
-                        } else {
-                            Ok(number)
-                        }
-                    })
-                    .collect()
-                {
-                    Ok(new_counts) => {
-                        return Ok(Some(UserSelection::NewCounts(new_counts)));
-                    }
-                    Err(err) => {
-                        println!("invalid custom input {target_str:?}: {err}");
-                    }
+
+/// Finishes the report, and writes the `summary` (including the report file) if requested
+fn finish_selection(
+    report: &RunReport,
+    verbose: bool,
+    report_file: Option<std::path::PathBuf>,
+    summary_file: Option<std::fs::File>,
+    mut summary: RunSummary,
+) -> anyhow::Result<()> {
+    summary.files.extend(report_file.clone());
+    finish_report(report, verbose, report_file)?;
+    if let Some(summary_file) = summary_file {
+        summary
+            .write_to(summary_file)
+            .context("writing summary to --summary-fd")?;
+    }
+    Ok(())
+}
+
+/// Options for re-evaluating the cutoff when the library changes
+struct Watch {
+    library_db: std::path::PathBuf,
+    poll_interval: Duration,
+}
+
+fn watch(mut parsed_args: ParsedArgs) -> anyhow::Result<()> {
+    let Watch {
+        library_db,
+        poll_interval,
+    } = parsed_args.watch.take().context("missing watch options")?;
+    let mut debouncer = Debouncer::new(parsed_args.debounce);
+    let evaluation = WatchEvaluation::new(parsed_args)?;
+    let unit = evaluation.transition_options.unit.name();
+
+    install_shutdown_handler();
+    let mut watcher = FileWatcher::new(library_db);
+    println!(
+        "[watching {:?}, checking every {}s]",
+        watcher.path(),
+        poll_interval.as_secs()
+    );
+    let mut ready = false;
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        let changed = watcher
+            .poll()
+            .with_context(|| format!("checking library database {:?}", watcher.path()))?;
+        // evaluate immediately on start, then after changes stop
+        if changed && ready {
+            debouncer.trigger(Instant::now());
+        }
+        if !ready || debouncer.take_ready(Instant::now()) {
+            // keep watching after a failed evaluation (e.g. beets busy importing)
+            let status = match evaluation.evaluate() {
+                Ok(WatchStatus {
+                    cutoff,
+                    count,
+                    updated,
+                }) => {
+                    let cutoff_state = if updated { "updated cutoff" } else { "cutoff" };
+                    format!("STATUS={cutoff_state} {cutoff}, {} {unit}", Grouped(count))
                 }
+                Err(err) => {
+                    println!("[evaluation failed: {err:#}]");
+                    format!("STATUS=evaluation failed: {err}")
+                }
+            };
+            let ready_state = if ready { "" } else { "READY=1\n" };
+            notify_service(&format!("{ready_state}{status}"));
+            ready = true;
+        }
+        sleep_until_shutdown(poll_interval);
+    }
+    evaluation.shut_down(debouncer.is_pending())
+}
+
+#[cfg(unix)]
+type WatchRunner = beet_command::ProcessGroupRunner;
+#[cfg(not(unix))]
+type WatchRunner = beet_command::SystemRunner;
+
+#[cfg(feature = "serve")]
+fn serve(parsed_args: ParsedArgs, serve_args: ServeArgs) -> anyhow::Result<()> {
+    use beet_smart_cutoff::http;
+    use std::io::BufReader;
+
+    let ServeArgs { listen } = serve_args;
+    let debounce = parsed_args.debounce;
+    let mut debouncer = Debouncer::new(debounce);
+    let evaluation = WatchEvaluation::new(parsed_args)?;
+    let evaluate = || match evaluation.evaluate() {
+        Ok(status) => status.to_json(evaluation.transition_options.unit),
+        Err(err) => {
+            println!("[evaluation failed: {err:#}]");
+            serde_json::json!({ "error": format!("{err:#}") })
+        }
+    };
+
+    install_shutdown_handler();
+    let listener =
+        std::net::TcpListener::bind(listen).with_context(|| format!("listening on {listen}"))?;
+    // poll for connections, to check for shutdown
+    listener.set_nonblocking(true)?;
+    let mut status = evaluate();
+    println!("[listening on {listen}]");
+    notify_service("READY=1");
+
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        if debouncer.take_ready(Instant::now()) {
+            status = evaluate();
+        }
+        let (stream, peer) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                sleep_until_shutdown(Duration::from_millis(100));
+                continue;
             }
-            Command::Number(number) => {
-                let index = number.get() - 1;
-                if let Some(Transition { included, .. }) = transitions.get(index) {
-                    return Ok(Some(UserSelection::Entry(included)));
-                } else {
-                    println!("invalid number {number}");
+            Err(err) => Err(err).context("accepting connection")?,
+        };
+        let mut respond = || {
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            let request = http::read_request(&mut BufReader::new(&stream))?;
+            println!("[{peer} {} {}]", request.method, request.path);
+            let (code, reason, body) = match (request.method.as_str(), request.path.as_str()) {
+                ("GET", "/status") => (200, "OK", status.clone()),
+                ("POST", "/reevaluate") if debounce.is_zero() => {
+                    status = evaluate();
+                    (200, "OK", status.clone())
                 }
+                ("POST", "/reevaluate") => {
+                    debouncer.trigger(Instant::now());
+                    let body = serde_json::json!({
+                        "scheduled_after_secs": debounce.as_secs(),
+                        "status": status,
+                    });
+                    (202, "Accepted", body)
+                }
+                (_, "/status" | "/reevaluate") => (
+                    405,
+                    "Method Not Allowed",
+                    serde_json::json!({ "error": "method not allowed" }),
+                ),
+                _ => (
+                    404,
+                    "Not Found",
+                    serde_json::json!({ "error": "not found" }),
+                ),
+            };
+            http::write_response(&mut &stream, code, reason, &body.to_string())?;
+            anyhow::Ok(())
+        };
+        if let Err(err) = respond() {
+            println!("[request from {peer} failed: {err:#}]");
+        }
+    }
+    evaluation.shut_down(debouncer.is_pending())
+}
+
+/// Set by SIGTERM or SIGINT, to stop watching (after any in-progress evaluation)
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+fn install_shutdown_handler() {
+    #[cfg(unix)]
+    {
+        extern "C" fn request_shutdown(signal: libc::c_int) {
+            if SHUTDOWN.swap(true, Ordering::SeqCst) {
+                // repeated signal, e.g. impatient Ctrl-C (writes are atomic, via a rename)
+                // SAFETY: `_exit` is async-signal-safe
+                unsafe { libc::_exit(128 + signal) }
             }
-            Command::Empty => {}
         }
+        let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // SAFETY: the handler only uses an atomic and `_exit`, which are async-signal-safe
+        unsafe {
+            libc::signal(libc::SIGTERM, handler);
+            libc::signal(libc::SIGINT, handler);
+        }
+    }
+}
+
+/// Sleeps for the `duration`, waking early on shutdown
+fn sleep_until_shutdown(duration: Duration) {
+    const STEP: Duration = Duration::from_millis(100);
+    let start = Instant::now();
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        let Some(remaining) = duration.checked_sub(start.elapsed()) else {
+            break;
+        };
+        std::thread::sleep(remaining.min(STEP));
     }
 }
 
-enum Command {
-    Quit,
-    Custom,
-    Number(NonZeroUsize),
-    Empty,
+/// Notification that the interactive candidates are ready, for users waiting elsewhere
+#[derive(Clone, Copy, Debug, Default)]
+struct ReadyNotice {
+    /// Ring the terminal bell
+    bell: bool,
+    /// Send a desktop notification
+    desktop: bool,
 }
-impl Command {
-    const PROMPT: &'static str = "Enter selection [#/Custom/Quit]:";
+impl ReadyNotice {
+    fn send(self, body: &str) {
+        let Self { bell, desktop } = self;
+        if bell {
+            print!("\x07");
+            let _ = std::io::Write::flush(&mut std::io::stdout());
+        }
+        if desktop {
+            let status = std::process::Command::new("notify-send")
+                .args(["--app-name=beet_smart_cutoff", "beet_smart_cutoff", body])
+                .stdin(std::process::Stdio::null())
+                .status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => println!("[desktop notification failed: notify-send {status}]"),
+                Err(err) => println!("[desktop notification failed: notify-send: {err}]"),
+            }
+        }
+    }
 }
-impl FromStr for Command {
-    type Err = anyhow::Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let result = match s.to_lowercase().as_str() {
-            "q" | "quit" | "exit" => Self::Quit,
-            "c" | "custom" => Self::Custom,
-            "" => Self::Empty,
-            input => {
-                if let Ok(number) = input.parse() {
-                    Self::Number(number)
-                } else {
-                    anyhow::bail!("unrecognized command {input:?}")
-                }
+
+/// Transcript of the interactive session, when recording with --transcript
+static TRANSCRIPT: std::sync::OnceLock<Transcript> = std::sync::OnceLock::new();
+
+/// Records the `event` in the --transcript (if any)
+fn record_transcript(event: transcript::Event) {
+    if let Some(transcript) = TRANSCRIPT.get() {
+        if let Err(err) = transcript.record(&event) {
+            println!("[failed to record the transcript: {err:#}]");
+        }
+    }
+}
+
+/// Session replayed with --replay
+static REPLAY: std::sync::OnceLock<Mutex<Replay>> = std::sync::OnceLock::new();
+
+fn lock_replay() -> Option<std::sync::MutexGuard<'static, Replay>> {
+    let replay = REPLAY.get()?;
+    Some(
+        replay
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()),
+    )
+}
+
+/// Responses of the --replay session, read in place of the terminal
+#[derive(Default)]
+struct ReplayInput {
+    /// Remainder of the current response line
+    pending: Vec<u8>,
+}
+impl std::io::Read for ReplayInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pending.is_empty() {
+            let Some((_, response)) = lock_replay().and_then(|mut replay| replay.next_response())
+            else {
+                println!("[replay: no recorded responses left]");
+                return Ok(0);
+            };
+            println!("{response} [replayed]");
+            self.pending = format!("{response}\n").into_bytes();
+        }
+        let length = buf.len().min(self.pending.len());
+        buf[..length].copy_from_slice(&self.pending[..length]);
+        self.pending.drain(..length);
+        Ok(length)
+    }
+}
+
+/// Prompt on the terminal (or replaying the --replay session), recording each response in the
+/// --transcript (if any)
+fn terminal_prompt() -> Prompt {
+    let prompt = if REPLAY.get().is_some() {
+        Prompt::with_input(std::io::BufReader::new(ReplayInput::default()))
+    } else {
+        Prompt::default()
+    };
+    if TRANSCRIPT.get().is_none() {
+        return prompt;
+    }
+    prompt.with_response_hook(|prompt, response| {
+        record_transcript(transcript::Event::Response {
+            prompt: prompt.to_owned(),
+            response: response.to_owned(),
+        });
+    })
+}
+
+/// Sends the state to systemd, when running as a `Type=notify` service
+fn notify_service(state: &str) {
+    #[cfg(unix)]
+    {
+        use beet_smart_cutoff::notify::Notifier;
+        use std::sync::OnceLock;
+        static NOTIFIER: OnceLock<Option<Notifier>> = OnceLock::new();
+        let notifier = NOTIFIER.get_or_init(|| match Notifier::from_env()? {
+            Ok(notifier) => Some(notifier),
+            Err(err) => {
+                println!("[failed to connect to NOTIFY_SOCKET: {err}]");
+                None
+            }
+        });
+        if let Some(notifier) = notifier {
+            if let Err(err) = notifier.notify(state) {
+                println!("[failed to notify systemd: {err}]");
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// Result of a [`WatchEvaluation`]
+struct WatchStatus {
+    /// Stored cutoff, with the --boundary semantics
+    cutoff: Date,
+    /// Count of units after the cutoff
+    count: usize,
+    /// Whether the stored cutoff was updated
+    updated: bool,
+}
+impl WatchStatus {
+    #[cfg(feature = "serve")]
+    fn to_json(&self, unit: CountUnit) -> serde_json::Value {
+        let Self {
+            cutoff,
+            count,
+            updated,
+        } = self;
+        serde_json::json!({
+            "cutoff": cutoff.to_string(),
+            "count": count,
+            "unit": unit.name(),
+            "updated": updated,
+        })
+    }
+}
+
+/// Non-interactive evaluation of the --max-count constraint for the stored cutoff
+struct WatchEvaluation<'a> {
+    /// Queries unaffected by a terminal interrupt, to finish an in-progress evaluation
+    beets: BeetCommand<'a, WatchRunner>,
+    output_file: json::OutputFile,
+    output_key: String,
+    audit_log: Option<std::path::PathBuf>,
+    metadata_keys: bool,
+    #[cfg(feature = "webhook")]
+    webhook: Option<WebhookSink>,
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<MqttSink>,
+    max_count: usize,
+    window: Option<Window>,
+    on_existing: OnExisting,
+    nesting: Option<(json::Nesting, json::OnNestingViolation)>,
+    transition_options: TransitionOptions<'a>,
+    max_entries: usize,
+    lenient: bool,
+    undated: UndatedAction,
+    dry_run: bool,
+}
+impl<'a> WatchEvaluation<'a> {
+    fn new(parsed_args: ParsedArgs<'a>) -> anyhow::Result<Self> {
+        let ParsedArgs {
+            beets,
+            lenient,
+            undated,
+            on_existing,
+            max_entries,
+            output_file,
+            output_key,
+            audit_log,
+            metadata_keys,
+            #[cfg(feature = "webhook")]
+            webhook,
+            #[cfg(feature = "mqtt")]
+            mqtt,
+            nesting,
+            transition_options,
+            window,
+            max_count,
+            dry_run,
+            ..
+        } = parsed_args;
+        let (output_file, output_key) = output_file_key(output_file, output_key)?
+            .context("missing output_file and output_key to update")?;
+        Ok(Self {
+            beets: beets
+                .context("missing beet_command to evaluate")?
+                .with_runner(WatchRunner::default()),
+            output_file,
+            output_key,
+            audit_log,
+            metadata_keys,
+            #[cfg(feature = "webhook")]
+            webhook,
+            #[cfg(feature = "mqtt")]
+            mqtt,
+            max_count: max_count.context("missing --max-count to evaluate")?,
+            window,
+            on_existing,
+            nesting,
+            transition_options,
+            max_entries,
+            lenient,
+            undated,
+            dry_run,
+        })
+    }
+    /// Records the shutdown in the audit log (if any), after discarding any `pending`
+    /// re-evaluation (which runs on the next start)
+    fn shut_down(&self, pending: bool) -> anyhow::Result<()> {
+        notify_service("STOPPING=1");
+        if pending {
+            println!("[discarding the pending re-evaluation, evaluated on the next start]");
+        }
+        println!("[shutting down]");
+        match &self.audit_log {
+            Some(audit_log) if !self.dry_run => audit::append_event(audit_log, "shutdown")
+                .with_context(|| format!("appending to audit log {audit_log:?}")),
+            _ => Ok(()),
+        }
+    }
+    /// Updates the stored cutoff if it violates the constraints
+    fn evaluate(&self) -> anyhow::Result<WatchStatus> {
+        let Self {
+            beets,
+            output_file,
+            output_key,
+            audit_log,
+            metadata_keys,
+            #[cfg(feature = "webhook")]
+            webhook,
+            #[cfg(feature = "mqtt")]
+            mqtt,
+            max_count,
+            window,
+            on_existing,
+            nesting,
+            transition_options,
+            max_entries,
+            lenient,
+            undated,
+            dry_run,
+        } = self;
+        let json_file = json::read_json_file(output_file.clone()).context("reading json file")?;
+        let stored = json_file
+            .map
+            .as_ref()
+            .and_then(|map| map.get(output_key))
+            .and_then(stored_cutoff)
+            .map(str::parse::<Date>)
+            .transpose()
+            .with_context(|| format!("stored cutoff for key {output_key:?}"))?
+            .map(|stored| transition_options.boundary.earliest(stored));
+
+        let output = beets.fetch_timeless().context("query current items")?;
+        let parsed = parse_timeless(&output, *max_entries, *lenient)
+            .failure(Failure::Parse)
+            .context("query current items")?;
+        let mut entries = parsed.entries;
+        undated.apply(&mut entries);
+        let constraints = Constraints {
+            max_count: Some(*max_count),
+            not_before: window.map(|window| window.start(Date::today())),
+        };
+        let TransitionOptions { unit, boundary, .. } = *transition_options;
+        if let Some(stored) = stored {
+            let within = entries
+                .iter()
+                .take_while(|entry| entry.date() >= stored)
+                .count();
+            let running_counts = transition_options.running_counts(&entries);
+            let count = within.checked_sub(1).map_or(0, |last| running_counts[last]);
+            let unit = unit.name();
+            let outside_window = constraints
+                .not_before
+                .is_some_and(|not_before| stored < not_before);
+            if count <= *max_count && !outside_window {
+                println!(
+                    "[{} {unit} {}, within the constraints]",
+                    Grouped(count),
+                    boundary.describe(stored)
+                );
+                return Ok(WatchStatus {
+                    cutoff: boundary.cutoff(stored),
+                    count,
+                    updated: false,
+                });
+            }
+            println!(
+                "[{} {unit} {}, violates the constraints]",
+                Grouped(count),
+                boundary.describe(stored)
+            );
+        }
+
+        let solution = constraint::solve(&entries, constraints, *transition_options)?;
+        let cutoff = boundary.cutoff(solution.transition.cutoff);
+        println!(
+            "[updating cutoff to {cutoff}, {} {}]",
+            Grouped(solution.count),
+            unit.name()
+        );
+        let output = CutoffOutput {
+            earliest: solution.transition.cutoff,
+            boundary,
+            count: solution.count,
+            unit,
+            choice: "constraints",
+            query: None,
+        };
+        let mut sinks = Sinks::default();
+        sinks.push(
+            JsonFileSink::new(json_file, output_key.clone(), *on_existing)
+                .with_metadata(*metadata_keys)
+                .with_nesting(nesting.clone())
+                .with_audit_log(audit_log.clone()),
+        );
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = webhook {
+            sinks.push(webhook.clone());
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = mqtt {
+            sinks.push(mqtt.clone());
+        }
+        sinks.prepare(&output)?.write(*dry_run)?;
+        Ok(WatchStatus {
+            cutoff,
+            count: solution.count,
+            updated: true,
+        })
+    }
+}
+
+fn finish_report(
+    report: &RunReport,
+    verbose: bool,
+    report_file: Option<std::path::PathBuf>,
+) -> anyhow::Result<()> {
+    if verbose {
+        print!("Timings:\n{}", report.timings);
+    }
+    if let Some(report_file) = report_file {
+        report
+            .write(&report_file)
+            .with_context(|| format!("writing report file {report_file:?}"))
+            .failure(Failure::Write)?;
+    }
+    Ok(())
+}
+
+fn export_shuffled(parsed_args: ParsedArgs, export_args: ExportShuffledArgs) -> anyhow::Result<()> {
+    let ParsedArgs {
+        beets,
+        output_file,
+        output_key,
+        transition_options,
+        sentinel,
+        ..
+    } = parsed_args;
+    let ExportShuffledArgs {
+        path,
+        cutoff,
+        format,
+        seed,
+        newest_bias,
+    } = export_args;
+
+    let beets = beets.context("missing beet_command to query items for export")?;
+    let sentinel = sentinel.as_ref().map(|sentinel| (&beets, sentinel));
+    let boundary = transition_options.boundary;
+    let cutoff = resolve_cutoff(cutoff, output_file, output_key, sentinel, boundary)?;
+
+    let items = beets
+        .query_after(cutoff, &format)
+        .context("query items after cutoff")?;
+
+    let seed = seed.unwrap_or_else(shuffle::seed_from_time);
+    println!(
+        "Shuffling {} items {} with seed {seed}",
+        items.len(),
+        boundary.describe(cutoff)
+    );
+    let mut rng = shuffle::Rng::new(seed);
+    let item_count = items.len() as f64;
+    let items = shuffle::weighted_shuffle(items, &mut rng, |index| {
+        // items are sorted newest first
+        1.0 + newest_bias * (1.0 - index as f64 / item_count)
+    });
+
+    let contents: String = items.iter().map(|item| format!("{item}\n")).collect();
+    std::fs::write(&path, contents)
+        .with_context(|| format!("writing {path:?}"))
+        .failure(Failure::Write)?;
+    println!("Wrote {} items to {}", items.len(), path.display());
+    Ok(())
+}
+
+fn export_csv(parsed_args: ParsedArgs, export_args: ExportCsvArgs) -> anyhow::Result<()> {
+    let ParsedArgs {
+        beets,
+        entries_input,
+        lenient,
+        undated,
+        max_entries,
+        output_file,
+        output_key,
+        transition_options,
+        sentinel,
+        ..
+    } = parsed_args;
+    let ExportCsvArgs { path, cutoff } = export_args;
+    let mut report = RunReport::default();
+
+    let sentinel = beets.as_ref().zip(sentinel.as_ref());
+    let boundary = transition_options.boundary;
+    let cutoff = resolve_cutoff(cutoff, output_file, output_key, sentinel, boundary)?;
+    let source = EntrySource::new(&entries_input, &beets)?;
+    let output = source.fetch(None).context("query current items")?;
+    let parsed = source
+        .parse(&output, max_entries, lenient)
+        .context("query current items")?;
+    let entries = report.record_parsed(parsed, undated);
+
+    let delimiter = match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("tsv") => '\t',
+        _ => ',',
+    };
+    let header = [
+        "id",
+        "timestamp",
+        "album_artist",
+        "album",
+        "artist",
+        "title",
+        "filesize",
+        "included",
+    ];
+    let mut contents = delimited_line(header, delimiter);
+    for entry in &entries {
+        let DateEntry {
+            id,
+            timestamp,
+            album_artist,
+            album,
+            artist,
+            title,
+            filesize,
+            mb_albumid: _,
+            genre: _,
+            format: _,
+            compilation: _,
+            play_count: _,
+            extra: _,
+        } = entry;
+        let optional =
+            |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_default();
+        contents.push_str(&delimited_line(
+            [
+                optional(*id),
+                timestamp.to_string(),
+                album_artist.clone(),
+                album.clone(),
+                artist.clone(),
+                title.clone(),
+                optional(*filesize),
+                (entry.date() >= cutoff).to_string(),
+            ],
+            delimiter,
+        ));
+    }
+    std::fs::write(&path, contents)
+        .with_context(|| format!("writing {path:?}"))
+        .failure(Failure::Write)?;
+    let included = entries
+        .iter()
+        .filter(|entry| entry.date() >= cutoff)
+        .count();
+    println!(
+        "Wrote {} entries ({} {}) to {}",
+        Grouped(entries.len()),
+        Grouped(included),
+        boundary.describe(cutoff),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Whether the `path` (or its nearest existing ancestor) is on a filesystem mounted read-only
+#[cfg(unix)]
+fn on_read_only_filesystem(path: &std::path::Path) -> bool {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let existing = path
+        .ancestors()
+        .map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                std::path::Path::new(".")
+            } else {
+                ancestor
+            }
+        })
+        .find(|ancestor| ancestor.exists());
+    let Some(c_path) =
+        existing.and_then(|existing| std::ffi::CString::new(existing.as_os_str().as_bytes()).ok())
+    else {
+        return false;
+    };
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: the path is NUL-terminated, and the stat is only read when initialized
+    unsafe {
+        libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) == 0
+            && stat.assume_init().f_flag & libc::ST_RDONLY != 0
+    }
+}
+#[cfg(not(unix))]
+fn on_read_only_filesystem(_path: &std::path::Path) -> bool {
+    false
+}
+
+/// Pairs the output file and key, which must be specified together
+fn output_file_key(
+    output_file: Option<json::OutputFile>,
+    output_key: Option<String>,
+) -> anyhow::Result<Option<(json::OutputFile, String)>> {
+    match (output_file, output_key) {
+        (Some(file), Some(key)) => Ok(Some((file, key))),
+        (None, None) => Ok(None),
+        (Some(_), None) => anyhow::bail!("missing output_key for provided output_file"),
+        (None, Some(_)) => anyhow::bail!("missing output_file for provided output_key"),
+    }
+}
+
+/// Reads the entry lines from the file at `path`, or stdin for `-`
+fn read_entries_input(path: &std::path::Path) -> anyhow::Result<Vec<u8>> {
+    if path == std::path::Path::new("-") {
+        let mut input = vec![];
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut input)?;
+        Ok(input)
+    } else {
+        Ok(std::fs::read(path)?)
+    }
+}
+
+fn count(parsed_args: ParsedArgs, count_args: CountArgs) -> anyhow::Result<()> {
+    let CountArgs { from, to } = count_args;
+    let to = to.unwrap_or_else(Date::today);
+    if from > to {
+        anyhow::bail!("--from {from} is after --to {to}");
+    }
+    let beets = parsed_args
+        .beets
+        .context("missing beet_command to count items")?;
+    let count = beets.count_entries_between(from, to)?;
+    println!("{} items added from {from} to {to}", Grouped(count));
+    Ok(())
+}
+
+fn lint_filters(parsed_args: ParsedArgs) -> anyhow::Result<()> {
+    let ParsedArgs {
+        beets,
+        timeless_args,
+        ..
+    } = parsed_args;
+    let beets = beets.context("missing beet_command to lint the filters")?;
+
+    let filter_sets = beets.filter_sets();
+    match filter_sets.len() {
+        0 => println!("No filters, matching all items"),
+        1 => println!("Matching items with all of:"),
+        count => println!("Matching items with any of {count} filter sets:"),
+    }
+    let indent = if filter_sets.len() > 1 {
+        "      "
+    } else {
+        "  "
+    };
+    for (number, filter_set) in (1..).zip(&filter_sets) {
+        if filter_sets.len() > 1 {
+            println!("  {number}. all of:");
+        }
+        for atom in filter_set {
+            println!("{indent}{atom:?}");
+        }
+    }
+    println!("Fetched with:\n  {}", beets.describe_timeless());
+
+    let lints = beet_command::lint_filter_args(timeless_args);
+    if lints.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+    println!("Problems:");
+    for lint in &lints {
+        println!("  {lint}");
+    }
+    Err(anyhow::anyhow!("{} problems in the filters", lints.len())).failure(Failure::Config)
+}
+
+fn raw(parsed_args: ParsedArgs, raw_args: RawArgs) -> anyhow::Result<()> {
+    let RawArgs {
+        timeout,
+        retries,
+        args,
+    } = raw_args;
+    let beets = parsed_args
+        .beets
+        .context("missing beet_command to run the raw command")?;
+    if parsed_args.read_only {
+        let command = args.first().map(String::as_str).unwrap_or_default();
+        if !beet_command::READ_ONLY_COMMANDS.contains(&command) {
+            anyhow::bail!(
+                "refusing beet command {command:?} when read-only, expected one of: {}",
+                beet_command::READ_ONLY_COMMANDS.join(", ")
+            );
+        }
+    }
+    let retry_delay = Duration::from_secs(1);
+    let output = match timeout {
+        #[cfg(unix)]
+        Some(timeout) => beets
+            .with_runner(beet_command::TimeoutRunner::new(Duration::from_secs(
+                timeout,
+            )))
+            .run_raw(&args, retries, retry_delay)?,
+        #[cfg(not(unix))]
+        Some(_) => anyhow::bail!("--timeout is only supported on unix (killing the process group)"),
+        None => beets.run_raw(&args, retries, retry_delay)?,
+    };
+    std::io::stdout()
+        .write_all(&output)
+        .context("writing beet output")
+}
+
+fn show(parsed_args: ParsedArgs, show_args: ShowArgs) -> anyhow::Result<()> {
+    let ParsedArgs {
+        beets,
+        output_file,
+        output_key,
+        jobs,
+        transition_options,
+        ..
+    } = parsed_args;
+    let ShowArgs { all } = show_args;
+    let boundary = transition_options.boundary;
+
+    let beets = beets.context("missing beet_command to count items")?;
+    let profile_filters = if all {
+        read_profile_filters()?
+    } else {
+        std::collections::HashMap::new()
+    };
+    let output_file = output_file.context("missing output_file to show")?;
+    let json::JsonFile { map, path, .. } =
+        json::read_json_file(output_file).context("reading json file")?;
+    let Some(map) = map else {
+        anyhow::bail!("missing json file {path:?}")
+    };
+
+    let cutoffs: Vec<(String, Date)> = if all {
+        map.iter()
+            .filter_map(|(key, value)| Some((key.clone(), stored_cutoff(value)?.parse().ok()?)))
+            .collect()
+    } else {
+        let output_key = output_key.context("missing output_key to show (or specify --all)")?;
+        let cutoff = read_stored_cutoff_from(&map, &output_key, &path)?;
+        vec![(output_key, cutoff)]
+    };
+
+    let today = Date::today();
+    let rows = parallel::map_bounded(cutoffs, jobs, |(key, cutoff)| {
+        let profile_beets = profile_filters
+            .get(&key)
+            .map(|filters| filters.apply(&beets));
+        let count = profile_beets
+            .as_ref()
+            .unwrap_or(&beets)
+            .count_entries_after(boundary.earliest(cutoff))
+            .with_context(|| format!("counting entries for key {key:?}"))?;
+        Ok((key, cutoff, cutoff.describe_ago(today), count))
+    })
+    .into_iter()
+    .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut table = Table::new([
+        ("key", Align::Left),
+        ("cutoff", Align::Left),
+        ("age", Align::Left),
+        ("count", Align::Right),
+    ]);
+    for (key, cutoff, age, count) in rows {
+        table.push_row([key, cutoff.to_string(), age, Grouped(count).to_string()]);
+    }
+    print!("{table}");
+    Ok(())
+}
+
+/// Filters of a config file profile, counting the items of its `output_key` for `show --all`
+struct ProfileFilters {
+    timeless_args: String,
+    exclude_args: String,
+    min_rating: Option<f64>,
+}
+impl ProfileFilters {
+    fn apply<'a>(&self, beets: &BeetCommand<'a>) -> BeetCommand<'a> {
+        beets
+            .with_timeless_args(&self.timeless_args)
+            .with_excluded(&self.exclude_args)
+            .with_min_rating(self.min_rating)
+    }
+}
+/// Reads the filters of each config file profile by its `output_key`, if the profiles do not
+/// share the key (the other keys are counted with the filters of the arguments)
+fn read_profile_filters() -> anyhow::Result<std::collections::HashMap<String, ProfileFilters>> {
+    let Some(path) = config_path() else {
+        return Ok(std::collections::HashMap::new());
+    };
+    let config = config::Config::read(&path).failure(Failure::Config)?;
+    let mut profile_filters = std::collections::HashMap::new();
+    let mut shared_keys = vec![];
+    for (name, _) in &config.profiles {
+        let values = config.profile_values(name)?;
+        let value = |key: &str| {
+            values
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        let Some(output_key) = value("output_key") else {
+            continue;
+        };
+        let timeless_args = match value("timeless_args_file") {
+            Some(file) => {
+                let text = std::fs::read_to_string(file)
+                    .with_context(|| {
+                        format!("reading timeless_args_file {file:?} of profile {name:?}")
+                    })
+                    .failure(Failure::Config)?;
+                beet_command::filter_file_args(&text)
+            }
+            None => value("timeless_args").unwrap_or_default().to_owned(),
+        };
+        let min_rating = value("min_rating")
+            .map(|min_rating| {
+                min_rating.parse().with_context(|| {
+                    format!("invalid min_rating {min_rating:?} of profile {name:?}")
+                })
+            })
+            .transpose()
+            .failure(Failure::Config)?;
+        let filters = ProfileFilters {
+            timeless_args,
+            exclude_args: value("exclude_args").unwrap_or_default().to_owned(),
+            min_rating,
+        };
+        if profile_filters
+            .insert(output_key.to_owned(), filters)
+            .is_some()
+        {
+            shared_keys.push(output_key.to_owned());
+        }
+    }
+    for key in shared_keys {
+        if profile_filters.remove(&key).is_some() {
+            eprintln!("warning: key {key:?} is shared by profiles in {path:?}, counting it with the filters of the arguments");
+        }
+    }
+    Ok(profile_filters)
+}
+
+fn simulate(parsed_args: ParsedArgs, simulate_args: SimulateArgs) -> anyhow::Result<()> {
+    const BAR_WIDTH: f64 = 40.0;
+
+    let ParsedArgs {
+        beets,
+        entries_input,
+        lenient,
+        undated,
+        max_entries,
+        output_file,
+        output_key,
+        verbose,
+        report_file,
+        transition_options,
+        sentinel,
+        ..
+    } = parsed_args;
+    let SimulateArgs {
+        cutoff,
+        weeks,
+        review_count,
+    } = simulate_args;
+    let mut report = RunReport::default();
+
+    let sentinel = beets.as_ref().zip(sentinel.as_ref());
+    let boundary = transition_options.boundary;
+    let cutoff = resolve_cutoff(cutoff, output_file, output_key, sentinel, boundary)?;
+    let source = EntrySource::new(&entries_input, &beets)?;
+    let current_output = report
+        .timings
+        .record("fetch", || source.fetch(None))
+        .context("query current items")?;
+    let parsed = report
+        .timings
+        .record("parse", || {
+            source.parse(&current_output, max_entries, lenient)
+        })
+        .context("query current items")?;
+    let entries = report.record_parsed(parsed, undated);
+
+    let today = Date::today();
+    let current_count = source.count_after(cutoff, &entries, &mut report)?;
+    let Some(rate) = simulate::add_rate(&entries, today) else {
+        anyhow::bail!("no entries to estimate the rate of additions")
+    };
+    println!(
+        "{} entries {}, adding {rate:.2} entries per day (from {} fetched entries)",
+        Grouped(current_count),
+        boundary.describe(cutoff),
+        Grouped(entries.len())
+    );
+
+    let projected = simulate::project_weekly(current_count, rate, today, weeks);
+    let max_count = projected.last().map_or(0.0, |last| last.count).max(1.0);
+    let mut table = Table::new([
+        ("date", Align::Left),
+        ("count", Align::Right),
+        ("", Align::Left),
+    ]);
+    for simulate::ProjectedCount { date, count } in projected {
+        let bar = "#".repeat((count / max_count * BAR_WIDTH).round() as usize);
+        let count = Grouped(count.round() as u64);
+        table.push_row([date.to_string(), count.to_string(), bar]);
+    }
+    print!("{table}");
+
+    let review_count = review_count.unwrap_or((current_count * 2).max(1));
+    match simulate::review_date(current_count, rate, today, review_count) {
+        Some(date) => println!(
+            "Suggested review on {date}, when the count exceeds {}",
+            Grouped(review_count)
+        ),
+        None => println!("No review suggested, the count is not growing"),
+    }
+
+    finish_report(&report, verbose, report_file)
+}
+
+/// Returns the stored cutoff string of the value (the latest, for an appended history)
+fn stored_cutoff(value: &serde_json::Value) -> Option<&str> {
+    match value {
+        serde_json::Value::Array(history) => history.last()?.as_str(),
+        value => value.as_str(),
+    }
+}
+
+/// Returns the earliest date included by the specified `cutoff`, or else by the cutoff stored in
+/// the output file key (or else on the sentinel item)
+fn resolve_cutoff(
+    cutoff: Option<Date>,
+    output_file: Option<json::OutputFile>,
+    output_key: Option<String>,
+    sentinel: Option<(&BeetCommand<'_>, &Sentinel)>,
+    boundary: Boundary,
+) -> anyhow::Result<Date> {
+    let cutoff = match (cutoff, output_file_key(output_file, output_key)?, sentinel) {
+        (Some(cutoff), _, _) => anyhow::Ok(cutoff),
+        (None, Some((output_file, output_key)), _) => read_stored_cutoff(output_file, &output_key),
+        (None, None, Some((beets, sentinel))) => beets
+            .read_sentinel_cutoff(sentinel)?
+            .with_context(|| format!("missing cutoff on the sentinel item {:?}", sentinel.query)),
+        (None, None, None) => anyhow::bail!(
+            "missing cutoff, specify --cutoff, output_file/output_key, or sentinel_query"
+        ),
+    }?;
+    Ok(boundary.earliest(cutoff))
+}
+
+fn read_stored_cutoff(output_file: json::OutputFile, output_key: &str) -> anyhow::Result<Date> {
+    let json::JsonFile { map, path, .. } =
+        json::read_json_file(output_file).context("reading json file")?;
+    let Some(map) = map else {
+        anyhow::bail!("missing json file {path:?}")
+    };
+    read_stored_cutoff_from(&map, output_key, &path)
+}
+fn read_stored_cutoff_from(
+    map: &serde_json::Map<String, serde_json::Value>,
+    output_key: &str,
+    path: &std::path::Path,
+) -> anyhow::Result<Date> {
+    let Some(value) = map.get(output_key) else {
+        anyhow::bail!("missing key {output_key:?} in json file {path:?}")
+    };
+    let Some(cutoff) = stored_cutoff(value) else {
+        anyhow::bail!("unexpected value for key {output_key:?}: {value}")
+    };
+    cutoff
+        .parse()
+        .with_context(|| format!("stored cutoff for key {output_key:?}"))
+}
+
+/// Opens the file descriptor inherited from the parent process, for writing the [`RunSummary`]
+fn open_summary_fd(fd: i32) -> anyhow::Result<std::fs::File> {
+    if (0..=2).contains(&fd) {
+        anyhow::bail!("summary fd {fd} is a standard stream, use a descriptor above 2");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::fd::FromRawFd as _;
+        // SAFETY: only queries the descriptor flags
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            let err = std::io::Error::last_os_error();
+            anyhow::bail!("summary fd {fd} is not open: {err}");
+        }
+        // SAFETY: the descriptor is open, and is not used elsewhere in this process
+        Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+    }
+    #[cfg(not(unix))]
+    anyhow::bail!("--summary-fd is only supported on unix")
+}
+
+/// Restores the default SIGPIPE action (terminate quietly), which the Rust runtime ignores
+///
+/// Otherwise `println!` panics when stdout is closed early, e.g. when piped to `head`
+fn reset_sigpipe() {
+    #[cfg(unix)]
+    // SAFETY: called at startup before any other threads exist, restoring the default handler
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+struct ParsedArgs<'a> {
+    verbose: bool,
+    /// Notification when the interactive candidates are ready
+    ready_notice: ReadyNotice,
+    report_file: Option<std::path::PathBuf>,
+    /// Destination for the [`RunSummary`] of the selected cutoff
+    summary_file: Option<std::fs::File>,
+    beets: Option<BeetCommand<'a>>,
+    /// Filter arguments, as specified (see [`beet_command::lint_filter_args`])
+    timeless_args: &'a str,
+    /// Entry lines read in place of querying beets
+    entries_input: Option<Vec<u8>>,
+    lenient: bool,
+    undated: UndatedAction,
+    /// Refuse cutoffs that incomplete input may have affected
+    strict: bool,
+    /// File saving the fetched entries of an interactive run
+    session_file: Option<std::path::PathBuf>,
+    resume: bool,
+    on_existing: OnExisting,
+    jobs: NonZeroUsize,
+    max_entries: usize,
+    max_entries_hard_limit: usize,
+    /// Start with fewer entries, fetching more for targets out of range
+    adaptive_fetch: bool,
+    output_file: Option<json::OutputFile>,
+    output_key: Option<String>,
+    /// Key of the stored cutoff compared with each candidate
+    compare_with: Option<String>,
+    /// Keys whose stored cutoffs must nest with the written cutoff
+    nesting: Option<(json::Nesting, json::OnNestingViolation)>,
+    /// JSON lines file recording each write to the output file
+    audit_log: Option<std::path::PathBuf>,
+    /// Write the count and update time next to the output key
+    metadata_keys: bool,
+    /// Webhook receiving the written cutoff
+    #[cfg(feature = "webhook")]
+    webhook: Option<WebhookSink>,
+    /// MQTT message announcing the written cutoff
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<MqttSink>,
+    transition_options: TransitionOptions<'a>,
+    targets: Targets,
+    window: Option<Window>,
+    max_count: Option<usize>,
+    min_count: Option<usize>,
+    on_min_count: MinCountAction,
+    /// Item storing the cutoff in the beets library
+    sentinel: Option<Sentinel>,
+    watch: Option<Watch>,
+    /// Delay re-evaluating in watch and serve modes until triggers stop
+    debounce: Duration,
+    /// Text file with the date token to replace, and the pattern locating it
+    patch_file: Option<(std::path::PathBuf, regex_lite::Regex)>,
+    /// Template file, and the destination of its rendering
+    template: Option<(std::path::PathBuf, std::path::PathBuf)>,
+    /// Destination of the script recording the final query
+    emit_script: Option<std::path::PathBuf>,
+    /// Show the filter set composition of the selected items
+    composition: bool,
+    /// Show the selected items per artist and album
+    breakdown: bool,
+    /// Minimum count of entries from each filter set
+    balance: Option<usize>,
+    /// Most tracks per artist after the cutoff
+    max_per_artist: Option<NonZeroUsize>,
+    /// Output key storing the query excluding the tracks beyond `max_per_artist`
+    exclusion_key: Option<String>,
+    modify_assignments: Vec<String>,
+    clear_previous: bool,
+    dry_run: bool,
+    /// Refuse to modify the beets library (implies `dry_run`)
+    read_only: bool,
+}
+
+fn parse_assignment(assignment: &str) -> anyhow::Result<String> {
+    match assignment.split_once('=') {
+        Some((field, _value)) if !field.is_empty() => Ok(assignment.to_owned()),
+        _ => anyhow::bail!("expected FIELD=VALUE"),
+    }
+}
+
+/// Bounds on the number of entries fetched
+struct FetchLimits {
+    /// Current number of entries fetched
+    max_entries: usize,
+    /// Largest number of entries to fetch when re-fetching for larger targets
+    hard_limit: usize,
+    /// Skip lines of the re-fetched entries that fail to parse
+    lenient: bool,
+    /// Handling of re-fetched entries without a date
+    undated: UndatedAction,
+    /// When fetching adaptively, the largest number of entries to fetch for targets out of range
+    adaptive_limit: Option<usize>,
+}
+impl FetchLimits {
+    /// Fetches and parses up to `max_entries` entries, recording the parse in the `report`
+    ///
+    /// When fetching adaptively, stops the query once the lines needed to detect truncation are
+    /// read.
+    fn fetch(
+        &self,
+        source: &EntrySource<'_, '_>,
+        report: &mut RunReport,
+    ) -> anyhow::Result<Vec<DateEntry>> {
+        let head = self.adaptive_limit.map(|_| self.max_entries + 1);
+        let output = report.timings.record("fetch", || source.fetch(head))?;
+        let parsed = report.timings.record("parse", || {
+            source.parse(&output, self.max_entries, self.lenient)
+        })?;
+        let entries = report.record_parsed(parsed, self.undated);
+        if let (EntrySource::Beets(beets), false) = (source, entries.is_empty()) {
+            for (index, field) in beets.extra_fields().iter().enumerate() {
+                if entries
+                    .iter()
+                    .all(|entry| entry.extra_field(index, field).is_none())
+                {
+                    println!(
+                        "[WARNING: no item has the field {field:?} (e.g. from a plugin that is not enabled), leaving it out]"
+                    );
+                }
+            }
+        }
+        Ok(entries)
+    }
+    /// Returns the increased limit when fetching adaptively, if below the adaptive limit
+    fn adaptive_increase(&self) -> Option<usize> {
+        let limit = self.adaptive_limit?;
+        (self.max_entries < limit).then(|| self.max_entries.max(1).saturating_mul(2).min(limit))
+    }
+    /// Returns the increased limit required to find transitions for `target_counts`, if any
+    fn required_increase(&self, target_counts: &[usize]) -> Option<usize> {
+        let largest_target = target_counts.iter().copied().max()?;
+        // transitions require entries beyond the target
+        (largest_target >= self.max_entries)
+            .then(|| (largest_target * 2).clamp(self.max_entries, self.hard_limit))
+            .filter(|&increased| increased > self.max_entries)
+    }
+}
+
+/// Target counts for the candidate breakpoints
+struct Targets {
+    /// Strategy specified on the command line, otherwise preloaded from the previous run
+    strategy: Option<StrategySpec>,
+    /// When set, the number of breakpoints shown nearest to a single target count
+    candidates: Option<NonZeroUsize>,
+    /// Selection stored by the previous run
+    last_run: Option<json::LastRun>,
+    /// Stored cutoff described relative to each candidate
+    compare_with: Option<Comparison>,
+    /// Output key changed by the chosen candidate, for explaining the candidates
+    output: Option<OutputValue>,
+    /// Show the additions around each candidate (see [`additions_sparkline`])
+    sparklines: bool,
+    /// Show the genres and formats included by each candidate (see [`genre_format_summary`])
+    genre_mix: bool,
+    /// Policy choosing a candidate without prompting, if any
+    auto_select: Option<AutoSelect>,
+}
+impl Targets {
+    const DEFAULT_COUNTS: [usize; 3] = [30, 50, 70];
+
+    /// Returns the strategy and default choice, preloading the `last_run` (when no strategy is
+    /// specified, or when it matches the specified strategy)
+    fn preload(&self) -> (StrategySpec, Option<NonZeroUsize>) {
+        let stored = self.last_run.clone().and_then(|last_run| {
+            let json::LastRun { strategy, choice } = last_run;
+            match strategy.parse::<StrategySpec>() {
+                Ok(strategy) => Some((strategy, choice)),
+                Err(err) => {
+                    println!("[ignoring stored strategy {strategy:?}: {err}]");
+                    None
+                }
+            }
+        });
+        match (&self.strategy, stored) {
+            (None, Some((stored, choice))) => {
+                println!(
+                    "[preloaded strategy {stored} and choice #{choice} from the previous run]"
+                );
+                (stored, Some(choice))
+            }
+            (Some(strategy), Some((stored, choice))) if *strategy == stored => {
+                (stored, Some(choice))
+            }
+            (strategy, _) => {
+                let strategy = strategy
+                    .clone()
+                    .unwrap_or_else(|| StrategySpec::Targets(Self::DEFAULT_COUNTS.to_vec()));
+                (strategy, None)
+            }
+        }
+    }
+    /// Returns the largest target count of the strategy chosen by [`Self::preload`], if any
+    fn largest_count(&self) -> Option<usize> {
+        let stored = self
+            .last_run
+            .as_ref()
+            .and_then(|last_run| last_run.strategy.parse::<StrategySpec>().ok());
+        self.strategy
+            .clone()
+            .or(stored)
+            .unwrap_or_else(|| StrategySpec::Targets(Self::DEFAULT_COUNTS.to_vec()))
+            .largest_count()
+    }
+    /// Returns the strategy for the target `counts` (e.g. from custom input)
+    fn custom_strategy(counts: Vec<usize>, candidates: Option<NonZeroUsize>) -> StrategySpec {
+        match (candidates, &counts[..]) {
+            (Some(candidate_count), &[target_count]) => StrategySpec::Around {
+                target_count,
+                candidate_count: candidate_count.get(),
+            },
+            // candidates surround a single target only
+            _ => StrategySpec::Targets(counts),
+        }
+    }
+}
+
+/// Items on the boundary day of a candidate, when the fetched entries miss some in the library
+/// (e.g. from a fetch ending within the day)
+struct BoundarySplit {
+    day: Date,
+    fetched: usize,
+    library: usize,
+}
+impl BoundarySplit {
+    /// Counts the items on the boundary day of the `transition` in the library, returning the
+    /// split if the fetched `entries` (sorted newest first) miss any
+    fn check(
+        beets: &BeetCommand<'_>,
+        entries: &[DateEntry],
+        transition: &Transition<'_>,
+    ) -> anyhow::Result<Option<Self>> {
+        let day = transition.included.date();
+        let start = entries.partition_point(|entry| entry.date() > day);
+        let end = entries.partition_point(|entry| entry.date() >= day);
+        let fetched = count_items(&entries[start..end]);
+        let library = beets.count_entries_on(day)?;
+        Ok((library > fetched).then_some(Self {
+            day,
+            fetched,
+            library,
+        }))
+    }
+    /// Items on the day missing from the fetched entries
+    fn missing(&self) -> usize {
+        self.library - self.fetched
+    }
+}
+impl std::fmt::Display for BoundarySplit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            day,
+            fetched,
+            library,
+        } = self;
+        write!(
+            f,
+            "boundary day {day} has {} items, but only {} were fetched",
+            Grouped(library),
+            Grouped(fetched)
+        )
+    }
+}
+
+/// Days before and after each candidate cutoff in the sparkline of additions
+const SPARKLINE_DAYS: u16 = 14;
+const SPARKLINE_HEADER: &str = "additions ±2 weeks";
+
+/// Cutoff stored at another key, compared with each candidate (e.g. for nested playlists)
+/// Output key with its current value
+struct OutputValue {
+    path: std::path::PathBuf,
+    key: String,
+    old: Option<serde_json::Value>,
+}
+impl OutputValue {
+    /// Describes the change from storing the `cutoff`
+    fn describe_change(&self, cutoff: Date) -> String {
+        let Self { path, key, old } = self;
+        let old = old
+            .as_ref()
+            .map_or_else(|| "(unset)".to_owned(), ToString::to_string);
+        let new = serde_json::Value::from(cutoff.to_string());
+        format!("{path:?} key {key:?}: {old} → {new}")
+    }
+}
+
+struct Comparison {
+    key: String,
+    /// Earliest date included by the stored cutoff
+    earliest: Date,
+}
+impl Comparison {
+    /// Describes the candidate `cutoff` relative to the stored cutoff, with the count included by
+    /// both (from the `running_counts` of the `entries`)
+    fn describe(&self, cutoff: Date, entries: &[DateEntry], running_counts: &[usize]) -> String {
+        let relation = match cutoff.cmp(&self.earliest) {
+            std::cmp::Ordering::Greater => "newer",
+            std::cmp::Ordering::Less => "older",
+            std::cmp::Ordering::Equal => "same",
+        };
+        let later = cutoff.max(self.earliest);
+        let shared_entries = entries.partition_point(|entry| entry.date() >= later);
+        let shared = shared_entries
+            .checked_sub(1)
+            .map_or(0, |index| running_counts[index]);
+        format!("{relation}, {} shared", Grouped(shared))
+    }
+}
+
+/// Prints the count of items on or after the `cutoff` excluded by the `--min-rating`, if any
+fn print_below_min_rating(beets: &BeetCommand<'_>, cutoff: Date) -> anyhow::Result<()> {
+    let (Some(min_rating), Some(below)) = (beets.min_rating(), beets.below_min_rating()) else {
+        return Ok(());
+    };
+    let excluded = below
+        .count_entries_after(cutoff)
+        .context("counting entries below the minimum rating")?;
+    println!(
+        "[{} more entries after the cutoff are rated below {min_rating} (or unrated), excluded]",
+        Grouped(excluded)
+    );
+    Ok(())
+}
+
+/// Source of the entries to select a cutoff from
+enum EntrySource<'a, 'b> {
+    /// Query `beet list`
+    Beets(&'b BeetCommand<'a>),
+    /// Lines formatted as `$added $artist - $album - $title` (e.g. read from stdin)
+    Lines(&'b [u8]),
+}
+impl<'a, 'b> EntrySource<'a, 'b> {
+    fn new(
+        entries_input: &'b Option<Vec<u8>>,
+        beets: &'b Option<BeetCommand<'a>>,
+    ) -> anyhow::Result<Self> {
+        match (entries_input, beets) {
+            (Some(input), _) => Ok(EntrySource::Lines(input)),
+            (None, Some(beets)) => Ok(EntrySource::Beets(beets)),
+            (None, None) => anyhow::bail!("missing beet_command to query entries"),
+        }
+    }
+    /// Counts the entries on or after the `cutoff`, given the fetched `entries` (newest first)
+    ///
+    /// Warns when the count query disagrees with the fetched entries, recording the mismatch in
+    /// the `report`.
+    fn count_after(
+        &self,
+        cutoff: Date,
+        entries: &[DateEntry],
+        report: &mut RunReport,
+    ) -> anyhow::Result<usize> {
+        let fetched_count = entries
+            .iter()
+            .take_while(|entry| entry.date() >= cutoff)
+            .count();
+        let expected = count_items(&entries[..fetched_count]);
+        match self {
+            EntrySource::Beets(beets) => {
+                let counted = report
+                    .timings
+                    .record("count", || beets.count_entries_after(cutoff))
+                    .context("counting entries with chosen date bound")?;
+                // undated entries are before any cutoff, for the `beet` query
+                let actual = counted + report.undated_included;
+                // older entries beyond the fetched limit may also be after the cutoff
+                let is_exact = fetched_count < entries.len();
+                if is_exact && actual != expected {
+                    let mismatch = CountMismatch { expected, actual };
+                    println!("[WARNING: {mismatch}]");
+                    println!(
+                        "[WARNING: the library may have changed during this run (e.g. a concurrent import), or the filters differ between the queries]"
+                    );
+                    report.count_mismatch = Some(mismatch);
+                }
+                Ok(actual)
+            }
+            EntrySource::Lines(_) => Ok(expected),
+        }
+    }
+    /// Fetches the entry lines, or only the first `head` lines (when querying beets)
+    fn fetch(&self, head: Option<usize>) -> anyhow::Result<Vec<u8>> {
+        match self {
+            EntrySource::Beets(beets) => match head {
+                Some(max_lines) => beets.fetch_timeless_head(max_lines),
+                None => beets.fetch_timeless(),
+            },
+            EntrySource::Lines(input) => Ok(input.to_vec()),
+        }
+    }
+    fn parse(
+        &self,
+        output: &[u8],
+        max_entries: usize,
+        lenient: bool,
+    ) -> anyhow::Result<ParsedEntries> {
+        match self {
+            EntrySource::Beets(_) => parse_timeless(output, max_entries, lenient),
+            EntrySource::Lines(_) => parse_entry_lines(output, max_entries, lenient),
+        }
+        .failure(Failure::Parse)
+    }
+}
+
+fn select_end<'a>(
+    entries: &'a mut Vec<DateEntry>,
+    fetch_limits: &mut FetchLimits,
+    source: &EntrySource<'_, '_>,
+    report: &mut RunReport,
+    mut transition_options: TransitionOptions,
+    targets: Targets,
+    ready_notice: ReadyNotice,
+) -> anyhow::Result<Option<(Transition<'a>, json::LastRun)>> {
+    let today = Date::today();
+    let (mut strategy_spec, mut default_choice) = targets.preload();
+    let compare_header = targets
+        .compare_with
+        .as_ref()
+        .map(|comparison| format!("vs {}", comparison.key));
+    let mut strategy = strategy_spec.clone().build(today);
+    // notify after each fetch only, not for re-displaying the same entries
+    let mut fetched = true;
+    // boundaries bookmarked while adjusting a candidate, offered as candidates
+    let mut bookmarks = Bookmarks::default();
+
+    let (index, cutoff, choice) = loop {
+        let candidates_start = Instant::now();
+        let running_counts = transition_options.running_counts(entries);
+        let outcomes = strategy.candidates(entries, transition_options);
+        let out_of_range = outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, Outcome::OutOfRange(_)));
+        if let Some(increased) = fetch_limits
+            .adaptive_increase()
+            .filter(|_| out_of_range && report.truncated)
+        {
+            println!("[fetching up to {increased} entries for the targets out of range]");
+            fetch_limits.max_entries = increased;
+            *entries = fetch_limits
+                .fetch(source, report)
+                .context("re-query current items")?;
+            continue;
+        }
+        let mut labeled_transitions: Vec<_> = outcomes
+            .into_iter()
+            .filter_map(|outcome| match outcome {
+                Outcome::Candidate(Candidate {
+                    label,
+                    target,
+                    transition,
+                }) => Some((label, target, transition)),
+                Outcome::Note(note) | Outcome::OutOfRange(note) => {
+                    println!("[{note}]");
+                    None
+                }
+            })
+            .collect();
+        let offered: Vec<_> = labeled_transitions
+            .iter()
+            .map(|(_, _, transition)| transition.cutoff)
+            .collect();
+        labeled_transitions.extend(
+            bookmarks
+                .candidates(entries, transition_options, &offered)
+                .into_iter()
+                .map(
+                    |Candidate {
+                         label,
+                         target,
+                         transition,
+                     }| (label, target, transition),
+                ),
+        );
+        // a fetch ending within a boundary day misses some of its items
+        let boundary_splits = match source {
+            EntrySource::Beets(beets) if report.truncated => labeled_transitions
+                .iter()
+                .map(|(_, _, transition)| BoundarySplit::check(beets, entries, transition))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .context("counting items on the boundary days")?,
+            _ => vec![],
+        };
+        let unit = transition_options.unit;
+        let weighted = transition_options.compilations.is_reduced();
+        let unit_header = if weighted {
+            format!("weighted {}", unit.name())
+        } else {
+            unit.name().to_owned()
+        };
+        let show_tracks = unit != CountUnit::Tracks || weighted;
+        let mut columns = vec![
+            ("#", Align::Right),
+            ("target", Align::Left),
+            (&unit_header, Align::Right),
+        ];
+        if show_tracks {
+            columns.push(("tracks", Align::Right));
+        }
+        let boundary = transition_options.boundary;
+        columns.extend([("cutoff", Align::Left), ("age", Align::Left)]);
+        if targets.sparklines {
+            columns.push((SPARKLINE_HEADER, Align::Left));
+        }
+        if let Some(header) = &compare_header {
+            columns.push((header, Align::Left));
+        }
+        if targets.genre_mix {
+            columns.push(("genres · formats", Align::Left));
+        }
+        columns.push(("boundary album", Align::Left));
+        // fields set on any item, by their index in the fetched extra fields
+        let extra_fields: Vec<(usize, &str)> = match source {
+            EntrySource::Beets(beets) => beets
+                .extra_fields()
+                .iter()
+                .enumerate()
+                .filter(|&(index, field)| {
+                    entries
+                        .iter()
+                        .any(|entry| entry.extra_field(index, field).is_some())
+                })
+                .map(|(index, field)| (index, field.as_str()))
+                .collect(),
+            EntrySource::Lines(_) => vec![],
+        };
+        columns.extend(extra_fields.iter().map(|&(_, field)| (field, Align::Left)));
+        let show_links = labeled_transitions
+            .iter()
+            .any(|(_, _, transition)| transition.included.mb_albumid.is_some());
+        if show_links {
+            columns.push(("musicbrainz", Align::Left));
+        }
+        let mut table = Table::new(columns);
+        let mut candidate_reports = vec![];
+        let mut explanations = vec![];
+        let candidate_targets: Vec<_> = labeled_transitions
+            .iter()
+            .map(|&(_, target, _)| target)
+            .collect();
+        let transitions: Vec<_> = labeled_transitions
+            .into_iter()
+            .enumerate()
+            .map(|(choice_index, (label, _, transition))| {
+                let choice = choice_index + 1;
+                let mut count = running_counts[transition.index];
+                if let Some(split) = boundary_splits.get(choice_index).and_then(Option::as_ref) {
+                    let adjustment = if unit == CountUnit::Tracks {
+                        count += split.missing();
+                        "adjusted"
+                    } else {
+                        "not adjusted"
+                    };
+                    println!(
+                        "[#{choice}: {split}, {unit} count {adjustment}]",
+                        unit = unit.name()
+                    );
+                }
+                let (album_artist, album) = transition.included.album_key();
+                let mut boundary_album = format!("{album_artist} - {album}");
+                if transition.included.compilation {
+                    boundary_album.push_str(" [VA]");
+                }
+                let musicbrainz_url = transition.included.musicbrainz_url();
+
+                let mut cells = vec![
+                    choice.to_string(),
+                    label.clone(),
+                    Grouped(count).to_string(),
+                ];
+                if show_tracks {
+                    cells.push(Grouped(transition.index + 1).to_string());
+                }
+                cells.extend([
+                    boundary.describe(transition.cutoff),
+                    transition.cutoff.describe_ago(today),
+                ]);
+                if targets.sparklines {
+                    cells.push(additions_sparkline(
+                        entries,
+                        transition.cutoff,
+                        SPARKLINE_DAYS,
+                    ));
+                }
+                if let Some(comparison) = &targets.compare_with {
+                    cells.push(comparison.describe(transition.cutoff, entries, &running_counts));
+                }
+                if targets.genre_mix {
+                    cells.push(genre_format_summary(&entries[..=transition.index]));
+                }
+                cells.push(boundary_album.clone());
+                cells.extend(extra_fields.iter().map(|&(index, field)| {
+                    let value = transition.included.extra_field(index, field);
+                    value.unwrap_or_default().to_owned()
+                }));
+                if show_links {
+                    cells.push(musicbrainz_url.clone().unwrap_or_default());
+                }
+                table.push_row(cells);
+                // a cutoff includes all items of the boundary day, beyond the target
+                let boundary_day = transition.included.date();
+                let day_start = entries.partition_point(|entry| entry.date() > boundary_day);
+                let without_day = day_start
+                    .checked_sub(1)
+                    .map_or(0, |index| running_counts[index]);
+                let query = match source {
+                    EntrySource::Beets(beets) => beets.describe_list_after(transition.cutoff),
+                    EntrySource::Lines(_) => "none, for entries read from the input".to_owned(),
+                };
+                let output = targets.output.as_ref().map_or_else(
+                    || "none, without an output file".to_owned(),
+                    |output| output.describe_change(boundary.cutoff(transition.cutoff)),
+                );
+                explanations.push(format!(
+                    "#{choice}: target {label} of strategy {strategy_spec}\n  \
+                     {count} {unit} {cutoff}\n  \
+                     {day_count} {unit} added on {boundary_day} are included together, as a cutoff cannot split a day ({without_day} {unit} without them)\n  \
+                     query: {query}\n  \
+                     output: {output}",
+                    count = Grouped(count),
+                    unit = unit.name(),
+                    cutoff = boundary.describe(transition.cutoff),
+                    day_count = Grouped(count - without_day),
+                    without_day = Grouped(without_day),
+                ));
+                candidate_reports.push(CandidateReport {
+                    choice,
+                    label,
+                    cutoff: boundary.cutoff(transition.cutoff),
+                    count,
+                    boundary_album,
+                    musicbrainz_url,
+                });
+                transition
+            })
+            .collect();
+        if !table.is_empty() {
+            print!("{table}");
+            let table = table.to_string();
+            if let Some(recorded) =
+                lock_replay().and_then(|mut replay| replay.check_candidates(&table))
+            {
+                println!("[replay: the candidates changed since the recording]");
+                print!("{}", diff_lines(&recorded, &table));
+            }
+            record_transcript(transcript::Event::Candidates { table });
+        }
+        report.candidates = candidate_reports;
+        report.timings.add("candidates", candidates_start.elapsed());
+        if std::mem::take(&mut fetched) {
+            ready_notice.send(&format!("{} candidates ready", transitions.len()));
+        }
+        if let Some(policy) = targets.auto_select {
+            let candidates: Vec<_> = candidate_targets
+                .iter()
+                .zip(&report.candidates)
+                .map(|(&target, candidate)| (target, candidate.count))
+                .collect();
+            let index = policy
+                .choose(&candidates)
+                .with_context(|| format!("no candidate to choose by --auto-select {policy}"))?;
+            let choice = NonZeroUsize::new(index + 1).expect("nonzero");
+            println!("[auto-selected #{choice} by {policy}]");
+            let transition = &transitions[index];
+            break (transition.index, transition.cutoff, choice);
+        }
+
+        // the default applies to the initial candidates only
+        let prompt_default = default_choice
+            .take()
+            .filter(|choice| choice.get() <= transitions.len());
+        let selection = prompt_user_selection(
+            &transitions,
+            fetch_limits.hard_limit,
+            prompt_default,
+            &explanations,
+            Adjustable {
+                entries,
+                running_counts: &running_counts,
+                options: transition_options,
+            },
+            &mut bookmarks,
+        )?;
+        match selection {
+            Some(UserSelection::NewCounts(new_counts)) => {
+                if let Some(increased) = fetch_limits.required_increase(&new_counts) {
+                    println!("[fetching up to {increased} entries for the new targets]");
+                    fetch_limits.max_entries = increased;
+                    *entries = fetch_limits
+                        .fetch(source, report)
+                        .context("re-query current items")?;
+                    fetched = true;
+                }
+                strategy_spec = Targets::custom_strategy(new_counts, targets.candidates);
+                strategy = strategy_spec.clone().build(today);
+            }
+            Some(UserSelection::Mode(unit)) => {
+                let unit = unit.unwrap_or(match transition_options.unit {
+                    CountUnit::Tracks => CountUnit::Albums,
+                    CountUnit::Albums | CountUnit::Events => CountUnit::Tracks,
+                });
+                println!("[counting targets in {}]", unit.name());
+                transition_options.unit = unit;
+            }
+            Some(UserSelection::Bookmarked) => {}
+            Some(UserSelection::Transition {
+                index,
+                cutoff,
+                choice,
+            }) => break (index, cutoff, choice),
+            None => return Ok(None),
+        }
+    };
+    let entries: &'a [DateEntry] = entries;
+    let transition = Transition {
+        index,
+        included: &entries[index],
+        excluded: &entries[index + 1],
+        cutoff,
+    };
+    let last_run = json::LastRun {
+        strategy: strategy_spec.to_string(),
+        choice,
+    };
+    Ok(Some((transition, last_run)))
+}
+
+enum UserSelection {
+    Transition {
+        index: usize,
+        cutoff: Date,
+        /// Number of the chosen candidate
+        choice: NonZeroUsize,
+    },
+    NewCounts(Vec<usize>),
+    /// Count targets in the unit (or toggle between tracks and albums)
+    Mode(Option<CountUnit>),
+    /// Show the candidates again, with the new bookmarks
+    Bookmarked,
+}
+fn prompt_user_selection(
+    transitions: &[Transition<'_>],
+    max_entries_hard_limit: usize,
+    default_choice: Option<NonZeroUsize>,
+    explanations: &[String],
+    adjustable: Adjustable<'_>,
+    bookmarks: &mut Bookmarks,
+) -> anyhow::Result<Option<UserSelection>> {
+    let mut machine =
+        SelectionStateMachine::new(transitions.len(), default_choice, max_entries_hard_limit);
+    let mut prompt = terminal_prompt();
+    loop {
+        let input = prompt.read_line(&machine.prompt())?;
+        let selection = match machine.step(input) {
+            selection::Output::Awaiting { message } => {
+                if let Some(message) = message {
+                    println!("{message}");
+                }
+                continue;
+            }
+            selection::Output::Quit => return Ok(None),
+            selection::Output::Selected(selection) => selection,
+        };
+        let user_selection = match selection {
+            Selection::Candidate(choice) => {
+                let transition = &transitions[choice.get() - 1];
+                UserSelection::Transition {
+                    index: transition.index,
+                    cutoff: transition.cutoff,
+                    choice,
+                }
+            }
+            Selection::Adjust(choice) => {
+                let start = &transitions[choice.get() - 1];
+                let bookmark_count = bookmarks.len();
+                match adjust_transition(&mut prompt, start, adjustable, bookmarks)? {
+                    Some(transition) => UserSelection::Transition {
+                        index: transition.index,
+                        cutoff: transition.cutoff,
+                        choice,
+                    },
+                    None if bookmarks.len() > bookmark_count => UserSelection::Bookmarked,
+                    None => {
+                        machine.reset();
+                        continue;
+                    }
+                }
+            }
+            Selection::Explain(choice) => {
+                println!("{}", explanations[choice.get() - 1]);
+                machine.reset();
+                continue;
             }
+            Selection::NewCounts(new_counts) => UserSelection::NewCounts(new_counts),
+            Selection::Mode(unit) => UserSelection::Mode(unit),
         };
-        Ok(result)
+        return Ok(Some(user_selection));
     }
 }
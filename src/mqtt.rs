@@ -0,0 +1,207 @@
+//! Minimal MQTT 3.1.1 client publishing a retained message (QoS 0), e.g. announcing the cutoff
+//! to a Home Assistant dashboard
+
+use anyhow::Context as _;
+use std::{
+    io::{Read as _, Write as _},
+    net::{TcpStream, ToSocketAddrs as _},
+    time::Duration,
+};
+
+const DEFAULT_PORT: u16 = 1883;
+
+/// Broker address, as `HOST` or `HOST:PORT`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Broker {
+    pub host: String,
+    pub port: u16,
+}
+impl std::str::FromStr for Broker {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (host, port) = match s.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .with_context(|| format!("invalid port {port:?}"))?;
+                (host, port)
+            }
+            None => (s, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            anyhow::bail!("missing host in broker address {s:?}");
+        }
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+        })
+    }
+}
+impl std::fmt::Display for Broker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { host, port } = self;
+        write!(f, "{host}:{port}")
+    }
+}
+
+/// Username and password authenticating with the broker
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+/// Connects to the `broker`, publishes the retained `payload` on the `topic`, and disconnects
+pub fn publish_retained(
+    broker: &Broker,
+    client_id: &str,
+    credentials: Option<&Credentials>,
+    topic: &str,
+    payload: &[u8],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let address = (broker.host.as_str(), broker.port)
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("no address for broker {broker}"))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    stream.write_all(&connect_packet(client_id, credentials))?;
+    let mut connack = [0; 4];
+    stream
+        .read_exact(&mut connack)
+        .context("reading the connection acknowledgement")?;
+    match connack {
+        [0x20, 0x02, _, 0] => {}
+        [0x20, 0x02, _, code] => anyhow::bail!("connection refused by {broker}: {}", refusal(code)),
+        _ => anyhow::bail!("unexpected response from {broker}: {connack:02x?}"),
+    }
+    stream.write_all(&publish_packet(topic, payload))?;
+    stream.write_all(&[DISCONNECT, 0])?;
+    stream.flush()?;
+    Ok(())
+}
+
+const CONNECT: u8 = 0x10;
+/// PUBLISH with QoS 0 and the retain flag
+const PUBLISH_RETAINED: u8 = 0x31;
+const DISCONNECT: u8 = 0xe0;
+
+fn refusal(code: u8) -> &'static str {
+    match code {
+        1 => "unacceptable protocol version",
+        2 => "client identifier rejected",
+        3 => "server unavailable",
+        4 => "bad username or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    }
+}
+
+fn connect_packet(client_id: &str, credentials: Option<&Credentials>) -> Vec<u8> {
+    const CLEAN_SESSION: u8 = 0x02;
+    const PASSWORD: u8 = 0x40;
+    const USERNAME: u8 = 0x80;
+    const KEEP_ALIVE_SECS: u16 = 60;
+
+    let mut flags = CLEAN_SESSION;
+    let mut payload = vec![];
+    push_string(&mut payload, client_id);
+    if let Some(Credentials { username, password }) = credentials {
+        flags |= USERNAME;
+        push_string(&mut payload, username);
+        if let Some(password) = password {
+            flags |= PASSWORD;
+            push_string(&mut payload, password);
+        }
+    }
+    let mut body = vec![];
+    push_string(&mut body, "MQTT");
+    // protocol level 4 is MQTT 3.1.1
+    body.extend([4, flags]);
+    body.extend(KEEP_ALIVE_SECS.to_be_bytes());
+    body.extend(payload);
+    packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![];
+    push_string(&mut body, topic);
+    body.extend(payload);
+    packet(PUBLISH_RETAINED, &body)
+}
+
+/// Prefixes the `body` with the fixed header (type and remaining length)
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut remaining = body.len();
+    loop {
+        let byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend(body);
+    packet
+}
+
+/// Appends the length-prefixed UTF-8 `text`
+fn push_string(buffer: &mut Vec<u8>, text: &str) {
+    let length = u16::try_from(text.len()).unwrap_or(u16::MAX);
+    buffer.extend(length.to_be_bytes());
+    buffer.extend(&text.as_bytes()[..usize::from(length)]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish_to_broker() {
+        assert_eq!(packet(PUBLISH_RETAINED, &[7; 200])[..3], [0x31, 0xc8, 0x01]);
+        let broker: Broker = "localhost".parse().expect("broker");
+        assert_eq!(broker.to_string(), "localhost:1883");
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("address").port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut connect = [0; 2];
+            stream.read_exact(&mut connect).expect("read header");
+            let mut body = vec![0; usize::from(connect[1])];
+            stream.read_exact(&mut body).expect("read connect");
+            stream.write_all(&[0x20, 0x02, 0, 0]).expect("acknowledge");
+            let mut rest = vec![];
+            stream.read_to_end(&mut rest).expect("read publish");
+            (body, rest)
+        });
+        let broker: Broker = format!("127.0.0.1:{port}").parse().expect("broker");
+        let credentials = Credentials {
+            username: "user".to_owned(),
+            password: Some("pass".to_owned()),
+        };
+        publish_retained(
+            &broker,
+            "client",
+            Some(&credentials),
+            "music/recent",
+            b"since 2024-11-03",
+            Duration::from_secs(5),
+        )
+        .expect("publish");
+        let (connect, rest) = server.join().expect("server");
+
+        assert_eq!(connect[..8], [0, 4, b'M', b'Q', b'T', b'T', 4, 0xc2]);
+        assert!(connect.ends_with(b"\x00\x04user\x00\x04pass"));
+        let mut expected = vec![0x31, 30, 0, 12];
+        expected.extend(b"music/recent");
+        expected.extend(b"since 2024-11-03");
+        expected.extend([0xe0, 0]);
+        assert_eq!(rest, expected);
+    }
+}
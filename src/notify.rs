@@ -0,0 +1,62 @@
+use std::{
+    ffi::OsStr,
+    os::unix::{
+        ffi::OsStrExt as _,
+        net::{SocketAddr, UnixDatagram},
+    },
+};
+
+/// Sends service state updates to systemd (`sd_notify`), for services with `Type=notify`
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+}
+impl Notifier {
+    /// Connects to the socket given by systemd in `NOTIFY_SOCKET`, if any
+    pub fn from_env() -> Option<std::io::Result<Self>> {
+        let socket_path = std::env::var_os("NOTIFY_SOCKET")?;
+        Some(Self::new(socket_path))
+    }
+    /// Connects to the socket path, or abstract socket name prefixed by `@`
+    pub fn new(socket_path: impl AsRef<OsStr>) -> std::io::Result<Self> {
+        let socket_path = socket_path.as_ref();
+        let address = match socket_path.as_bytes().strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt as _;
+                SocketAddr::from_abstract_name(name)?
+            }
+            _ => SocketAddr::from_pathname(socket_path)?,
+        };
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            address,
+        })
+    }
+    /// Sends the newline-separated `KEY=VALUE` assignments, e.g. `READY=1` or `STATUS=...`
+    pub fn notify(&self, state: &str) -> std::io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.address)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notify_datagram() {
+        let path = std::env::temp_dir().join(format!("notify_test_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).expect("bind test socket");
+
+        let notifier = Notifier::new(&path).expect("connect to test socket");
+        notifier.notify("READY=1\nSTATUS=ok").expect("send");
+
+        let mut buffer = [0; 64];
+        let length = receiver.recv(&mut buffer).expect("receive");
+        std::fs::remove_file(&path).expect("remove test socket");
+        assert_eq!(&buffer[..length], b"READY=1\nSTATUS=ok");
+    }
+}
@@ -0,0 +1,69 @@
+use std::{num::NonZeroUsize, sync::Mutex};
+
+/// Applies `f` to each of the `items` on up to `jobs` threads, returning the results in order
+///
+/// Bounds the concurrent work (e.g. `beet` queries) to avoid overloading the library database.
+pub fn map_bounded<T, U>(items: Vec<T>, jobs: NonZeroUsize, f: impl Fn(T) -> U + Sync) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+{
+    let thread_count = jobs.get().min(items.len());
+    if thread_count <= 1 {
+        return items.into_iter().map(f).collect();
+    }
+
+    let pending = Mutex::new(items.into_iter().enumerate());
+    let next_item = || {
+        pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .next()
+    };
+    let mut results: Vec<(usize, U)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..thread_count)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    while let Some((index, item)) = next_item() {
+                        results.push((index, f(item)));
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| match worker.join() {
+                Ok(results) => results,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect()
+    });
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn bounded_concurrency_preserves_order() {
+        let jobs = NonZeroUsize::new(3).expect("nonzero");
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        let results = map_bounded((0..20).collect(), jobs, |item: u32| {
+            let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now_running, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            running.fetch_sub(1, Ordering::SeqCst);
+            item * 2
+        });
+
+        assert_eq!(results, (0..20).map(|item| item * 2).collect::<Vec<_>>());
+        assert!(max_running.load(Ordering::SeqCst) <= 3);
+    }
+}
@@ -0,0 +1,155 @@
+use regex_lite::Regex;
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+/// Text file (e.g. a playlist template) containing a date token located by a pattern
+#[derive(Debug)]
+pub struct PatchFile {
+    pub path: PathBuf,
+    pub contents: String,
+    pattern: Regex,
+}
+impl PatchFile {
+    /// Reads the file at `path`, checking that the `pattern` matches exactly once
+    pub fn read(path: PathBuf, pattern: Regex) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(&path)?;
+        let patch_file = Self {
+            path,
+            contents,
+            pattern,
+        };
+        patch_file.patched("")?;
+        Ok(patch_file)
+    }
+    /// Returns the contents with the date token replaced by `replacement`
+    pub fn patched(&self, replacement: &str) -> anyhow::Result<String> {
+        replace_token(&self.contents, &self.pattern, replacement)
+    }
+}
+
+/// Replaces the token matched by the `pattern` in the `contents`
+///
+/// The token is the first capture group of the pattern (if any), otherwise the whole match.
+/// The pattern must match exactly once, to avoid editing an unintended part of the file.
+pub fn replace_token(contents: &str, pattern: &Regex, replacement: &str) -> anyhow::Result<String> {
+    let mut matches = pattern.captures_iter(contents);
+    let Some(captures) = matches.next() else {
+        anyhow::bail!("pattern {pattern} does not match")
+    };
+    if matches.next().is_some() {
+        anyhow::bail!("pattern {pattern} matches more than once");
+    }
+    let token = captures
+        .get(1)
+        .or_else(|| captures.get(0))
+        .expect("match includes group 0");
+    let mut patched = String::with_capacity(contents.len() + replacement.len());
+    patched.push_str(&contents[..token.start()]);
+    patched.push_str(replacement);
+    patched.push_str(&contents[token.end()..]);
+    Ok(patched)
+}
+
+/// Describes the changed lines from `old` to `new` as a unified diff hunk (empty if unchanged)
+pub fn diff_lines(old: &str, new: &str) -> String {
+    let old_lines: Vec<_> = old.lines().collect();
+    let new_lines: Vec<_> = new.lines().collect();
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let removed = &old_lines[prefix..old_lines.len() - suffix];
+    let added = &new_lines[prefix..new_lines.len() - suffix];
+    if removed.is_empty() && added.is_empty() {
+        return String::new();
+    }
+
+    let line_number = prefix + 1;
+    let mut diff = format!(
+        "@@ -{line_number},{} +{line_number},{} @@\n",
+        removed.len(),
+        added.len()
+    );
+    for line in removed {
+        diff.push_str(&format!("-{line}\n"));
+    }
+    for line in added {
+        diff.push_str(&format!("+{line}\n"));
+    }
+    diff
+}
+
+/// Replaces the file at `path` with the `contents`, via a temporary file renamed into place
+pub fn write_file(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let mut temp_path = path.to_owned().into_os_string();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "---\ntitle: Recent\nsince: 2024-01-01\n---\nadded:2024-01-01..\n";
+
+    fn pattern(pattern: &str) -> Regex {
+        Regex::new(pattern).expect("valid pattern")
+    }
+
+    #[test]
+    fn replace_capture_group() {
+        let patched = replace_token(
+            TEMPLATE,
+            &pattern(r"added:(\d{4}-\d{2}-\d{2})"),
+            "2024-03-01",
+        )
+        .expect("single match");
+        assert_eq!(
+            patched,
+            "---\ntitle: Recent\nsince: 2024-01-01\n---\nadded:2024-03-01..\n"
+        );
+
+        let patched = replace_token(TEMPLATE, &pattern(r"since: \S+"), "since: 2024-03-01")
+            .expect("single match");
+        assert_eq!(
+            patched,
+            "---\ntitle: Recent\nsince: 2024-03-01\n---\nadded:2024-01-01..\n"
+        );
+    }
+
+    #[test]
+    fn replace_requires_single_match() {
+        let date = pattern(r"\d{4}-\d{2}-\d{2}");
+        let err = replace_token(TEMPLATE, &date, "2024-03-01").expect_err("two matches");
+        assert!(err.to_string().contains("more than once"), "{err}");
+
+        let missing = pattern("until: (.*)");
+        let err = replace_token(TEMPLATE, &missing, "2024-03-01").expect_err("no match");
+        assert!(err.to_string().contains("does not match"), "{err}");
+    }
+
+    #[test]
+    fn diff_changed_lines() {
+        let patched = TEMPLATE.replace("since: 2024-01-01", "since: 2024-03-01");
+        assert_eq!(
+            diff_lines(TEMPLATE, &patched),
+            "@@ -3,1 +3,1 @@\n-since: 2024-01-01\n+since: 2024-03-01\n"
+        );
+        assert_eq!(diff_lines(TEMPLATE, TEMPLATE), "");
+    }
+}
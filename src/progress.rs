@@ -0,0 +1,71 @@
+//! Progress events of long operations (e.g. fetching a large library), written to stderr as JSON
+//! lines for wrapping interfaces to show a progress bar
+//!
+//! Events are only written once [`enable`]d, e.g.
+//! `{"event":"progress","phase":"fetch","items":25000,"elapsed_ms":1500,"done":false}`.
+
+use std::{
+    io::Write as _,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Minimum time between progress events of a phase
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Starts writing progress events, for the rest of the process
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Progress of one phase, reporting the items processed at most every [`INTERVAL`]
+#[derive(Debug)]
+pub struct Progress {
+    phase: &'static str,
+    start: Instant,
+    last_event: Instant,
+    /// Whether events are written (fixed at the start, for a consistent sequence of events)
+    enabled: bool,
+}
+impl Progress {
+    pub fn start(phase: &'static str) -> Self {
+        let now = Instant::now();
+        Self {
+            phase,
+            start: now,
+            last_event: now,
+            enabled: is_enabled(),
+        }
+    }
+    /// Reports the `items` processed so far, if the interval elapsed since the last event
+    pub fn update(&mut self, items: usize) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if now.duration_since(self.last_event) >= INTERVAL {
+            self.last_event = now;
+            self.write(items, false);
+        }
+    }
+    /// Reports the `items` processed by the completed phase
+    pub fn finish(self, items: usize) {
+        if self.enabled {
+            self.write(items, true);
+        }
+    }
+    fn write(&self, items: usize, done: bool) {
+        let Self { phase, start, .. } = self;
+        let elapsed_ms = start.elapsed().as_millis();
+        let line = format!(
+            "{{\"event\":\"progress\",\"phase\":\"{phase}\",\"items\":{items},\"elapsed_ms\":{elapsed_ms},\"done\":{done}}}\n"
+        );
+        // NOTE: progress is best-effort, ignore a closed stderr
+        let _ = std::io::stderr().lock().write_all(line.as_bytes());
+    }
+}
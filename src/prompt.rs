@@ -1,16 +1,216 @@
-use std::io::{stdin, Write as _};
+use std::io::{stdin, BufRead, BufReader, IsTerminal as _, Write as _};
 
 #[derive(Default)]
 pub struct Prompt {
+    /// Source of user input, opened on first use
+    input: Option<Box<dyn BufRead>>,
     buffer: String,
+    /// Whether the input was given, instead of the terminal
+    #[cfg(all(unix, feature = "tty"))]
+    scripted: bool,
+    /// Called with each prompt and the response
+    on_response: Option<ResponseHook>,
+}
+/// Called with a prompt and the response, see [`Prompt::with_response_hook`]
+type ResponseHook = Box<dyn FnMut(&str, &str)>;
+/// Key pressed in response to [`Prompt::read_key`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Left,
+    Right,
+    Enter,
+    /// Abandon the interaction (`q`, `Escape` twice in quick succession, or `Ctrl-C`)
+    Cancel,
+    /// Mark the current item (`b`)
+    Bookmark,
+    Other,
+}
+impl Key {
+    /// Line read as the key without a terminal, see [`Prompt::read_key`]
+    #[cfg(all(unix, feature = "tty"))]
+    fn as_line(self) -> &'static str {
+        match self {
+            Key::Left => "h",
+            Key::Right => "l",
+            Key::Enter => "",
+            Key::Cancel => "q",
+            Key::Bookmark => "b",
+            Key::Other => "?",
+        }
+    }
 }
 impl Prompt {
+    /// Reads the responses from `input`, instead of the terminal (e.g. scripted answers)
+    pub fn with_input(input: impl BufRead + 'static) -> Self {
+        Self {
+            input: Some(Box::new(input)),
+            buffer: String::new(),
+            #[cfg(all(unix, feature = "tty"))]
+            scripted: true,
+            on_response: None,
+        }
+    }
+    /// Calls `on_response` with each prompt and the response (e.g. recording a transcript), with
+    /// key presses as the equivalent line read without a terminal (see [`Self::read_key`])
+    pub fn with_response_hook(self, on_response: impl FnMut(&str, &str) + 'static) -> Self {
+        Self {
+            on_response: Some(Box::new(on_response)),
+            ..self
+        }
+    }
     pub fn read_line(&mut self, prompt: &str) -> anyhow::Result<&str> {
         print!("\n{prompt} ");
         let _ = std::io::stdout().flush();
 
+        let input = match &mut self.input {
+            Some(input) => input,
+            input @ None => input.insert(open_input()),
+        };
+
         self.buffer.clear();
-        stdin().read_line(&mut self.buffer)?;
-        Ok(self.buffer.trim())
+        let bytes_read = input.read_line(&mut self.buffer)?;
+        if bytes_read == 0 {
+            anyhow::bail!("end of input while waiting for a response")
+        }
+        let response = self.buffer.trim();
+        if let Some(on_response) = &mut self.on_response {
+            on_response(prompt, response);
+        }
+        Ok(response)
+    }
+    /// Shows the `status` and reads a single key press, replacing the status on the next call
+    ///
+    /// Without a terminal supporting raw mode, reads a line instead: `h` or `<` for
+    /// [`Key::Left`], `l` or `>` for [`Key::Right`], empty for [`Key::Enter`], and `q` for
+    /// [`Key::Cancel`].
+    pub fn read_key(&mut self, status: &str) -> anyhow::Result<Key> {
+        #[cfg(all(unix, feature = "tty"))]
+        if !self.scripted {
+            if let Ok(mut terminal) = raw::RawTerminal::open() {
+                print!("\r\x1b[K{status} ");
+                let _ = std::io::stdout().flush();
+                let key = terminal.read_key()?;
+                if let Some(on_response) = &mut self.on_response {
+                    on_response(status, key.as_line());
+                }
+                return Ok(key);
+            }
+        }
+        let key = match self.read_line(status)? {
+            "h" | "<" => Key::Left,
+            "l" | ">" => Key::Right,
+            "" => Key::Enter,
+            "q" => Key::Cancel,
+            "b" => Key::Bookmark,
+            _ => Key::Other,
+        };
+        Ok(key)
+    }
+}
+
+#[cfg(all(unix, feature = "tty"))]
+mod raw {
+    use super::{Key, TERMINAL_PATH};
+    use std::{io::Read as _, os::fd::AsRawFd as _};
+
+    /// Time to wait for the rest of an escape sequence (or a second Escape) after an Escape byte
+    const ESCAPE_TIMEOUT_MS: libc::c_int = 300;
+
+    /// Terminal in raw mode (unbuffered, without echo), restored when dropped
+    pub(super) struct RawTerminal {
+        terminal: std::fs::File,
+        original: libc::termios,
+    }
+    impl RawTerminal {
+        pub fn open() -> std::io::Result<Self> {
+            let terminal = std::fs::File::open(TERMINAL_PATH)?;
+            let fd = terminal.as_raw_fd();
+            let mut original = std::mem::MaybeUninit::uninit();
+            // SAFETY: the fd is open for the lifetime of `terminal`, and `original` is only
+            // read after `tcgetattr` succeeds
+            let original = unsafe {
+                if libc::tcgetattr(fd, original.as_mut_ptr()) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                original.assume_init()
+            };
+            let mut raw = original;
+            // NOTE: keep ISIG off to read Ctrl-C as a key, restoring the terminal on exit
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // SAFETY: the fd is open, and `raw` is a valid termios
+            if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(Self { terminal, original })
+        }
+        pub fn read_key(&mut self) -> std::io::Result<Key> {
+            let key = match self.read_byte()? {
+                b'\r' | b'\n' => Key::Enter,
+                b'q' | 0x03 => Key::Cancel,
+                b'h' | b'<' => Key::Left,
+                b'l' | b'>' => Key::Right,
+                b'b' => Key::Bookmark,
+                // escape sequences of the arrow keys, `ESC [ D` and `ESC [ C`
+                0x1b => match self.read_pending_byte()? {
+                    Some(b'[') => match self.read_pending_byte()? {
+                        Some(b'D') => Key::Left,
+                        Some(b'C') => Key::Right,
+                        _ => Key::Other,
+                    },
+                    Some(0x1b) => Key::Cancel,
+                    // a single Escape press
+                    _ => Key::Other,
+                },
+                _ => Key::Other,
+            };
+            Ok(key)
+        }
+        /// Reads the next byte if available within [`ESCAPE_TIMEOUT_MS`], instead of waiting for
+        /// another key press
+        fn read_pending_byte(&mut self) -> std::io::Result<Option<u8>> {
+            let mut poll_fd = libc::pollfd {
+                fd: self.terminal.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: `poll_fd` is a single valid pollfd, for the open fd of `terminal`
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, ESCAPE_TIMEOUT_MS) };
+            match ready {
+                ..=-1 => Err(std::io::Error::last_os_error()),
+                0 => Ok(None),
+                _ => self.read_byte().map(Some),
+            }
+        }
+        fn read_byte(&mut self) -> std::io::Result<u8> {
+            let mut byte = [0];
+            self.terminal.read_exact(&mut byte)?;
+            Ok(byte[0])
+        }
+    }
+    impl Drop for RawTerminal {
+        fn drop(&mut self) {
+            // SAFETY: the fd is still open, and `original` is the termios read on open
+            unsafe {
+                libc::tcsetattr(self.terminal.as_raw_fd(), libc::TCSANOW, &self.original);
+            }
+        }
+    }
+}
+
+/// Opens the terminal for interaction, even when stdin is a pipe (e.g. carrying entries)
+fn open_input() -> Box<dyn BufRead> {
+    if stdin().is_terminal() {
+        return Box::new(stdin().lock());
+    }
+    match std::fs::File::open(TERMINAL_PATH) {
+        Ok(terminal) => Box::new(BufReader::new(terminal)),
+        // no terminal available (e.g. scripted usage), answers are on stdin
+        Err(_) => Box::new(stdin().lock()),
     }
 }
+#[cfg(windows)]
+const TERMINAL_PATH: &str = "CONIN$";
+#[cfg(not(windows))]
+const TERMINAL_PATH: &str = "/dev/tty";
@@ -0,0 +1,308 @@
+use crate::{Date, DateEntry};
+
+/// Integer displayed with thousands separators, e.g. `12,493`
+///
+/// Respects the width and alignment of the format, e.g. `{:>8}`.
+#[derive(Clone, Copy, Debug)]
+pub struct Grouped<T>(pub T);
+impl<T: std::fmt::Display> std::fmt::Display for Grouped<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = self.0.to_string();
+        let (sign, digits) = match digits.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", digits.as_str()),
+        };
+        let mut grouped = String::from(sign);
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        f.pad(&grouped)
+    }
+}
+
+/// Alignment of the cells in a [`Table`] column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Text table, with each column padded to its widest cell
+#[derive(Clone, Debug)]
+pub struct Table {
+    columns: Vec<(String, Align)>,
+    rows: Vec<Vec<String>>,
+}
+impl Table {
+    const SEPARATOR: &'static str = "  ";
+
+    pub fn new<'a>(columns: impl IntoIterator<Item = (&'a str, Align)>) -> Self {
+        let columns = columns
+            .into_iter()
+            .map(|(header, align)| (header.to_owned(), align))
+            .collect();
+        Self {
+            columns,
+            rows: vec![],
+        }
+    }
+    /// Appends a row of cells, one per column (missing cells are blank)
+    pub fn push_row<S: Into<String>>(&mut self, cells: impl IntoIterator<Item = S>) {
+        self.rows.push(cells.into_iter().map(Into::into).collect());
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    fn widths(&self) -> Vec<usize> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(index, (header, _))| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(index))
+                    .chain(std::iter::once(header))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+}
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let widths = self.widths();
+        let headers = self.columns.iter().map(|(header, _)| header);
+        for row in std::iter::once(headers.collect::<Vec<_>>())
+            .chain(self.rows.iter().map(|row| row.iter().collect()))
+        {
+            let mut line = String::new();
+            for (index, ((_, align), width)) in self.columns.iter().zip(&widths).enumerate() {
+                let cell = row.get(index).map_or("", |cell| cell.as_str());
+                if index > 0 {
+                    line.push_str(Self::SEPARATOR);
+                }
+                let padding = " ".repeat(width - cell.chars().count());
+                match align {
+                    Align::Left => {
+                        line.push_str(cell);
+                        line.push_str(&padding);
+                    }
+                    Align::Right => {
+                        line.push_str(&padding);
+                        line.push_str(cell);
+                    }
+                }
+            }
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats the `cells` as a line of CSV (or TSV) with the `delimiter`, quoting cells containing
+/// the delimiter, quotes, or line breaks
+pub fn delimited_line<S: AsRef<str>>(
+    cells: impl IntoIterator<Item = S>,
+    delimiter: char,
+) -> String {
+    let mut line = String::new();
+    for (index, cell) in cells.into_iter().enumerate() {
+        if index > 0 {
+            line.push(delimiter);
+        }
+        let cell = cell.as_ref();
+        if cell.contains([delimiter, '"', '\n', '\r']) {
+            line.push('"');
+            line.push_str(&cell.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(cell);
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Renders the count of `entries` added on each day within `days` of the `boundary` as a
+/// sparkline, with a `|` before the boundary day, e.g. `▁ ▃█|▂  ▁` for a cutoff just after a busy day
+///
+/// Bar heights are relative to the busiest day shown, and days without additions are blank.
+pub fn additions_sparkline(entries: &[DateEntry], boundary: Date, days: u16) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let days = usize::from(days);
+    let first = boundary.add_days(-(days as i64));
+    let mut counts = vec![0_usize; 2 * days + 1];
+    for entry in entries {
+        let offset = usize::try_from(first.days_until(entry.date())).ok();
+        if let Some(count) = offset.and_then(|offset| counts.get_mut(offset)) {
+            *count += 1;
+        }
+    }
+    let busiest = counts.iter().copied().max().unwrap_or(0);
+    let mut sparkline = String::with_capacity(counts.len() + 1);
+    for (offset, &count) in counts.iter().enumerate() {
+        if offset == days {
+            sparkline.push('|');
+        }
+        sparkline.push(match count {
+            0 => ' ',
+            count => BARS[(count * BARS.len()).div_ceil(busiest) - 1],
+        });
+    }
+    sparkline
+}
+
+/// Summarizes the `entries` (e.g. those included by a cutoff) as the top genres and the mix of
+/// formats with their shares, e.g. `rock 40% jazz 25% pop 10% · FLAC 80% MP3 20%`, followed by
+/// the share of compilation items (e.g. `· VA 15%`) if any
+///
+/// Genre fields listing several genres (separated by `,` or `;`) count toward each.
+pub fn genre_format_summary(entries: &[DateEntry]) -> String {
+    const TOP: usize = 3;
+
+    let genres = entries
+        .iter()
+        .filter_map(|entry| entry.genre.as_deref())
+        .flat_map(|genre| genre.split([',', ';']).map(str::trim))
+        .filter(|genre| !genre.is_empty());
+    let formats = entries.iter().filter_map(|entry| entry.format.as_deref());
+    let compilations = entries
+        .iter()
+        .filter(|entry| entry.compilation)
+        .map(|_| "VA");
+    let summaries: Vec<_> = [
+        top_shares(genres, entries.len(), TOP),
+        top_shares(formats, entries.len(), TOP),
+        top_shares(compilations, entries.len(), 1),
+    ]
+    .into_iter()
+    .filter(|summary| !summary.is_empty())
+    .collect();
+    summaries.join(" · ")
+}
+
+/// Lists the `top` most frequent of the `values` with their percentage of the `total`
+fn top_shares<'a>(values: impl Iterator<Item = &'a str>, total: usize, top: usize) -> String {
+    let mut counts: Vec<(&str, usize)> = vec![];
+    for value in values {
+        match counts.iter_mut().find(|(existing, _)| *existing == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    // most frequent first, ties in order of appearance (newest first)
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    counts
+        .iter()
+        .take(top)
+        .map(|(value, count)| format!("{value} {:.0}%", *count as f64 / total as f64 * 100.0))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grouped_digits() {
+        let grouped = |n: i64| Grouped(n).to_string();
+        assert_eq!(grouped(0), "0");
+        assert_eq!(grouped(999), "999");
+        assert_eq!(grouped(1_000), "1,000");
+        assert_eq!(grouped(12_493), "12,493");
+        assert_eq!(grouped(1_234_567), "1,234,567");
+        assert_eq!(grouped(-45_000), "-45,000");
+        assert_eq!(format!("[{:>7}]", Grouped(12_493_usize)), "[ 12,493]");
+    }
+
+    #[test]
+    fn table_alignment() {
+        let mut table = Table::new([
+            ("#", Align::Right),
+            ("name", Align::Left),
+            ("age", Align::Left),
+        ]);
+        table.push_row(["1", "Björk — Post", "3 days ago"]);
+        table.push_row(["10", "A", ""]);
+        table.push_row(["100"]);
+        assert_eq!(
+            table.to_string(),
+            "  #  name          age\n\
+             \x20 1  Björk — Post  3 days ago\n\
+             \x2010  A\n\
+             100\n"
+        );
+    }
+
+    #[test]
+    fn delimited_quoting() {
+        assert_eq!(
+            delimited_line(["1", "Crosby, Stills & Nash", "say \"hi\"", ""], ','),
+            "1,\"Crosby, Stills & Nash\",\"say \"\"hi\"\"\",\n"
+        );
+        assert_eq!(delimited_line(["a, b", "c"], '\t'), "a, b\tc\n");
+    }
+
+    #[test]
+    fn sparkline_around_boundary() {
+        let entries: Vec<_> = [
+            "2024-03-04 10:00:00",
+            "2024-03-02 10:00:00",
+            "2024-03-01 10:00:00",
+            "2024-02-28 10:00:00",
+            "2024-02-28 11:00:00",
+            "2024-02-28 12:00:00",
+            "2024-02-28 13:00:00",
+            "2024-02-20 10:00:00",
+        ]
+        .iter()
+        .map(|timestamp| {
+            DateEntry::try_from(format!("{timestamp} Artist - Album - Title"))
+                .expect("valid entry in test case")
+        })
+        .collect();
+        let boundary = "2024-03-01".parse().expect("valid date");
+
+        assert_eq!(additions_sparkline(&entries, boundary, 3), " █ |▂▂ ▂");
+        assert_eq!(additions_sparkline(&[], boundary, 1), " |  ");
+    }
+
+    #[test]
+    fn genre_format_mix() {
+        let entry = |genre: Option<&str>, format: &str| {
+            let mut entry =
+                DateEntry::try_from("2024-03-01 10:00:00 Artist - Album - Title".to_owned())
+                    .expect("valid entry in test case");
+            entry.genre = genre.map(str::to_owned);
+            entry.format = Some(format.to_owned());
+            entry
+        };
+        let entries = [
+            entry(Some("Jazz"), "FLAC"),
+            entry(Some("Rock; Pop"), "MP3"),
+            entry(Some("Rock"), "FLAC"),
+            entry(None, "FLAC"),
+            entry(Some("Ambient, Jazz"), "FLAC"),
+        ];
+
+        assert_eq!(
+            genre_format_summary(&entries),
+            "Jazz 40% Rock 40% Pop 20% · FLAC 80% MP3 20%"
+        );
+        assert_eq!(genre_format_summary(&entries[3..4]), "FLAC 100%");
+        assert_eq!(genre_format_summary(&[]), "");
+
+        let mut entries = entries;
+        entries[1].compilation = true;
+        assert_eq!(
+            genre_format_summary(&entries[..2]),
+            "Jazz 50% Rock 50% Pop 50% · FLAC 50% MP3 50% · VA 50%"
+        );
+    }
+}
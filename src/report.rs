@@ -0,0 +1,262 @@
+use crate::{timing::Timings, Date, DateEntry, ItemId, ParseFailure, ParsedEntries, UndatedAction};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Details of a run, for diagnosing performance and input problems
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub timings: Timings,
+    /// Input lines skipped by a lenient parse
+    pub parse_failures: Vec<ParseFailure>,
+    /// Candidates of the latest interactive prompt
+    pub candidates: Vec<CandidateReport>,
+    /// Final count differing from the count expected from the fetched entries
+    pub count_mismatch: Option<CountMismatch>,
+    /// Whether the latest parse ignored entries beyond the maximum count
+    pub truncated: bool,
+    /// Entries of the latest parse without a meaningful date
+    pub undated: usize,
+    /// Ids of the [`Self::undated`] entries, e.g. to repair their dates
+    pub undated_ids: Vec<ItemId>,
+    /// Undated entries included by every cutoff (not counted by `beet` queries)
+    pub undated_included: usize,
+}
+/// Candidate breakpoint shown in the interactive prompt
+#[derive(Debug)]
+pub struct CandidateReport {
+    /// Number entered to choose the candidate
+    pub choice: usize,
+    pub label: String,
+    pub cutoff: Date,
+    /// Count of units after the cutoff
+    pub count: usize,
+    /// Oldest album included by the cutoff
+    pub boundary_album: String,
+    pub musicbrainz_url: Option<String>,
+}
+/// Disagreement between the fetched entries and the count query for the same cutoff, e.g. from
+/// a concurrent import or filters that the count query does not match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CountMismatch {
+    /// Count of the fetched entries on or after the cutoff
+    pub expected: usize,
+    /// Count returned by the count query
+    pub actual: usize,
+}
+impl std::fmt::Display for CountMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { expected, actual } = self;
+        write!(
+            f,
+            "counted {actual} items after the cutoff, but the fetched entries include {expected}"
+        )
+    }
+}
+impl RunReport {
+    pub fn to_json(&self) -> serde_json::Value {
+        let Self {
+            timings,
+            parse_failures,
+            candidates,
+            count_mismatch,
+            truncated,
+            undated,
+            undated_ids: _,
+            undated_included: _,
+        } = self;
+        let parse_failures: Vec<_> = parse_failures
+            .iter()
+            .map(|failure| {
+                let ParseFailure {
+                    line_number,
+                    content,
+                    error,
+                } = failure;
+                serde_json::json!({
+                    "line": line_number,
+                    "content": content,
+                    "error": error,
+                })
+            })
+            .collect();
+        let candidates: Vec<_> = candidates
+            .iter()
+            .map(|candidate| {
+                let CandidateReport {
+                    choice,
+                    label,
+                    cutoff,
+                    count,
+                    boundary_album,
+                    musicbrainz_url,
+                } = candidate;
+                serde_json::json!({
+                    "choice": choice,
+                    "label": label,
+                    "cutoff": cutoff.to_string(),
+                    "count": count,
+                    "boundary_album": boundary_album,
+                    "musicbrainz_url": musicbrainz_url,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "timings_ms": timings.to_json(),
+            "parse_failures": parse_failures,
+            "candidates": candidates,
+            "count_mismatch": count_mismatch.map(|CountMismatch { expected, actual }| {
+                serde_json::json!({
+                    "expected": expected,
+                    "actual": actual,
+                })
+            }),
+            "truncated": truncated,
+            "undated": undated,
+        })
+    }
+    /// Records the failures of a (re-)parse, replacing those of any previous parse
+    ///
+    /// Prints a summary of the failures and undated entries (handled by the `undated` action),
+    /// returning the parsed entries.
+    pub fn record_parsed(
+        &mut self,
+        parsed: ParsedEntries,
+        undated: UndatedAction,
+    ) -> Vec<DateEntry> {
+        let ParsedEntries {
+            mut entries,
+            failures,
+            truncated,
+        } = parsed;
+        if let Some(first) = failures.first() {
+            let ParseFailure {
+                line_number, error, ..
+            } = first;
+            let count = failures.len();
+            let plural = if count == 1 { "" } else { "s" };
+            println!(
+                "[skipped {count} line{plural} that failed to parse, first on line {line_number}: {error}]"
+            );
+        }
+        self.parse_failures = failures;
+        self.truncated = truncated;
+        self.undated_ids = entries
+            .iter()
+            .filter(|entry| entry.is_undated())
+            .filter_map(|entry| entry.id)
+            .collect();
+        self.undated = undated.apply(&mut entries);
+        self.undated_included = match undated {
+            UndatedAction::Include => self.undated,
+            UndatedAction::Exclude | UndatedAction::Keep => 0,
+        };
+        if self.undated > 0 {
+            let handling = match undated {
+                UndatedAction::Exclude => "excluded",
+                UndatedAction::Include => "included by every cutoff",
+                UndatedAction::Keep => "kept as the oldest entries",
+            };
+            println!(
+                "[found {} entries without a date (empty or the Unix epoch), {handling}]",
+                self.undated
+            );
+        }
+        entries
+    }
+    pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let contents = serde_json::to_string_pretty(&self.to_json())?;
+        std::fs::write(path, contents + "\n")?;
+        Ok(())
+    }
+}
+
+/// Result of a run, written as a single JSON object for wrapper scripts
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    /// Chosen cutoff, or `None` if the user quit
+    pub cutoff: Option<Date>,
+    /// Number of entries on or after the cutoff
+    pub count: Option<usize>,
+    /// Description of how the cutoff was chosen
+    pub choice: Option<String>,
+    /// Files written (or skipped, for a dry run)
+    pub files: Vec<PathBuf>,
+    pub dry_run: bool,
+}
+impl RunSummary {
+    pub fn to_json(&self) -> serde_json::Value {
+        let Self {
+            cutoff,
+            count,
+            choice,
+            files,
+            dry_run,
+        } = self;
+        let files: Vec<_> = files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        serde_json::json!({
+            "cutoff": cutoff.map(|cutoff| cutoff.to_string()),
+            "count": count,
+            "choice": choice,
+            "files": files,
+            "dry_run": dry_run,
+        })
+    }
+    /// Writes the summary as one line of JSON
+    pub fn write_to(&self, mut writer: impl Write) -> anyhow::Result<()> {
+        let line = serde_json::to_string(&self.to_json())?;
+        writeln!(writer, "{line}")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn summary_line(summary: &RunSummary) -> serde_json::Value {
+        let mut output = vec![];
+        summary.write_to(&mut output).expect("write succeeds");
+        let output = String::from_utf8(output).expect("utf8 output");
+        let line = output.strip_suffix('\n').expect("trailing newline");
+        assert!(!line.contains('\n'), "single line: {output:?}");
+        serde_json::from_str(line).expect("valid json")
+    }
+
+    #[test]
+    fn summary_json_line() {
+        let summary = RunSummary {
+            cutoff: Some("2024-03-01".parse().expect("valid date")),
+            count: Some(42),
+            choice: Some("window 90d".to_owned()),
+            files: vec![PathBuf::from("out.json")],
+            dry_run: false,
+        };
+        assert_eq!(
+            summary_line(&summary),
+            json!({
+                "cutoff": "2024-03-01",
+                "count": 42,
+                "choice": "window 90d",
+                "files": ["out.json"],
+                "dry_run": false,
+            })
+        );
+        assert_eq!(
+            summary_line(&RunSummary::default()),
+            json!({
+                "cutoff": null,
+                "count": null,
+                "choice": null,
+                "files": [],
+                "dry_run": false,
+            })
+        );
+    }
+}
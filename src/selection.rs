@@ -0,0 +1,529 @@
+//! Interactive selection among the candidate breakpoints, as a state machine advanced by lines
+//! of input (from a terminal, a scripted front-end, or a test)
+
+use crate::{
+    prompt::{Key, Prompt},
+    render::Grouped,
+    strategy::Candidate,
+    transitions, CountUnit, Date, DateEntry, Transition, TransitionOptions,
+};
+use anyhow::Context as _;
+use std::{num::NonZeroUsize, str::FromStr};
+
+/// Progress of the interactive selection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelectionState {
+    /// Awaiting a [`Command`] for the candidates shown
+    ShowingCandidates,
+    /// Awaiting the target counts after [`Command::Custom`]
+    AwaitingCustomTargets,
+    /// Awaiting confirmation of the chosen candidate (see
+    /// [`SelectionStateMachine::with_confirmation`])
+    Confirming { choice: NonZeroUsize },
+    /// Selection finished, ignoring further input
+    Done,
+}
+
+/// Result of the interactive selection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Numbered candidate (from 1)
+    Candidate(NonZeroUsize),
+    /// Adjust the numbered candidate one date boundary at a time (driven by key presses, see
+    /// [`adjust_transition`], and [`SelectionStateMachine::reset`] when cancelled)
+    Adjust(NonZeroUsize),
+    /// Explain how the numbered candidate was found (then [`SelectionStateMachine::reset`])
+    Explain(NonZeroUsize),
+    /// Show the candidates for new target counts
+    NewCounts(Vec<usize>),
+    /// Count targets in the unit (or toggle between tracks and albums)
+    Mode(Option<CountUnit>),
+}
+
+/// Response to one line of input, from [`SelectionStateMachine::step`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// Awaiting more input (see [`SelectionStateMachine::prompt`]), after showing the `message`
+    /// (e.g. for invalid input)
+    Awaiting {
+        message: Option<String>,
+    },
+    Selected(Selection),
+    /// Abandon the selection
+    Quit,
+}
+
+/// Interactive selection among a number of candidates
+#[derive(Clone, Debug)]
+pub struct SelectionStateMachine {
+    state: SelectionState,
+    candidate_count: usize,
+    /// Candidate chosen by empty input
+    default_choice: Option<NonZeroUsize>,
+    /// Largest accepted custom target count
+    max_target: usize,
+    confirm: bool,
+}
+impl SelectionStateMachine {
+    /// Starts showing `candidate_count` candidates, accepting custom targets up to `max_target`
+    pub fn new(
+        candidate_count: usize,
+        default_choice: Option<NonZeroUsize>,
+        max_target: usize,
+    ) -> Self {
+        Self {
+            state: SelectionState::ShowingCandidates,
+            candidate_count,
+            default_choice: default_choice.filter(|choice| choice.get() <= candidate_count),
+            max_target,
+            confirm: false,
+        }
+    }
+    /// Requires confirming a chosen candidate before selecting it
+    pub fn with_confirmation(self) -> Self {
+        Self {
+            confirm: true,
+            ..self
+        }
+    }
+    pub fn state(&self) -> &SelectionState {
+        &self.state
+    }
+    /// Returns to showing the candidates, e.g. after a cancelled [`Selection::Adjust`] or a
+    /// [`Selection::Explain`]
+    pub fn reset(&mut self) {
+        self.state = SelectionState::ShowingCandidates;
+    }
+    /// Prompt for the input expected in the current state
+    pub fn prompt(&self) -> String {
+        match &self.state {
+            SelectionState::ShowingCandidates => match self.default_choice {
+                Some(choice) => {
+                    format!("Enter selection [#/Adjust # (to move or bookmark)/Explain #/Custom/Mode/Quit] (Enter for #{choice}):")
+                }
+                None => Command::PROMPT.to_owned(),
+            },
+            SelectionState::AwaitingCustomTargets => {
+                "Enter custom target numbers (space separated):".to_owned()
+            }
+            SelectionState::Confirming { choice } => format!("Use candidate #{choice}? [Y/n]:"),
+            SelectionState::Done => String::new(),
+        }
+    }
+    /// Advances the selection with a line of `input`
+    pub fn step(&mut self, input: &str) -> Output {
+        let input = input.trim();
+        match self.state {
+            SelectionState::ShowingCandidates => self.step_command(input),
+            SelectionState::AwaitingCustomTargets => match self.parse_targets(input) {
+                Ok(new_counts) => self.finish(Selection::NewCounts(new_counts)),
+                Err(err) => {
+                    self.state = SelectionState::ShowingCandidates;
+                    awaiting(format!("invalid custom input {input:?}: {err}"))
+                }
+            },
+            SelectionState::Confirming { choice } => match input.to_lowercase().as_str() {
+                "" | "y" | "yes" => self.finish(Selection::Candidate(choice)),
+                "n" | "no" => {
+                    self.state = SelectionState::ShowingCandidates;
+                    Output::Awaiting { message: None }
+                }
+                _ => awaiting("expected y or n".to_owned()),
+            },
+            SelectionState::Done => Output::Awaiting { message: None },
+        }
+    }
+    fn step_command(&mut self, input: &str) -> Output {
+        let command = match Command::from_str(input) {
+            Ok(command) => command,
+            Err(err) => return awaiting(err.to_string()),
+        };
+        match command {
+            Command::Quit => {
+                self.state = SelectionState::Done;
+                Output::Quit
+            }
+            Command::Mode(unit) => self.finish(Selection::Mode(unit)),
+            Command::Custom => {
+                self.state = SelectionState::AwaitingCustomTargets;
+                Output::Awaiting { message: None }
+            }
+            Command::Number(number) if number.get() <= self.candidate_count => self.choose(number),
+            Command::Adjust(number) if number.get() <= self.candidate_count => {
+                self.finish(Selection::Adjust(number))
+            }
+            Command::Explain(number) if number.get() <= self.candidate_count => {
+                self.finish(Selection::Explain(number))
+            }
+            Command::Number(number) | Command::Adjust(number) | Command::Explain(number) => {
+                awaiting(format!("invalid number {number}"))
+            }
+            Command::Empty => match self.default_choice {
+                Some(choice) => self.choose(choice),
+                None => Output::Awaiting { message: None },
+            },
+        }
+    }
+    fn choose(&mut self, choice: NonZeroUsize) -> Output {
+        if self.confirm {
+            self.state = SelectionState::Confirming { choice };
+            Output::Awaiting { message: None }
+        } else {
+            self.finish(Selection::Candidate(choice))
+        }
+    }
+    fn finish(&mut self, selection: Selection) -> Output {
+        self.state = SelectionState::Done;
+        Output::Selected(selection)
+    }
+    fn parse_targets(&self, input: &str) -> anyhow::Result<Vec<usize>> {
+        let max_target = self.max_target;
+        input
+            .split_whitespace()
+            .map(|token| {
+                let number = token.parse()?;
+                if number > max_target {
+                    anyhow::bail!("{number} exceeds max_entries_hard_limit ({max_target}) command-line argument")
+                } else {
+                    Ok(number)
+                }
+            })
+            .collect()
+    }
+}
+
+fn awaiting(message: String) -> Output {
+    Output::Awaiting {
+        message: Some(message),
+    }
+}
+
+/// Input while showing the candidates
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    Custom,
+    Mode(Option<CountUnit>),
+    Number(NonZeroUsize),
+    /// Move the numbered candidate one date boundary at a time, where `b` bookmarks a boundary
+    /// (only while adjusting, see [`Bookmarks`])
+    Adjust(NonZeroUsize),
+    /// Describe the target, boundary day, query, and output change of the numbered candidate
+    Explain(NonZeroUsize),
+    Empty,
+}
+impl Command {
+    pub const PROMPT: &'static str =
+        "Enter selection [#/Adjust # (to move or bookmark)/Explain #/Custom/Mode/Quit]:";
+}
+impl FromStr for Command {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let input = s.to_lowercase();
+        let (command, argument) = match input.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, Some(argument.trim())),
+            None => (input.as_str(), None),
+        };
+        let result = match (command, argument) {
+            ("q" | "quit" | "exit", None) => Self::Quit,
+            ("c" | "custom", None) => Self::Custom,
+            ("m" | "mode", unit) => Self::Mode(unit.map(str::parse).transpose()?),
+            ("a" | "adjust", Some(number)) => Self::Adjust(number.parse()?),
+            ("e" | "explain", Some(number)) => Self::Explain(number.parse()?),
+            ("b" | "bookmark", _) => {
+                anyhow::bail!("bookmark with b while adjusting a candidate (Adjust #)")
+            }
+            ("", None) => Self::Empty,
+            (input, None) => {
+                if let Ok(number) = input.parse() {
+                    Self::Number(number)
+                } else {
+                    anyhow::bail!("unrecognized command {input:?}")
+                }
+            }
+            (_, Some(_)) => anyhow::bail!("unrecognized command {s:?}"),
+        };
+        Ok(result)
+    }
+}
+
+/// Cutoffs marked while adjusting a candidate (see [`adjust_transition`]), offered as extra
+/// candidates until the selection finishes (e.g. across new target counts or a mode change)
+#[derive(Clone, Debug, Default)]
+pub struct Bookmarks {
+    cutoffs: Vec<Date>,
+}
+impl Bookmarks {
+    /// Marks the `cutoff`, returning `false` if already marked
+    pub fn insert(&mut self, cutoff: Date) -> bool {
+        let inserted = !self.cutoffs.contains(&cutoff);
+        if inserted {
+            self.cutoffs.push(cutoff);
+        }
+        inserted
+    }
+    pub fn len(&self) -> usize {
+        self.cutoffs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cutoffs.is_empty()
+    }
+    /// Candidates labeled "bookmark" for the marked cutoffs (in the order marked) among the
+    /// transitions of the `entries`, except the cutoffs of the `offered` candidates
+    pub fn candidates<'a>(
+        &self,
+        entries: &'a [DateEntry],
+        options: TransitionOptions<'_>,
+        offered: &[Date],
+    ) -> Vec<Candidate<'a>> {
+        self.cutoffs
+            .iter()
+            .filter(|cutoff| !offered.contains(cutoff))
+            .filter_map(|&cutoff| {
+                let transition =
+                    transitions(entries, options).find(|transition| transition.cutoff == cutoff)?;
+                Some(Candidate {
+                    label: "bookmark".to_owned(),
+                    target: None,
+                    transition,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Transitions reachable when adjusting a candidate
+#[derive(Clone, Copy)]
+pub struct Adjustable<'a> {
+    pub entries: &'a [DateEntry],
+    pub running_counts: &'a [usize],
+    pub options: TransitionOptions<'a>,
+}
+
+/// Moves from the `start` transition one date boundary at a time (with the arrow keys),
+/// returning the transition chosen with Enter, or `None` when cancelled
+///
+/// The cutoffs of the boundaries marked with `b` are added to the `bookmarks`.
+pub fn adjust_transition<'a>(
+    prompt: &mut Prompt,
+    start: &Transition<'_>,
+    adjustable: Adjustable<'a>,
+    bookmarks: &mut Bookmarks,
+) -> anyhow::Result<Option<Transition<'a>>> {
+    let Adjustable {
+        entries,
+        running_counts,
+        options,
+    } = adjustable;
+    let today = Date::today();
+    let mut all: Vec<_> = transitions(entries, options).collect();
+    let mut position = all
+        .iter()
+        .position(|transition| transition.index == start.index)
+        .context("adjusted candidate is not a transition")?;
+    let unit = options.unit.name();
+    println!("[← fewer {unit}, → more {unit}, b to bookmark as a candidate, Enter to choose, q to cancel]");
+    loop {
+        let transition = &all[position];
+        let (album_artist, album) = transition.included.album_key();
+        let status = format!(
+            "{} {unit}, cutoff {} ({}), boundary album {album_artist} - {album}",
+            Grouped(running_counts[transition.index]),
+            options.boundary.describe(transition.cutoff),
+            transition.cutoff.describe_ago(today),
+        );
+        match prompt.read_key(&status)? {
+            Key::Left => position = position.saturating_sub(1),
+            Key::Right => position = (position + 1).min(all.len() - 1),
+            Key::Enter => {
+                println!();
+                return Ok(Some(all.swap_remove(position)));
+            }
+            Key::Cancel => {
+                println!();
+                return Ok(None);
+            }
+            Key::Bookmark => {
+                bookmarks.insert(transition.cutoff);
+                println!("\n[bookmarked {album_artist} - {album}, offered as a candidate]");
+            }
+            Key::Other => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choice(number: usize) -> NonZeroUsize {
+        NonZeroUsize::new(number).expect("nonzero")
+    }
+
+    #[test]
+    fn select_candidates() {
+        let mut machine = SelectionStateMachine::new(3, Some(choice(2)), 100);
+        assert_eq!(
+            machine.prompt(),
+            "Enter selection [#/Adjust # (to move or bookmark)/Explain #/Custom/Mode/Quit] (Enter for #2):"
+        );
+        assert_eq!(
+            machine.step("7"),
+            Output::Awaiting {
+                message: Some("invalid number 7".to_owned())
+            }
+        );
+        assert_eq!(
+            machine.step("bogus"),
+            Output::Awaiting {
+                message: Some(r#"unrecognized command "bogus""#.to_owned())
+            }
+        );
+        assert_eq!(
+            machine.step(""),
+            Output::Selected(Selection::Candidate(choice(2)))
+        );
+        assert_eq!(machine.state(), &SelectionState::Done);
+
+        let mut machine = SelectionStateMachine::new(3, None, 100);
+        assert_eq!(
+            machine.step("a 1"),
+            Output::Selected(Selection::Adjust(choice(1)))
+        );
+        machine.reset();
+        assert_eq!(
+            machine.step("explain 3"),
+            Output::Selected(Selection::Explain(choice(3)))
+        );
+        machine.reset();
+        assert_eq!(
+            machine.step("e 4"),
+            Output::Awaiting {
+                message: Some("invalid number 4".to_owned())
+            }
+        );
+        assert_eq!(machine.step("q"), Output::Quit);
+
+        let mut machine = SelectionStateMachine::new(3, None, 100);
+        assert!(matches!(
+            machine.step("b"),
+            Output::Awaiting { message: Some(message) } if message.contains("Adjust #")
+        ));
+    }
+
+    #[test]
+    fn custom_targets() {
+        let mut machine = SelectionStateMachine::new(3, None, 100);
+        assert_eq!(machine.step("c"), Output::Awaiting { message: None });
+        assert_eq!(machine.state(), &SelectionState::AwaitingCustomTargets);
+        assert!(matches!(
+            machine.step("20 500"),
+            Output::Awaiting { message: Some(_) }
+        ));
+        assert_eq!(machine.state(), &SelectionState::ShowingCandidates);
+
+        machine.step("custom");
+        assert_eq!(
+            machine.step(" 20  40 "),
+            Output::Selected(Selection::NewCounts(vec![20, 40]))
+        );
+    }
+
+    #[test]
+    fn confirm_choice() {
+        let mut machine = SelectionStateMachine::new(3, None, 100).with_confirmation();
+        assert_eq!(machine.step("3"), Output::Awaiting { message: None });
+        assert_eq!(machine.prompt(), "Use candidate #3? [Y/n]:");
+        assert_eq!(machine.step("n"), Output::Awaiting { message: None });
+        assert_eq!(machine.state(), &SelectionState::ShowingCandidates);
+
+        machine.step("1");
+        assert_eq!(
+            machine.step("Y"),
+            Output::Selected(Selection::Candidate(choice(1)))
+        );
+    }
+
+    #[test]
+    fn adjust_bookmarks() {
+        let entries: Vec<_> = [
+            "2024-03-05",
+            "2024-03-04",
+            "2024-03-04",
+            "2024-03-02",
+            "2024-03-01",
+        ]
+        .iter()
+        .map(|date| {
+            DateEntry::try_from(format!("{date} 10:00:00 Artist - Album - Title"))
+                .expect("valid entry in test case")
+        })
+        .collect();
+        let options = TransitionOptions::default();
+        let running_counts = options.running_counts(&entries);
+        let adjustable = Adjustable {
+            entries: &entries,
+            running_counts: &running_counts,
+            options,
+        };
+        let all: Vec<_> = transitions(&entries, options).collect();
+        let cutoffs: Vec<_> = all.iter().map(|transition| transition.cutoff).collect();
+        let date = |date: &str| -> Date { date.parse().expect("valid date in test case") };
+        assert_eq!(
+            cutoffs,
+            ["2024-03-05", "2024-03-04", "2024-03-02"].map(date)
+        );
+        let scripted = |answers: &[&str]| {
+            let input: String = answers.iter().map(|answer| format!("{answer}\n")).collect();
+            Prompt::with_input(std::io::Cursor::new(input))
+        };
+
+        // bookmarking the same boundary twice, then cancelling
+        let mut bookmarks = Bookmarks::default();
+        let mut prompt = scripted(&["l", "b", "b", "l", "b", "q"]);
+        let adjusted = adjust_transition(&mut prompt, &all[0], adjustable, &mut bookmarks)
+            .expect("scripted keys");
+        assert!(adjusted.is_none());
+        assert_eq!(bookmarks.len(), 2);
+
+        // offered again, except the cutoffs of the candidates already offered
+        let offered = |bookmarks: &Bookmarks, options, candidates: &[Date]| {
+            let offered = bookmarks.candidates(&entries, options, candidates);
+            offered
+                .into_iter()
+                .map(|candidate| {
+                    (
+                        candidate.label,
+                        candidate.target,
+                        candidate.transition.cutoff,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            offered(&bookmarks, options, &[date("2024-03-02")]),
+            [("bookmark".to_owned(), None, date("2024-03-04"))]
+        );
+
+        // kept for the candidates of another unit (after Mode) or new target counts
+        let albums = TransitionOptions {
+            unit: CountUnit::Albums,
+            ..options
+        };
+        assert_eq!(
+            offered(&bookmarks, albums, &[]),
+            [
+                ("bookmark".to_owned(), None, date("2024-03-04")),
+                ("bookmark".to_owned(), None, date("2024-03-02")),
+            ]
+        );
+
+        // bookmarking an existing boundary, then choosing it
+        let mut prompt = scripted(&["l", "b", ""]);
+        let adjusted = adjust_transition(&mut prompt, &all[0], adjustable, &mut bookmarks)
+            .expect("scripted keys")
+            .expect("chosen");
+        assert_eq!(adjusted.cutoff, date("2024-03-04"));
+        assert_eq!(bookmarks.len(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{Date, DateEntry};
+use anyhow::Context as _;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Entries fetched for an interactive run, saved to resume an interrupted run without re-querying
+///
+/// The candidates are regenerated from the entries, so resuming with the same arguments shows the
+/// same candidates.
+#[derive(Debug)]
+pub struct Session {
+    /// Date the entries were fetched
+    pub fetched: Date,
+    /// Limit on the number of fetched entries
+    pub max_entries: usize,
+    pub entries: Vec<DateEntry>,
+}
+impl Session {
+    pub fn to_json(&self) -> Value {
+        let Self {
+            fetched,
+            max_entries,
+            entries,
+        } = self;
+        let entries: Vec<_> = entries.iter().map(entry_to_json).collect();
+        json!({
+            "fetched": fetched.to_string(),
+            "max_entries": max_entries,
+            "entries": entries,
+        })
+    }
+    pub fn from_json(value: &Value) -> anyhow::Result<Self> {
+        let fetched = value["fetched"]
+            .as_str()
+            .context("missing fetched date")?
+            .parse()?;
+        let max_entries = value["max_entries"]
+            .as_u64()
+            .and_then(|max_entries| usize::try_from(max_entries).ok())
+            .context("missing max_entries")?;
+        let entries = value["entries"]
+            .as_array()
+            .context("missing entries")?
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                entry_from_json(entry).with_context(|| format!("session entry {}", index + 1))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            fetched,
+            max_entries,
+            entries,
+        })
+    }
+    /// Loads the session from the file, if it exists
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => Err(e)?,
+        };
+        let value: Value = serde_json::from_str(&contents)?;
+        Self::from_json(&value).map(Some)
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let contents = serde_json::to_string(&self.to_json())?;
+        std::fs::write(path, contents + "\n")?;
+        Ok(())
+    }
+    /// Removes the session file, if it exists
+    pub fn remove(path: impl AsRef<Path>) -> anyhow::Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)?,
+            _ => Ok(()),
+        }
+    }
+}
+
+fn entry_to_json(entry: &DateEntry) -> Value {
+    let DateEntry {
+        id,
+        timestamp,
+        album_artist,
+        album,
+        artist,
+        title,
+        filesize,
+        mb_albumid,
+        genre,
+        format,
+        compilation,
+        play_count,
+        extra,
+    } = entry;
+    json!({
+        "id": id,
+        "timestamp": timestamp.to_string(),
+        "albumartist": album_artist,
+        "album": album,
+        "artist": artist,
+        "title": title,
+        "filesize": filesize,
+        "mb_albumid": mb_albumid,
+        "genre": genre,
+        "format": format,
+        "compilation": compilation,
+        "play_count": play_count,
+        "extra": extra,
+    })
+}
+fn entry_from_json(value: &Value) -> anyhow::Result<DateEntry> {
+    let field = |name: &str| {
+        value[name]
+            .as_str()
+            .map(str::to_owned)
+            .with_context(|| format!("missing field {name}"))
+    };
+    Ok(DateEntry {
+        id: value["id"].as_u64(),
+        timestamp: field("timestamp")?.parse()?,
+        album_artist: field("albumartist")?,
+        album: field("album")?,
+        artist: field("artist")?,
+        title: field("title")?,
+        filesize: value["filesize"].as_u64(),
+        mb_albumid: value["mb_albumid"].as_str().map(str::to_owned),
+        genre: value["genre"].as_str().map(str::to_owned),
+        format: value["format"].as_str().map(str::to_owned),
+        compilation: value["compilation"].as_bool().unwrap_or_default(),
+        play_count: value["play_count"].as_u64(),
+        extra: match value["extra"].as_array() {
+            Some(extra) => extra
+                .iter()
+                .map(|value| {
+                    value
+                        .as_str()
+                        .map(str::to_owned)
+                        .context("expected a string")
+                })
+                .collect::<anyhow::Result<_>>()
+                .context("invalid field extra")?,
+            None => vec![],
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_roundtrip() {
+        let entry = |line: &str| DateEntry::parse_fields(line).expect("valid entry in test case");
+        let session = Session {
+            fetched: "2024-03-05".parse().expect("valid date"),
+            max_entries: 400,
+            entries: vec![
+                entry("12\t2024-03-02 10:11:12\tVarious\tMix\tArtist\tTitle 1\t1000\tmbid"),
+                DateEntry {
+                    id: None,
+                    ..entry("7\t2024-03-01 09:00:00\t\t\tArtist\tSingle\t\t")
+                },
+            ],
+        };
+
+        let restored = Session::from_json(&session.to_json()).expect("valid session");
+
+        assert_eq!(restored.to_json(), session.to_json());
+        assert_eq!(restored.entries[0].filesize, Some(1000));
+        assert_eq!(restored.entries[0].mb_albumid.as_deref(), Some("mbid"));
+        assert_eq!(restored.entries[1].id, None);
+        assert!(Session::from_json(&json!({"fetched": "2024-03-05"})).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+/// Small seedable pseudo-random generator (SplitMix64), sufficient for playlist shuffling
+pub struct Rng {
+    state: u64,
+}
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Uniform value in the open interval (0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        // 53 bits of precision, offset by half a step to exclude zero
+        ((self.next_u64() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+}
+
+/// Seed derived from the current time, for runs that don't request a reproducible order
+pub fn seed_from_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+/// Randomly orders the `items`, where items with a larger weight (by index) tend to be earlier
+///
+/// Uses the Efraimidis-Spirakis method: each item is sorted by the key `u^(1/weight)` for a
+/// uniform random `u`. Non-positive weights are treated as the smallest positive weight.
+pub fn weighted_shuffle<T>(
+    items: Vec<T>,
+    rng: &mut Rng,
+    weight_fn: impl Fn(usize) -> f64,
+) -> Vec<T> {
+    let mut keyed: Vec<(f64, T)> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let weight = weight_fn(index).max(f64::MIN_POSITIVE);
+            let key = rng.next_f64().powf(1.0 / weight);
+            (key, item)
+        })
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_shuffle_is_reproducible_permutation() {
+        let shuffle = |seed| weighted_shuffle((0..20).collect(), &mut Rng::new(seed), |_| 1.0);
+
+        let first: Vec<u32> = shuffle(7);
+        assert_eq!(first, shuffle(7));
+        assert_ne!(first, (0..20).collect::<Vec<_>>());
+
+        let mut sorted = first;
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{Date, DateEntry};
+
+/// Average number of entries added per day, from the oldest of the `entries` through `today`
+///
+/// Returns `None` without entries, or when the entries are all from the future.
+pub fn add_rate(entries: &[DateEntry], today: Date) -> Option<f64> {
+    let oldest = entries.iter().map(DateEntry::date).min()?;
+    let span_days = oldest.days_until(today) + 1;
+    (span_days > 0).then(|| entries.len() as f64 / span_days as f64)
+}
+
+/// Projected count after a cutoff on a future date
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProjectedCount {
+    pub date: Date,
+    pub count: f64,
+}
+
+/// Projects the count weekly for `weeks`, starting from `current_count` today and growing by
+/// `rate_per_day` entries
+pub fn project_weekly(
+    current_count: usize,
+    rate_per_day: f64,
+    today: Date,
+    weeks: u32,
+) -> Vec<ProjectedCount> {
+    (0..=weeks)
+        .map(|week| {
+            let days = 7 * i64::from(week);
+            ProjectedCount {
+                date: today.add_days(days),
+                count: current_count as f64 + rate_per_day * days as f64,
+            }
+        })
+        .collect()
+}
+
+/// Date when the projected count first exceeds `review_count` (today, if already exceeded)
+///
+/// Returns `None` when the count is not growing.
+pub fn review_date(
+    current_count: usize,
+    rate_per_day: f64,
+    today: Date,
+    review_count: usize,
+) -> Option<Date> {
+    if current_count > review_count {
+        return Some(today);
+    }
+    if rate_per_day <= 0.0 {
+        return None;
+    }
+    let remaining = (review_count - current_count) as f64;
+    // first whole day strictly exceeding the review count
+    let days = (remaining / rate_per_day).floor() as i64 + 1;
+    Some(today.add_days(days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> Date {
+        s.parse().expect("valid date in test case")
+    }
+
+    #[test]
+    fn projection_and_review() {
+        let entries: Vec<_> = ["2024-03-10", "2024-03-05", "2024-03-01"]
+            .iter()
+            .map(|date| {
+                DateEntry::try_from(format!("{date} 10:00:00 Artist - Album - Title"))
+                    .expect("valid entry in test case")
+            })
+            .collect();
+        let today = date("2024-03-12");
+
+        let rate = add_rate(&entries, today).expect("rate for entries");
+        assert_eq!(rate, 0.25);
+
+        let projected = project_weekly(10, rate, today, 2);
+        let projected: Vec<_> = projected
+            .iter()
+            .map(|projected| (projected.date.to_string(), projected.count))
+            .collect();
+        assert_eq!(
+            projected,
+            [
+                ("2024-03-12".to_owned(), 10.0),
+                ("2024-03-19".to_owned(), 11.75),
+                ("2024-03-26".to_owned(), 13.5),
+            ]
+        );
+
+        assert_eq!(review_date(10, rate, today, 12), Some(date("2024-03-21")));
+        assert_eq!(review_date(13, rate, today, 12), Some(today));
+        assert_eq!(review_date(10, 0.0, today, 12), None);
+    }
+}
@@ -0,0 +1,701 @@
+//! Destinations of the chosen cutoff, sharing the preview and dry-run handling
+//!
+//! Each [`OutputSink`] in [`Sinks`] prepares its change before any sink writes, so that a sink
+//! failing to prepare (e.g. a template missing a value) leaves every destination untouched.
+
+use crate::{
+    date::{Boundary, Date},
+    render::Grouped,
+    template::Template,
+    CountUnit,
+};
+use anyhow::Context as _;
+use std::path::{Path, PathBuf};
+
+/// Cutoff chosen by a run, as written by each sink
+#[derive(Clone, Debug)]
+pub struct CutoffOutput<'a> {
+    /// Earliest date included
+    pub earliest: Date,
+    /// Meaning of the stored cutoff (see [`Self::stored`])
+    pub boundary: Boundary,
+    /// Count of units on or after the cutoff
+    pub count: usize,
+    pub unit: CountUnit,
+    /// Description of how the cutoff was chosen
+    pub choice: &'a str,
+    /// Command line listing the items on or after the cutoff, when querying beets
+    pub query: Option<String>,
+}
+impl CutoffOutput<'_> {
+    /// Cutoff date as stored, per the boundary semantics
+    pub fn stored(&self) -> Date {
+        self.boundary.cutoff(self.earliest)
+    }
+}
+
+/// Destination of the chosen cutoff
+pub trait OutputSink {
+    /// Destination shown in messages, e.g. the file path
+    fn destination(&self) -> String;
+    /// File written by the sink, if any (listed in the run summary)
+    fn path(&self) -> Option<&Path>;
+    /// Computes the change for the `output` without writing, returning a preview of it (empty
+    /// when there is nothing to show)
+    fn prepare(&mut self, output: &CutoffOutput<'_>) -> anyhow::Result<String>;
+    /// Writes the change computed by [`Self::prepare`]
+    fn write(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// Registry of the sinks written by a run
+#[derive(Default)]
+pub struct Sinks<'a> {
+    sinks: Vec<Box<dyn OutputSink + 'a>>,
+}
+impl<'a> Sinks<'a> {
+    pub fn push(&mut self, sink: impl OutputSink + 'a) {
+        self.sinks.push(Box::new(sink));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+    /// Prepares the change of each sink for the `output`, failing before any sink writes
+    pub fn prepare(self, output: &CutoffOutput<'_>) -> anyhow::Result<PreparedSinks<'a>> {
+        let prepared = self
+            .sinks
+            .into_iter()
+            .map(|mut sink| {
+                let preview = sink
+                    .prepare(output)
+                    .with_context(|| format!("preparing {}", sink.destination()))?;
+                Ok((sink, preview))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(PreparedSinks { prepared })
+    }
+}
+
+/// Sinks with their changes computed, see [`Sinks::prepare`]
+pub struct PreparedSinks<'a> {
+    prepared: Vec<(Box<dyn OutputSink + 'a>, String)>,
+}
+impl PreparedSinks<'_> {
+    /// Shows the preview of each change, and writes it unless a `dry_run`
+    ///
+    /// Returns the files written (or skipped, for a dry run).
+    pub fn write(self, dry_run: bool) -> anyhow::Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        for (sink, preview) in self.prepared {
+            let destination = sink.destination();
+            files.extend(sink.path().map(Path::to_owned));
+            print!("{preview}");
+            if dry_run {
+                println!("[dry-run] skipped writing {destination}");
+            } else {
+                sink.write()
+                    .with_context(|| format!("writing {destination}"))?;
+                println!("Wrote {destination}");
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Text file rendered from a [`Template`]
+pub struct TemplateSink {
+    template: Template,
+    path: PathBuf,
+    rendered: String,
+}
+impl TemplateSink {
+    /// Names of the placeholders substituted in the template
+    pub const PLACEHOLDERS: &'static [&'static str] = &[
+        "cutoff_date",
+        "final_count",
+        "query",
+        "choice",
+        "generated_date",
+    ];
+
+    /// Renders the `template` to the `path`
+    pub fn new(template: Template, path: PathBuf) -> Self {
+        Self {
+            template,
+            path,
+            rendered: String::new(),
+        }
+    }
+}
+impl OutputSink for TemplateSink {
+    fn destination(&self) -> String {
+        self.path.display().to_string()
+    }
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+    fn prepare(&mut self, output: &CutoffOutput<'_>) -> anyhow::Result<String> {
+        let mut values = vec![
+            ("cutoff_date", output.stored().to_string()),
+            ("final_count", output.count.to_string()),
+            ("choice", output.choice.to_owned()),
+            ("generated_date", Date::today().to_string()),
+        ];
+        if let Some(query) = &output.query {
+            values.push(("query", query.clone()));
+        }
+        self.rendered = self.template.render(&values)?;
+        Ok(self.rendered.clone())
+    }
+    fn write(self: Box<Self>) -> anyhow::Result<()> {
+        std::fs::write(&self.path, &self.rendered)?;
+        Ok(())
+    }
+}
+
+/// Shell script running the query for the items on or after the cutoff
+pub struct ScriptSink {
+    path: PathBuf,
+    script: String,
+}
+impl ScriptSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            script: String::new(),
+        }
+    }
+}
+impl OutputSink for ScriptSink {
+    fn destination(&self) -> String {
+        format!("script {}", self.path.display())
+    }
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+    fn prepare(&mut self, output: &CutoffOutput<'_>) -> anyhow::Result<String> {
+        let query = output
+            .query
+            .as_ref()
+            .context("missing beet_command for the emitted script")?;
+        self.script = [
+            "#!/bin/sh".to_owned(),
+            format!("# generated by beet_smart_cutoff on {}", Date::today()),
+            format!("# {}", output.choice),
+            format!(
+                "# {} entries {}",
+                Grouped(output.count),
+                output.boundary.describe(output.earliest)
+            ),
+            format!("exec {query} \"$@\""),
+        ]
+        .map(|line| line + "\n")
+        .concat();
+        Ok(self.script.clone())
+    }
+    /// Writes the script executable by the owner (and readable by all)
+    fn write(self: Box<Self>) -> anyhow::Result<()> {
+        std::fs::write(&self.path, &self.script)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+/// Date token replaced in place in a text file (see [`crate::patch`])
+#[cfg(feature = "patch")]
+pub struct PatchFileSink {
+    file: crate::patch::PatchFile,
+    patched: String,
+}
+#[cfg(feature = "patch")]
+impl PatchFileSink {
+    pub fn new(file: crate::patch::PatchFile) -> Self {
+        Self {
+            file,
+            patched: String::new(),
+        }
+    }
+}
+#[cfg(feature = "patch")]
+impl OutputSink for PatchFileSink {
+    fn destination(&self) -> String {
+        self.file.path.display().to_string()
+    }
+    fn path(&self) -> Option<&Path> {
+        Some(&self.file.path)
+    }
+    fn prepare(&mut self, output: &CutoffOutput<'_>) -> anyhow::Result<String> {
+        self.patched = self.file.patched(&output.stored().to_string())?;
+        Ok(crate::patch::diff_lines(&self.file.contents, &self.patched))
+    }
+    fn write(self: Box<Self>) -> anyhow::Result<()> {
+        crate::patch::write_file(&self.file.path, &self.patched)
+    }
+}
+
+/// Key of the JSON state file, with the changes recorded in the audit log (if any)
+#[cfg(feature = "json")]
+pub struct JsonFileSink {
+    json_file: crate::json::JsonFile,
+    key: String,
+    on_existing: crate::json::OnExisting,
+    nesting: Option<(crate::json::Nesting, crate::json::OnNestingViolation)>,
+    last_run: Option<crate::json::LastRun>,
+    /// Cutoffs (earliest included dates) written to other keys along with the key
+    extra: Vec<(String, Date)>,
+    /// Other values (e.g. a query) written along with the key
+    text: Vec<(String, String)>,
+    /// Whether to write the count and update time at the sibling keys (see
+    /// [`Self::with_metadata`])
+    metadata: bool,
+    audit_log: Option<PathBuf>,
+    /// Whether to lock the file while preparing and writing, see [`Self::with_write_lock`]
+    write_lock: bool,
+    /// Lock held from preparing until written
+    lock: Option<std::fs::File>,
+    prepared: Option<(crate::JsonMap, Vec<crate::audit::AuditEntry>)>,
+}
+#[cfg(feature = "json")]
+impl JsonFileSink {
+    /// Writes the cutoff at the `key` of the `json_file`, handling an existing value per the
+    /// `on_existing`
+    pub fn new(
+        json_file: crate::json::JsonFile,
+        key: String,
+        on_existing: crate::json::OnExisting,
+    ) -> Self {
+        Self {
+            json_file,
+            key,
+            on_existing,
+            nesting: None,
+            last_run: None,
+            extra: vec![],
+            text: vec![],
+            metadata: false,
+            audit_log: None,
+            write_lock: false,
+            lock: None,
+            prepared: None,
+        }
+    }
+    /// Checks (or fixes) the other stored cutoffs of the `nesting`
+    pub fn with_nesting(
+        self,
+        nesting: Option<(crate::json::Nesting, crate::json::OnNestingViolation)>,
+    ) -> Self {
+        Self { nesting, ..self }
+    }
+    /// Stores the `last_run` selection, for preloading the next run
+    pub fn with_last_run(self, last_run: Option<crate::json::LastRun>) -> Self {
+        Self { last_run, ..self }
+    }
+    /// Also writes the `extra` cutoffs (earliest included dates) at their keys
+    pub fn with_extra(self, extra: Vec<(String, Date)>) -> Self {
+        Self { extra, ..self }
+    }
+    /// Also writes the `text` values at their keys
+    pub fn with_text(self, text: Vec<(String, String)>) -> Self {
+        Self { text, ..self }
+    }
+    /// Also writes the count after the cutoff at `KEY_count` and the time of the update (UTC) at
+    /// `KEY_updated`, replacing their previous values, when `metadata` is set
+    pub fn with_metadata(self, metadata: bool) -> Self {
+        Self { metadata, ..self }
+    }
+    /// Appends the changes to the `audit_log` after writing
+    pub fn with_audit_log(self, audit_log: Option<PathBuf>) -> Self {
+        Self { audit_log, ..self }
+    }
+    /// Locks the file from preparing until written when `write_lock` is set, re-reading it to
+    /// keep the changes of concurrent runs (e.g. other profiles writing other keys)
+    pub fn with_write_lock(self, write_lock: bool) -> Self {
+        Self { write_lock, ..self }
+    }
+}
+#[cfg(feature = "json")]
+impl OutputSink for JsonFileSink {
+    fn destination(&self) -> String {
+        self.json_file.path.display().to_string()
+    }
+    fn path(&self) -> Option<&Path> {
+        Some(&self.json_file.path)
+    }
+    fn prepare(&mut self, output: &CutoffOutput<'_>) -> anyhow::Result<String> {
+        use crate::json;
+
+        if self.write_lock && self.lock.is_none() {
+            self.lock = Some(json::lock_writes(&self.json_file.path)?);
+            self.json_file = self.json_file.reread()?;
+        }
+        let mut map = self.json_file.map.clone().unwrap_or_default();
+        if let Some(last_run) = self.last_run.clone() {
+            json::insert_last_run(&mut map, self.key.clone(), last_run)?;
+        }
+        let cutoff = output.stored();
+        let extra = self
+            .extra
+            .iter()
+            .map(|(key, earliest)| (key.clone(), output.boundary.cutoff(*earliest)));
+        let fixes = json::check_nesting(self.nesting.as_ref(), &map, &self.key, cutoff)?;
+        let mut changes = vec![];
+        let mut preview = String::new();
+        for (key, new, final_count) in extra
+            .chain(fixes)
+            .map(|(key, cutoff)| (key, cutoff.to_string(), None))
+            .chain(
+                self.text
+                    .iter()
+                    .cloned()
+                    .map(|(key, text)| (key, text, None)),
+            )
+            .chain([(self.key.clone(), cutoff.to_string(), Some(output.count))])
+        {
+            let old = json::latest_value(&map, &key).cloned();
+            let old_text = old
+                .as_ref()
+                .map_or_else(|| "(unset)".to_owned(), ToString::to_string);
+            preview.push_str(&format!("{key:?}: {old_text} → {new:?}\n"));
+            changes.push(crate::audit::AuditEntry {
+                file: self.json_file.path.clone(),
+                key: key.clone(),
+                old,
+                new: new.clone(),
+                final_count,
+            });
+            json::insert_value(&mut map, key, new, self.on_existing)?;
+        }
+        if self.metadata {
+            let key = &self.key;
+            for (key, new) in [
+                (
+                    format!("{key}_count"),
+                    serde_json::Value::from(output.count),
+                ),
+                (
+                    format!("{key}_updated"),
+                    serde_json::Value::from(crate::Timestamp::now().to_string()),
+                ),
+            ] {
+                preview.push_str(&format!("{key:?}: {new}\n"));
+                map.insert(key, new);
+            }
+        }
+        self.prepared = Some((map, changes));
+        Ok(preview)
+    }
+    fn write(self: Box<Self>) -> anyhow::Result<()> {
+        let Self {
+            json_file,
+            audit_log,
+            lock,
+            prepared,
+            ..
+        } = *self;
+        let (map, changes) = prepared.context("JSON change not prepared")?;
+        json_file.write(map)?;
+        if let Some(audit_log) = &audit_log {
+            crate::audit::append(audit_log, &changes)
+                .with_context(|| format!("appending to audit log {audit_log:?}"))?;
+        }
+        drop(lock);
+        Ok(())
+    }
+}
+
+/// Webhook receiving the new cutoff as JSON `{"key", "date", "count"}`, e.g. for home automation
+#[cfg(feature = "webhook")]
+#[derive(Clone)]
+pub struct WebhookSink {
+    url: crate::http::Url,
+    key: String,
+    bearer_token: Option<String>,
+    /// Attempts after a failed request
+    retries: u32,
+    /// Delay before the first retry, doubled for each retry
+    retry_delay: std::time::Duration,
+    body: String,
+}
+#[cfg(feature = "webhook")]
+impl WebhookSink {
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    /// Posts the cutoff for the `key` to the `url`
+    pub fn new(url: crate::http::Url, key: String) -> Self {
+        Self {
+            url,
+            key,
+            bearer_token: None,
+            retries: 0,
+            retry_delay: std::time::Duration::from_secs(1),
+            body: String::new(),
+        }
+    }
+    /// Authenticates with the `bearer_token` (if any)
+    pub fn with_bearer_token(self, bearer_token: Option<String>) -> Self {
+        Self {
+            bearer_token,
+            ..self
+        }
+    }
+    /// Retries a failed request up to `retries` times, first after the `retry_delay`
+    pub fn with_retries(self, retries: u32, retry_delay: std::time::Duration) -> Self {
+        Self {
+            retries,
+            retry_delay,
+            ..self
+        }
+    }
+}
+#[cfg(feature = "webhook")]
+impl OutputSink for WebhookSink {
+    fn destination(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+    fn prepare(&mut self, output: &CutoffOutput<'_>) -> anyhow::Result<String> {
+        let body = serde_json::json!({
+            "key": self.key,
+            "date": output.stored().to_string(),
+            "count": output.count,
+        });
+        self.body = body.to_string();
+        Ok(format!("POST {} {}\n", self.url, self.body))
+    }
+    fn write(self: Box<Self>) -> anyhow::Result<()> {
+        let token = self.bearer_token.as_deref();
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match crate::http::post_json(&self.url, token, &self.body, Self::TIMEOUT) {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt < self.retries => {
+                    attempt += 1;
+                    println!(
+                        "[webhook failed: {err:#}, retry {attempt} of {} in {delay:?}]",
+                        self.retries
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Retained MQTT message announcing the cutoff, e.g. for a Home Assistant dashboard showing
+/// "since 2024-11-03 (68 tracks)"
+#[cfg(feature = "mqtt")]
+#[derive(Clone)]
+pub struct MqttSink {
+    broker: crate::mqtt::Broker,
+    credentials: Option<crate::mqtt::Credentials>,
+    topic: String,
+    key: String,
+    payload: String,
+}
+#[cfg(feature = "mqtt")]
+impl MqttSink {
+    const CLIENT_ID: &'static str = "beet_smart_cutoff";
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    /// Publishes the cutoff for the `key` on the `topic` of the `broker`
+    pub fn new(broker: crate::mqtt::Broker, topic: String, key: String) -> Self {
+        Self {
+            broker,
+            credentials: None,
+            topic,
+            key,
+            payload: String::new(),
+        }
+    }
+    /// Authenticates with the `credentials` (if any)
+    pub fn with_credentials(self, credentials: Option<crate::mqtt::Credentials>) -> Self {
+        Self {
+            credentials,
+            ..self
+        }
+    }
+}
+#[cfg(feature = "mqtt")]
+impl OutputSink for MqttSink {
+    fn destination(&self) -> String {
+        format!("MQTT topic {} on {}", self.topic, self.broker)
+    }
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+    fn prepare(&mut self, output: &CutoffOutput<'_>) -> anyhow::Result<String> {
+        let unit = output.unit.name();
+        let payload = serde_json::json!({
+            "key": self.key,
+            "date": output.stored().to_string(),
+            "count": output.count,
+            "unit": unit,
+            "summary": format!("since {} ({} {unit})", output.earliest, Grouped(output.count)),
+        });
+        self.payload = payload.to_string();
+        Ok(format!("publish {}: {}\n", self.topic, self.payload))
+    }
+    fn write(self: Box<Self>) -> anyhow::Result<()> {
+        crate::mqtt::publish_retained(
+            &self.broker,
+            Self::CLIENT_ID,
+            self.credentials.as_ref(),
+            &self.topic,
+            self.payload.as_bytes(),
+            Self::TIMEOUT,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepare_before_writing() {
+        let dir = std::env::temp_dir();
+        let rendered_path = dir.join(format!("sink_test_{}.txt", std::process::id()));
+        let missing_path = dir.join(format!("sink_test_{}.missing", std::process::id()));
+        let template = |text: &str| {
+            Template::new(text.to_owned(), TemplateSink::PLACEHOLDERS).expect("template")
+        };
+        let output = CutoffOutput {
+            earliest: "2024-03-02".parse().expect("date"),
+            boundary: Boundary::Exclusive,
+            count: 42,
+            unit: CountUnit::Tracks,
+            choice: "choice 2 (2024-03-02 10:00:00 Air - Moon Safari - Sexy Boy)",
+            query: None,
+        };
+
+        let mut sinks = Sinks::default();
+        sinks.push(TemplateSink::new(
+            template("{{cutoff_date}} {{final_count}}"),
+            missing_path.clone(),
+        ));
+        sinks.push(TemplateSink::new(
+            template("{{query}}"),
+            missing_path.clone(),
+        ));
+        let err = sinks.prepare(&output).err().expect("missing query");
+        assert!(format!("{err:#}").contains("missing value for placeholder {{query}}"));
+        assert!(!missing_path.exists());
+
+        let mut sinks = Sinks::default();
+        sinks.push(TemplateSink::new(
+            template("{{cutoff_date}} {{final_count}}"),
+            rendered_path.clone(),
+        ));
+        let dry_run = sinks.prepare(&output).expect("prepare").write(true);
+        assert_eq!(
+            dry_run.expect("dry run"),
+            std::slice::from_ref(&rendered_path)
+        );
+        assert!(!rendered_path.exists());
+
+        let mut sinks = Sinks::default();
+        sinks.push(TemplateSink::new(
+            template("{{cutoff_date}} {{final_count}}\n{{choice}}"),
+            rendered_path.clone(),
+        ));
+        sinks
+            .prepare(&output)
+            .expect("prepare")
+            .write(false)
+            .expect("write");
+        let rendered = std::fs::read_to_string(&rendered_path).expect("read rendered");
+        std::fs::remove_file(&rendered_path).expect("remove test file");
+        assert_eq!(
+            rendered,
+            "2024-03-01 42\nchoice 2 (2024-03-02 10:00:00 Air - Moon Safari - Sexy Boy)"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_metadata_keys() {
+        let path = std::env::temp_dir().join(format!("sink_test_{}.json", std::process::id()));
+        let output = CutoffOutput {
+            earliest: "2024-03-02".parse().expect("date"),
+            boundary: Boundary::Inclusive,
+            count: 42,
+            unit: CountUnit::Tracks,
+            choice: "choice 1",
+            query: None,
+        };
+        let json_file = crate::json::read_json_file(path.clone()).expect("missing file is empty");
+        let mut sinks = Sinks::default();
+        sinks.push(
+            JsonFileSink::new(
+                json_file,
+                "recent".to_owned(),
+                crate::json::OnExisting::Replace,
+            )
+            .with_metadata(true),
+        );
+        sinks
+            .prepare(&output)
+            .expect("prepare")
+            .write(false)
+            .expect("write");
+
+        let written = crate::json::read_json_file(path.clone()).expect("read written file");
+        std::fs::remove_file(&path).expect("remove test file");
+        let map = written.map.expect("object written");
+        assert_eq!(map["recent"], "2024-03-02");
+        assert_eq!(map["recent_count"], 42);
+        assert!(map["recent_updated"].is_string());
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn webhook_retries() {
+        use std::io::{BufRead as _, Read as _, Write as _};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("address").port();
+        let server = std::thread::spawn(move || {
+            ["503 Service Unavailable", "204 No Content"].map(|status| {
+                let (stream, _) = listener.accept().expect("accept");
+                let mut reader = std::io::BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).expect("read") > 2 {
+                    line.clear();
+                }
+                let mut body = [0; 48];
+                let length = reader.read(&mut body).expect("read body");
+                write!(reader.get_mut(), "HTTP/1.1 {status}\r\n\r\n").expect("respond");
+                String::from_utf8_lossy(&body[..length]).into_owned()
+            })
+        });
+        let url = format!("http://127.0.0.1:{port}/hook")
+            .parse()
+            .expect("url");
+        let mut sinks = Sinks::default();
+        sinks.push(
+            WebhookSink::new(url, "recent".to_owned()).with_retries(1, std::time::Duration::ZERO),
+        );
+        let output = CutoffOutput {
+            earliest: "2024-11-03".parse().expect("date"),
+            boundary: Boundary::Inclusive,
+            count: 68,
+            unit: CountUnit::Tracks,
+            choice: "choice 1",
+            query: None,
+        };
+        let files = sinks.prepare(&output).expect("prepare").write(false);
+        let bodies = server.join().expect("server");
+
+        assert_eq!(files.expect("posted"), Vec::<PathBuf>::new());
+        let expected = r#"{"key":"recent","date":"2024-11-03","count":68}"#;
+        assert_eq!(bodies, [expected, expected]);
+    }
+}
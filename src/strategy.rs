@@ -0,0 +1,682 @@
+use crate::{
+    constraint::{self, Constraints},
+    date::Window,
+    find_transition_with, find_transitions_around,
+    render::Grouped,
+    transitions_from, CompilationWeight, CountUnit, Date, DateEntry, Transition, TransitionOptions,
+};
+use std::str::FromStr;
+
+/// Heuristic proposing candidate breakpoints for the interactive selection
+pub trait CandidateStrategy {
+    /// Returns the candidates within the `items` (sorted newest first), interleaved with notes
+    /// about any omitted candidates
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>>;
+}
+
+/// Result of a [`CandidateStrategy`] for one candidate
+#[derive(Debug)]
+pub enum Outcome<'a> {
+    Candidate(Candidate<'a>),
+    /// Explanation of an omitted candidate, e.g. "skipping target: 50"
+    Note(String),
+    /// Explanation of a candidate beyond the oldest item, e.g. "out of range: 70" (which more
+    /// items may reach)
+    OutOfRange(String),
+}
+
+#[derive(Debug)]
+pub struct Candidate<'a> {
+    /// Short description of the candidate, e.g. the target count
+    pub label: String,
+    /// Target count (in the unit of the [`TransitionOptions`]), for strategies targeting counts
+    pub target: Option<usize>,
+    pub transition: Transition<'a>,
+}
+
+/// Suffix naming the unit of counts, omitted for tracks
+fn unit_suffix(unit: CountUnit) -> String {
+    match unit {
+        CountUnit::Tracks => String::new(),
+        unit => format!(" {}", unit.name()),
+    }
+}
+
+/// First transition exceeding each of the target counts
+#[derive(Clone, Debug)]
+pub struct FixedTargets {
+    pub counts: Vec<usize>,
+}
+impl CandidateStrategy for FixedTargets {
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>> {
+        let running_counts = options.running_counts(items);
+        let unit_suffix = unit_suffix(options.unit);
+        let mut prev_index = None;
+        self.counts
+            .iter()
+            .map(|&target_count| {
+                if prev_index
+                    .is_some_and(|prev_index: usize| running_counts[prev_index] > target_count)
+                {
+                    return Outcome::Note(format!("skipping target: {}", Grouped(target_count)));
+                }
+                match find_transition_with(items, target_count, options) {
+                    Some(transition) => {
+                        prev_index = Some(transition.index);
+                        Outcome::Candidate(Candidate {
+                            label: format!("{}{unit_suffix}", Grouped(target_count)),
+                            target: Some(target_count),
+                            transition,
+                        })
+                    }
+                    None => Outcome::OutOfRange(format!("out of range: {}", Grouped(target_count))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Transitions nearest to a single target count
+#[derive(Clone, Debug)]
+pub struct AroundTarget {
+    pub target_count: usize,
+    pub candidate_count: usize,
+}
+impl CandidateStrategy for AroundTarget {
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>> {
+        let Self {
+            target_count,
+            candidate_count,
+        } = *self;
+        let running_counts = options.running_counts(items);
+        let unit_suffix = unit_suffix(options.unit);
+        let transitions = find_transitions_around(items, target_count, candidate_count, options);
+        if transitions.is_empty() {
+            return vec![Outcome::OutOfRange(format!(
+                "out of range: {}",
+                Grouped(target_count)
+            ))];
+        }
+        transitions
+            .into_iter()
+            .map(|transition| {
+                let count = running_counts[transition.index];
+                Outcome::Candidate(Candidate {
+                    label: format!(
+                        "{}{unit_suffix} (target {})",
+                        Grouped(count),
+                        Grouped(target_count)
+                    ),
+                    target: Some(target_count),
+                    transition,
+                })
+            })
+            .collect()
+    }
+}
+
+/// First transition exceeding each percentage of the units in the fetched entries
+#[derive(Clone, Debug)]
+pub struct Percent {
+    pub percents: Vec<f64>,
+}
+impl CandidateStrategy for Percent {
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>> {
+        let total = options.running_counts(items).last().copied().unwrap_or(0);
+        let unit_suffix = unit_suffix(options.unit);
+        self.percents
+            .iter()
+            .map(|&percent| {
+                let target_count = (total as f64 * percent / 100.0).round() as usize;
+                match find_transition_with(items, target_count, options) {
+                    Some(transition) => Outcome::Candidate(Candidate {
+                        label: format!("{percent}% ({}{unit_suffix})", Grouped(target_count)),
+                        target: Some(target_count),
+                        transition,
+                    }),
+                    None => Outcome::Note(format!("out of range: {percent}%")),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Oldest transition with a total file size within each budget (e.g. to fit on a device)
+#[derive(Clone, Debug)]
+pub struct SizeBudget {
+    /// Budgets in bytes
+    pub budgets: Vec<u64>,
+}
+impl CandidateStrategy for SizeBudget {
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>> {
+        if items.iter().any(|entry| entry.filesize.is_none()) {
+            return vec![Outcome::Note(
+                "file sizes unknown for the entries".to_owned(),
+            )];
+        }
+        let running_sizes: Vec<u64> = items
+            .iter()
+            .scan(0, |total, entry| {
+                *total += entry.filesize.unwrap_or(0);
+                Some(*total)
+            })
+            .collect();
+        self.budgets
+            .iter()
+            .map(|&budget| {
+                let transition = transitions_from(items, 0, options.snap)
+                    .take_while(|transition| running_sizes[transition.index] <= budget)
+                    .last();
+                match transition {
+                    Some(transition) => Outcome::Candidate(Candidate {
+                        label: format!("size {}", format_size(budget)),
+                        target: None,
+                        transition,
+                    }),
+                    None => Outcome::Note(format!("out of range: size {}", format_size(budget))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Oldest transition within each rolling window ending on `today`
+#[derive(Clone, Debug)]
+pub struct Windows {
+    pub windows: Vec<Window>,
+    pub today: Date,
+}
+impl CandidateStrategy for Windows {
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>> {
+        self.windows
+            .iter()
+            .map(|&window| {
+                let constraints = Constraints {
+                    max_count: None,
+                    not_before: Some(window.start(self.today)),
+                };
+                match constraint::solve(items, constraints, options) {
+                    Ok(solution) => Outcome::Candidate(Candidate {
+                        label: format!("window {window}"),
+                        target: None,
+                        transition: solution.transition,
+                    }),
+                    Err(err) => Outcome::Note(format!("out of range: window {window} ({err})")),
+                }
+            })
+            .collect()
+    }
+}
+
+/// First transition exceeding each target count, which does not split an album
+#[derive(Clone, Debug)]
+pub struct AlbumAligned {
+    pub counts: Vec<usize>,
+}
+impl CandidateStrategy for AlbumAligned {
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>> {
+        let running_counts = options.running_counts(items);
+        let unit_suffix = unit_suffix(options.unit);
+        self.counts
+            .iter()
+            .map(|&target_count| {
+                let transition = running_counts
+                    .iter()
+                    .position(|&count| count > target_count)
+                    .and_then(|first_index| {
+                        transitions_from(items, first_index, options.snap).find(|transition| {
+                            transition.included.album_key() != transition.excluded.album_key()
+                        })
+                    });
+                match transition {
+                    Some(transition) => Outcome::Candidate(Candidate {
+                        label: format!("{}{unit_suffix}, album-aligned", Grouped(target_count)),
+                        target: Some(target_count),
+                        transition,
+                    }),
+                    None => Outcome::OutOfRange(format!("out of range: {}", Grouped(target_count))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// First transition exceeding each target count, with each unit weighted by `1 / (plays + 1)`,
+/// so rarely played items stay included longer than often played ones
+#[derive(Clone, Debug)]
+pub struct PlayWeighted {
+    pub counts: Vec<usize>,
+}
+impl CandidateStrategy for PlayWeighted {
+    fn candidates<'a>(
+        &self,
+        items: &'a [DateEntry],
+        options: TransitionOptions,
+    ) -> Vec<Outcome<'a>> {
+        if items.iter().all(|entry| entry.play_count.is_none()) {
+            return vec![Outcome::Note(
+                "play counts unknown for the entries (e.g. without the mpdstats plugin)".to_owned(),
+            )];
+        }
+        let compilations = options.compilations;
+        let running_counts = options.unit.running_counts_by(items, |item| {
+            let per_mille = if item.compilation {
+                compilations.per_mille
+            } else {
+                CompilationWeight::FULL
+            };
+            // unknown play counts are unplayed
+            let plays = item.play_count.unwrap_or(0);
+            per_mille / usize::try_from(plays).map_or(usize::MAX, |plays| plays.saturating_add(1))
+        });
+        let unit_suffix = unit_suffix(options.unit);
+        self.counts
+            .iter()
+            .map(|&target_count| {
+                let transition = running_counts
+                    .iter()
+                    .position(|&count| count > target_count)
+                    .and_then(|first_index| {
+                        transitions_from(items, first_index, options.snap).next()
+                    });
+                match transition {
+                    Some(transition) => Outcome::Candidate(Candidate {
+                        label: format!("{}{unit_suffix}, play-weighted", Grouped(target_count)),
+                        // the weighted counts differ from the counts shown
+                        target: None,
+                        transition,
+                    }),
+                    None => Outcome::OutOfRange(format!(
+                        "out of range: {}, play-weighted",
+                        Grouped(target_count)
+                    )),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Strategy and parameters selected on the command line, e.g. `percent:10,25,50`
+#[derive(Clone, Debug, PartialEq)]
+pub enum StrategySpec {
+    /// `targets:COUNT,...`
+    Targets(Vec<usize>),
+    /// `around:COUNT:N`
+    Around {
+        target_count: usize,
+        candidate_count: usize,
+    },
+    /// `percent:PERCENT,...`
+    Percent(Vec<f64>),
+    /// `size:SIZE,...` with an optional unit K, M, or G (powers of 1024)
+    SizeBudget(Vec<u64>),
+    /// `window:WINDOW,...`
+    Windows(Vec<Window>),
+    /// `album-aligned:COUNT,...`
+    AlbumAligned(Vec<usize>),
+    /// `plays:COUNT,...`
+    PlayWeighted(Vec<usize>),
+}
+impl StrategySpec {
+    /// Largest target count, for strategies targeting counts
+    pub fn largest_count(&self) -> Option<usize> {
+        match self {
+            StrategySpec::Targets(counts)
+            | StrategySpec::AlbumAligned(counts)
+            | StrategySpec::PlayWeighted(counts) => counts.iter().copied().max(),
+            StrategySpec::Around { target_count, .. } => Some(*target_count),
+            StrategySpec::Percent(_) | StrategySpec::SizeBudget(_) | StrategySpec::Windows(_) => {
+                None
+            }
+        }
+    }
+    pub fn build(self, today: Date) -> Box<dyn CandidateStrategy> {
+        match self {
+            StrategySpec::Targets(counts) => Box::new(FixedTargets { counts }),
+            StrategySpec::Around {
+                target_count,
+                candidate_count,
+            } => Box::new(AroundTarget {
+                target_count,
+                candidate_count,
+            }),
+            StrategySpec::Percent(percents) => Box::new(Percent { percents }),
+            StrategySpec::SizeBudget(budgets) => Box::new(SizeBudget { budgets }),
+            StrategySpec::Windows(windows) => Box::new(Windows { windows, today }),
+            StrategySpec::AlbumAligned(counts) => Box::new(AlbumAligned { counts }),
+            StrategySpec::PlayWeighted(counts) => Box::new(PlayWeighted { counts }),
+        }
+    }
+}
+impl std::fmt::Display for StrategySpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_list<T>(
+            f: &mut std::fmt::Formatter<'_>,
+            name: &str,
+            params: impl IntoIterator<Item = T>,
+        ) -> std::fmt::Result
+        where
+            T: std::fmt::Display,
+        {
+            write!(f, "{name}:")?;
+            for (index, param) in params.into_iter().enumerate() {
+                let separator = if index == 0 { "" } else { "," };
+                write!(f, "{separator}{param}")?;
+            }
+            Ok(())
+        }
+        match self {
+            StrategySpec::Targets(counts) => write_list(f, "targets", counts),
+            StrategySpec::Around {
+                target_count,
+                candidate_count,
+            } => write!(f, "around:{target_count}:{candidate_count}"),
+            StrategySpec::Percent(percents) => write_list(f, "percent", percents),
+            StrategySpec::SizeBudget(budgets) => {
+                write_list(f, "size", budgets.iter().map(|&bytes| format_size(bytes)))
+            }
+            StrategySpec::Windows(windows) => write_list(f, "window", windows),
+            StrategySpec::AlbumAligned(counts) => write_list(f, "album-aligned", counts),
+            StrategySpec::PlayWeighted(counts) => write_list(f, "plays", counts),
+        }
+    }
+}
+impl FromStr for StrategySpec {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        fn parse_list<T: FromStr>(params: &str) -> anyhow::Result<Vec<T>>
+        where
+            T::Err: Into<anyhow::Error>,
+        {
+            params
+                .split(',')
+                .map(|param| param.trim().parse().map_err(Into::into))
+                .collect()
+        }
+        let Some((name, params)) = s.split_once(':') else {
+            anyhow::bail!("invalid strategy {s:?}, expected NAME:PARAMS")
+        };
+        let spec = match name {
+            "targets" => Self::Targets(parse_list(params)?),
+            "around" => {
+                let Some((target_count, candidate_count)) = params.split_once(':') else {
+                    anyhow::bail!("invalid strategy {s:?}, expected around:COUNT:N")
+                };
+                Self::Around {
+                    target_count: target_count.parse()?,
+                    candidate_count: candidate_count.parse()?,
+                }
+            }
+            "percent" => Self::Percent(parse_list(params)?),
+            "size" => Self::SizeBudget(
+                params
+                    .split(',')
+                    .map(|param| parse_size(param.trim()))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            "window" => Self::Windows(parse_list(params)?),
+            "album-aligned" => Self::AlbumAligned(parse_list(params)?),
+            "plays" => Self::PlayWeighted(parse_list(params)?),
+            _ => anyhow::bail!(
+                "unknown strategy {name:?}, expected \"targets\", \"around\", \"percent\", \"size\", \"window\", \"album-aligned\", or \"plays\""
+            ),
+        };
+        Ok(spec)
+    }
+}
+
+/// Policy choosing one of the candidates without prompting, e.g. for a scheduled run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoSelect {
+    /// First candidate proposed by the strategy
+    First,
+    /// Candidate with the count nearest to its target
+    Closest,
+    /// Candidate with the largest count within the largest target
+    LargestUnderMax,
+}
+impl AutoSelect {
+    /// Returns the index of the chosen candidate, given the target (if any) and count of each
+    pub fn choose(self, candidates: &[(Option<usize>, usize)]) -> Option<usize> {
+        let targeted = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &(target, count))| Some((index, target?, count)));
+        match self {
+            AutoSelect::First => (!candidates.is_empty()).then_some(0),
+            AutoSelect::Closest => targeted
+                .min_by_key(|&(_, target, count)| target.abs_diff(count))
+                .map(|(index, _, _)| index),
+            AutoSelect::LargestUnderMax => {
+                let max = targeted.clone().map(|(_, target, _)| target).max()?;
+                targeted
+                    .filter(|&(_, _, count)| count <= max)
+                    .max_by_key(|&(index, _, count)| (count, std::cmp::Reverse(index)))
+                    .map(|(index, _, _)| index)
+            }
+        }
+    }
+}
+impl std::fmt::Display for AutoSelect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AutoSelect::First => "first",
+            AutoSelect::Closest => "closest",
+            AutoSelect::LargestUnderMax => "largest-under-max",
+        };
+        write!(f, "{name}")
+    }
+}
+impl FromStr for AutoSelect {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "first" => Ok(AutoSelect::First),
+            "closest" => Ok(AutoSelect::Closest),
+            "largest-under-max" => Ok(AutoSelect::LargestUnderMax),
+            _ => anyhow::bail!(
+                "unknown policy {s:?}, expected \"first\", \"closest\", or \"largest-under-max\""
+            ),
+        }
+    }
+}
+
+const SIZE_UNITS: &[(char, u64)] = &[('K', 1 << 10), ('M', 1 << 20), ('G', 1 << 30)];
+
+/// Parses a size in bytes, with an optional unit K, M, or G (powers of 1024)
+fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let (number, multiplier) = match SIZE_UNITS.iter().find(|(suffix, _)| s.ends_with(*suffix)) {
+        Some(&(_, multiplier)) => (&s[..s.len() - 1], multiplier),
+        None => (s, 1),
+    };
+    let number: u64 = number.parse().map_err(|_| {
+        anyhow::anyhow!("invalid size {s:?}, expected a number with unit K, M, or G")
+    })?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("size too large: {s:?}"))
+}
+fn format_size(bytes: u64) -> String {
+    SIZE_UNITS
+        .iter()
+        .rev()
+        .find(|(_, multiplier)| bytes >= *multiplier && bytes.is_multiple_of(*multiplier))
+        .map_or_else(
+            || bytes.to_string(),
+            |(suffix, multiplier)| format!("{}{suffix}", bytes / multiplier),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(lines: &[(&str, &str, u64)]) -> Vec<DateEntry> {
+        lines
+            .iter()
+            .map(|&(date, album, filesize)| DateEntry {
+                filesize: Some(filesize),
+                ..DateEntry::try_from(format!("{date} 10:00:00 Artist - {album} - Title"))
+                    .expect("valid entry in test case")
+            })
+            .collect()
+    }
+    fn cutoffs(outcomes: Vec<Outcome<'_>>) -> Vec<String> {
+        outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                Outcome::Candidate(candidate) => {
+                    format!("{}: {}", candidate.label, candidate.transition.cutoff)
+                }
+                Outcome::Note(note) | Outcome::OutOfRange(note) => note,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strategy_candidates() {
+        let items = entries(&[
+            ("2024-03-05", "A", 400),
+            ("2024-03-04", "B", 400),
+            ("2024-03-03", "B", 400),
+            ("2024-03-02", "C", 400),
+            ("2024-03-01", "C", 400),
+        ]);
+        let candidates = |spec: &str| {
+            let spec: StrategySpec = spec.parse().expect("valid spec in test case");
+            let today = "2024-03-06".parse().expect("valid date");
+            cutoffs(
+                spec.build(today)
+                    .candidates(&items, TransitionOptions::default()),
+            )
+        };
+
+        assert_eq!(
+            candidates("targets:1,3,9"),
+            ["1: 2024-03-04", "3: 2024-03-02", "out of range: 9"]
+        );
+        let spec: StrategySpec = "targets:9".parse().expect("valid spec");
+        let today = "2024-03-06".parse().expect("valid date");
+        assert!(matches!(
+            spec.build(today)
+                .candidates(&items, TransitionOptions::default())[..],
+            [Outcome::OutOfRange(_)]
+        ));
+        assert_eq!(candidates("percent:40"), ["40% (2): 2024-03-03"]);
+        assert_eq!(candidates("size:1K"), ["size 1K: 2024-03-04"]);
+        assert_eq!(candidates("window:3d"), ["window 3d: 2024-03-03"]);
+        assert_eq!(
+            candidates("album-aligned:1"),
+            ["1, album-aligned: 2024-03-03"]
+        );
+    }
+
+    #[test]
+    fn play_weighted_candidates() {
+        let mut items = entries(&[
+            ("2024-03-05", "A", 400),
+            ("2024-03-04", "B", 400),
+            ("2024-03-03", "B", 400),
+            ("2024-03-02", "C", 400),
+            ("2024-03-01", "C", 400),
+        ]);
+        let spec = PlayWeighted { counts: vec![1, 9] };
+        let candidates =
+            |items: &[DateEntry]| cutoffs(spec.candidates(items, TransitionOptions::default()));
+        assert_eq!(
+            candidates(&items),
+            ["play counts unknown for the entries (e.g. without the mpdstats plugin)"]
+        );
+
+        // the often played album B counts as a quarter per track
+        items[1].play_count = Some(3);
+        items[2].play_count = Some(3);
+        assert_eq!(
+            candidates(&items),
+            [
+                "1, play-weighted: 2024-03-02",
+                "out of range: 9, play-weighted"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_spec() {
+        assert_eq!(
+            "around:50:5".parse::<StrategySpec>().expect("valid spec"),
+            StrategySpec::Around {
+                target_count: 50,
+                candidate_count: 5
+            }
+        );
+        assert_eq!(
+            "size:4G,512".parse::<StrategySpec>().expect("valid spec"),
+            StrategySpec::SizeBudget(vec![4 << 30, 512])
+        );
+        for s in [
+            "targets:30,50,70",
+            "around:50:5",
+            "percent:12.5,50",
+            "size:4G,1536K,100",
+            "window:90d,1y",
+            "album-aligned:20",
+            "plays:30,50",
+        ] {
+            let spec: StrategySpec = s.parse().expect("valid spec");
+            assert_eq!(spec.to_string(), s);
+        }
+        for s in ["targets", "targets:x", "unknown:1", "around:5", "size:4T"] {
+            assert!(s.parse::<StrategySpec>().is_err(), "{s}");
+        }
+        let largest = |s: &str| {
+            s.parse::<StrategySpec>()
+                .expect("valid spec")
+                .largest_count()
+        };
+        assert_eq!(largest("targets:30,70,50"), Some(70));
+        assert_eq!(largest("around:50:5"), Some(50));
+        assert_eq!(largest("window:90d"), None);
+    }
+
+    #[test]
+    fn auto_select_policies() {
+        let policy = |s: &str| s.parse::<AutoSelect>().expect("valid policy");
+        let candidates = [(Some(30), 34), (None, 45), (Some(50), 51), (Some(70), 90)];
+
+        assert_eq!(policy("first").choose(&candidates), Some(0));
+        assert_eq!(policy("closest").choose(&candidates), Some(2));
+        assert_eq!(policy("largest-under-max").choose(&candidates), Some(2));
+        assert_eq!(policy("closest").choose(&[(None, 45)]), None);
+        assert_eq!(policy("first").choose(&[]), None);
+        assert_eq!(policy("largest-under-max").to_string(), "largest-under-max");
+        assert!("nearest".parse::<AutoSelect>().is_err());
+    }
+}
@@ -0,0 +1,121 @@
+/// Text with `{{name}}` placeholders, substituted by [`Template::render`]
+#[derive(Clone, Debug)]
+pub struct Template {
+    text: String,
+}
+impl Template {
+    /// Parses the `text`, checking each placeholder is one of the `known` names
+    pub fn new(text: String, known: &[&str]) -> anyhow::Result<Self> {
+        let template = Self { text };
+        for name in template.placeholders()? {
+            if !known.contains(&name) {
+                anyhow::bail!(
+                    "unknown placeholder {{{{{name}}}}}, expected one of: {}",
+                    known.join(", ")
+                );
+            }
+        }
+        Ok(template)
+    }
+    /// Returns the names of the placeholders, in order of appearance
+    fn placeholders(&self) -> anyhow::Result<Vec<&str>> {
+        self.segments()
+            .filter_map(|segment| match segment {
+                Ok(Segment::Placeholder(name)) => Some(Ok(name)),
+                Ok(Segment::Text(_)) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .collect()
+    }
+    /// Substitutes each placeholder with the value of the same name
+    pub fn render(&self, values: &[(&str, String)]) -> anyhow::Result<String> {
+        let mut rendered = String::with_capacity(self.text.len());
+        for segment in self.segments() {
+            match segment? {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Placeholder(name) => {
+                    let Some((_, value)) = values.iter().find(|(key, _)| *key == name) else {
+                        anyhow::bail!("missing value for placeholder {{{{{name}}}}}")
+                    };
+                    rendered.push_str(value);
+                }
+            }
+        }
+        Ok(rendered)
+    }
+    fn segments(&self) -> impl Iterator<Item = anyhow::Result<Segment<'_>>> {
+        let mut rest = self.text.as_str();
+        std::iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            let Some(start) = rest.find("{{") else {
+                let text = std::mem::take(&mut rest);
+                return Some(Ok(Segment::Text(text)));
+            };
+            if start > 0 {
+                let (text, remaining) = rest.split_at(start);
+                rest = remaining;
+                return Some(Ok(Segment::Text(text)));
+            }
+            let Some(end) = rest.find("}}") else {
+                rest = "";
+                return Some(Err(anyhow::anyhow!(
+                    "unterminated placeholder, missing }}}}"
+                )));
+            };
+            let name = rest[2..end].trim();
+            rest = &rest[end + 2..];
+            Some(Ok(Segment::Placeholder(name)))
+        })
+    }
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: &[&str] = &["cutoff_date", "final_count"];
+
+    #[test]
+    fn render_placeholders() {
+        let template = Template::new(
+            "since {{cutoff_date}}: {{ final_count }} items{{final_count}}\n".to_owned(),
+            KNOWN,
+        )
+        .expect("valid template");
+
+        let rendered = template
+            .render(&[
+                ("cutoff_date", "2024-03-01".to_owned()),
+                ("final_count", "42".to_owned()),
+            ])
+            .expect("all values present");
+        assert_eq!(rendered, "since 2024-03-01: 42 items42\n");
+
+        let err = template
+            .render(&[("cutoff_date", "2024-03-01".to_owned())])
+            .expect_err("missing value");
+        assert_eq!(
+            err.to_string(),
+            "missing value for placeholder {{final_count}}"
+        );
+    }
+
+    #[test]
+    fn invalid_placeholders() {
+        let err = Template::new("{{query}}".to_owned(), KNOWN).expect_err("unknown placeholder");
+        assert_eq!(
+            err.to_string(),
+            "unknown placeholder {{query}}, expected one of: cutoff_date, final_count"
+        );
+
+        let err = Template::new("a {{cutoff_date".to_owned(), KNOWN).expect_err("unterminated");
+        assert_eq!(err.to_string(), "unterminated placeholder, missing }}");
+    }
+}
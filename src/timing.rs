@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+/// Accumulated durations of the named phases of a run
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+}
+impl Timings {
+    /// Runs `f`, adding its duration to the `phase`
+    pub fn record<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(phase, start.elapsed());
+        result
+    }
+    /// Adds the `duration` to the `phase` (appending new phases in order of first appearance)
+    pub fn add(&mut self, phase: &'static str, duration: Duration) {
+        if let Some((_, total)) = self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            *total += duration;
+        } else {
+            self.phases.push((phase, duration));
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.phases.iter().copied()
+    }
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        let map = self
+            .iter()
+            .map(|(phase, duration)| {
+                let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+                (phase.to_owned(), millis.into())
+            })
+            .collect();
+        serde_json::Value::Object(map)
+    }
+}
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.iter().map(|(phase, _)| phase.len()).max().unwrap_or(0);
+        for (phase, duration) in self.iter() {
+            writeln!(f, "    {phase:<width$}  {:>8.3}s", duration.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+//! Record of an interactive session as JSON lines, for reviewing why a cutoff was chosen (and
+//! replaying the responses against the current library, see [`Replay`])
+
+use crate::{date::Date, Timestamp};
+use anyhow::Context as _;
+use std::{collections::VecDeque, io::Write as _, path::Path, sync::Mutex};
+
+/// Step of an interactive session
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Session started at the `timestamp` (UTC)
+    Start { timestamp: Timestamp },
+    /// Table of candidates shown
+    Candidates { table: String },
+    /// Response entered at a prompt
+    Response { prompt: String, response: String },
+    /// Cutoff chosen (as stored), with the count of units on or after it
+    Decision {
+        cutoff: Date,
+        count: usize,
+        choice: String,
+    },
+    /// Selection abandoned
+    Quit,
+}
+impl Event {
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::Start { timestamp } => serde_json::json!({
+                "event": "start",
+                "timestamp_utc": timestamp.to_string(),
+            }),
+            Self::Candidates { table } => serde_json::json!({
+                "event": "candidates",
+                "table": table,
+            }),
+            Self::Response { prompt, response } => serde_json::json!({
+                "event": "response",
+                "prompt": prompt,
+                "response": response,
+            }),
+            Self::Decision {
+                cutoff,
+                count,
+                choice,
+            } => serde_json::json!({
+                "event": "decision",
+                "cutoff": cutoff.to_string(),
+                "count": count,
+                "choice": choice,
+            }),
+            Self::Quit => serde_json::json!({ "event": "quit" }),
+        }
+    }
+    /// Parses an event written by [`Self::to_json`]
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        let field = |name: &str| {
+            value
+                .get(name)
+                .with_context(|| format!("missing field {name:?}"))
+        };
+        let string = |name: &str| {
+            field(name)?
+                .as_str()
+                .map(str::to_owned)
+                .with_context(|| format!("expected a string for field {name:?}"))
+        };
+        let event = match string("event")?.as_str() {
+            "start" => Self::Start {
+                timestamp: string("timestamp_utc")?.parse()?,
+            },
+            "candidates" => Self::Candidates {
+                table: string("table")?,
+            },
+            "response" => Self::Response {
+                prompt: string("prompt")?,
+                response: string("response")?,
+            },
+            "decision" => Self::Decision {
+                cutoff: string("cutoff")?.parse()?,
+                count: field("count")?
+                    .as_u64()
+                    .and_then(|count| usize::try_from(count).ok())
+                    .context("expected a count")?,
+                choice: string("choice")?,
+            },
+            "quit" => Self::Quit,
+            event => anyhow::bail!("unknown event {event:?}"),
+        };
+        Ok(event)
+    }
+}
+
+/// Transcript file, written as each event occurs (to keep the events of an interrupted session)
+#[derive(Debug)]
+pub struct Transcript {
+    file: Mutex<std::fs::File>,
+}
+impl Transcript {
+    /// Creates (or truncates) the transcript file at the `path`, recording the start
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(path).with_context(|| format!("creating {path:?}"))?;
+        let transcript = Self {
+            file: Mutex::new(file),
+        };
+        transcript.record(&Event::Start {
+            timestamp: Timestamp::now(),
+        })?;
+        Ok(transcript)
+    }
+    /// Appends the `event` as a JSON line
+    pub fn record(&self, event: &Event) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(&event.to_json())?;
+        line.push('\n');
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Recorded session, with the responses replayed in order against the current library
+#[derive(Debug, Default)]
+pub struct Replay {
+    /// Prompt and response, in order
+    responses: VecDeque<(String, String)>,
+    candidates: VecDeque<String>,
+    /// Final decision (or [`Event::Quit`]), if recorded
+    outcome: Option<Event>,
+}
+impl Replay {
+    /// Parses the JSON lines of a transcript
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut replay = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let event = serde_json::from_str(line)
+                .map_err(anyhow::Error::from)
+                .and_then(|value| Event::from_json(&value))
+                .with_context(|| format!("invalid event on line {line_number}"))?;
+            match event {
+                Event::Start { .. } => {}
+                Event::Candidates { table } => replay.candidates.push_back(table),
+                Event::Response { prompt, response } => {
+                    replay.responses.push_back((prompt, response));
+                }
+                Event::Decision { .. } | Event::Quit => replay.outcome = Some(event),
+            }
+        }
+        Ok(replay)
+    }
+    /// Takes the next recorded prompt and response, if any
+    pub fn next_response(&mut self) -> Option<(String, String)> {
+        self.responses.pop_front()
+    }
+    /// Takes the next recorded table of candidates, returning it if it differs from the `table`
+    pub fn check_candidates(&mut self, table: &str) -> Option<String> {
+        self.candidates
+            .pop_front()
+            .filter(|recorded| recorded != table)
+    }
+    /// Fails if the `cutoff` and `count` differ from the recorded decision
+    pub fn check_decision(&self, cutoff: Date, count: usize) -> anyhow::Result<()> {
+        match &self.outcome {
+            Some(Event::Decision {
+                cutoff: recorded_cutoff,
+                count: recorded_count,
+                choice,
+            }) if (*recorded_cutoff, *recorded_count) != (cutoff, count) => anyhow::bail!(
+                "the replayed responses yield a different result\n\
+                 - recorded: cutoff {recorded_cutoff}, {recorded_count} entries, from {choice}\n\
+                 + replayed: cutoff {cutoff}, {count} entries"
+            ),
+            Some(Event::Quit) => anyhow::bail!("the recorded session quit without a decision"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_events() {
+        let path =
+            std::env::temp_dir().join(format!("transcript_test_{}.jsonl", std::process::id()));
+        let transcript = Transcript::create(&path).expect("create");
+        let events = [
+            Event::Candidates {
+                table: "1  30  2024-03-02\n".to_owned(),
+            },
+            Event::Response {
+                prompt: "Enter selection:".to_owned(),
+                response: "1".to_owned(),
+            },
+            Event::Decision {
+                cutoff: "2024-03-02".parse().expect("date"),
+                count: 31,
+                choice: "choice 1".to_owned(),
+            },
+        ];
+        for event in &events {
+            transcript.record(event).expect("record");
+        }
+        drop(transcript);
+
+        let text = std::fs::read_to_string(&path).expect("read transcript");
+        std::fs::remove_file(&path).expect("remove test file");
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with(r#"{"event":"start","timestamp_utc":"#));
+        assert_eq!(
+            lines[1..],
+            [
+                r#"{"event":"candidates","table":"1  30  2024-03-02\n"}"#,
+                r#"{"event":"response","prompt":"Enter selection:","response":"1"}"#,
+                r#"{"event":"decision","cutoff":"2024-03-02","count":31,"choice":"choice 1"}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn replay_responses() {
+        let text = [
+            r#"{"event":"start","timestamp_utc":"2024-03-05 10:00:00"}"#,
+            r#"{"event":"candidates","table":"1  30\n"}"#,
+            r#"{"event":"response","prompt":"Enter selection:","response":"c"}"#,
+            r#"{"event":"response","prompt":"Enter custom targets:","response":"40"}"#,
+            r#"{"event":"candidates","table":"1  40\n"}"#,
+            r#"{"event":"response","prompt":"Enter selection:","response":"1"}"#,
+            r#"{"event":"decision","cutoff":"2024-03-02","count":41,"choice":"choice 1"}"#,
+        ]
+        .join("\n");
+        let mut replay = Replay::parse(&text).expect("valid transcript");
+
+        assert_eq!(replay.check_candidates("1  30\n"), None);
+        let responses: Vec<_> = std::iter::from_fn(|| replay.next_response())
+            .map(|(_, response)| response)
+            .collect();
+        assert_eq!(responses, ["c", "40", "1"]);
+        assert_eq!(
+            replay.check_candidates("1  45\n"),
+            Some("1  40\n".to_owned())
+        );
+        let date = |date: &str| date.parse().expect("date");
+        assert!(replay.check_decision(date("2024-03-02"), 41).is_ok());
+        let err = replay
+            .check_decision(date("2024-03-03"), 45)
+            .expect_err("different result");
+        assert!(err
+            .to_string()
+            .ends_with("+ replayed: cutoff 2024-03-03, 45 entries"));
+        assert!(Replay::parse(r#"{"event":"bogus"}"#).is_err());
+    }
+}
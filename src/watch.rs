@@ -0,0 +1,128 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Detects modifications of a file (e.g. the beets library database) by polling its modification
+/// time, including the SQLite write-ahead log (`-wal`) next to it
+#[derive(Debug)]
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+impl FileWatcher {
+    /// Starts watching the file, reporting the first poll as a modification
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            modified: None,
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Returns true if the file was modified since the previous poll
+    pub fn poll(&mut self) -> std::io::Result<bool> {
+        let modified = modified_with_wal(&self.path)?;
+        let changed = self.modified != Some(modified);
+        self.modified = Some(modified);
+        Ok(changed)
+    }
+}
+
+/// Latest modification time of the SQLite database at the `path`, or of its write-ahead log
+pub fn modified_with_wal(path: &Path) -> std::io::Result<SystemTime> {
+    let mut modified = std::fs::metadata(path)?.modified()?;
+    let mut wal_path = path.to_owned().into_os_string();
+    wal_path.push("-wal");
+    if let Ok(wal_modified) = std::fs::metadata(wal_path).and_then(|wal| wal.modified()) {
+        modified = modified.max(wal_modified);
+    }
+    Ok(modified)
+}
+
+/// Delays an action until no triggers occur for the `delay`, coalescing bursts of triggers (e.g.
+/// the library changes during a long import session)
+#[derive(Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    last_trigger: Option<Instant>,
+}
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            last_trigger: None,
+        }
+    }
+    /// Restarts the delay
+    pub fn trigger(&mut self, now: Instant) {
+        self.last_trigger = Some(now);
+    }
+    pub fn is_pending(&self) -> bool {
+        self.last_trigger.is_some()
+    }
+    /// Returns true (once per burst of triggers) when the delay has passed since the last trigger
+    pub fn take_ready(&mut self, now: Instant) -> bool {
+        match self.last_trigger {
+            Some(last_trigger) if now.saturating_duration_since(last_trigger) >= self.delay => {
+                self.last_trigger = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn poll_modifications() {
+        let path = std::env::temp_dir().join(format!("watch_test_{}.db", std::process::id()));
+        std::fs::write(&path, "v1").expect("write test file");
+        let set_modified = |secs| {
+            let file = std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .expect("open test file");
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .expect("set modified time");
+        };
+        set_modified(1_000);
+        let mut watcher = FileWatcher::new(path.clone());
+
+        let first = watcher.poll().expect("poll");
+        let unchanged = watcher.poll().expect("poll");
+        set_modified(2_000);
+        let changed = watcher.poll().expect("poll");
+        std::fs::remove_file(&path).expect("remove test file");
+        let removed = watcher.poll();
+
+        assert!(first);
+        assert!(!unchanged);
+        assert!(changed);
+        assert!(removed.is_err());
+    }
+
+    #[test]
+    fn debounce_bursts() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut debouncer = Debouncer::new(Duration::from_secs(300));
+        assert!(!debouncer.take_ready(at(0)));
+
+        debouncer.trigger(at(0));
+        debouncer.trigger(at(200));
+        assert!(!debouncer.take_ready(at(400)));
+        assert!(debouncer.is_pending());
+        assert!(debouncer.take_ready(at(500)));
+        assert!(!debouncer.take_ready(at(600)));
+
+        let mut immediate = Debouncer::new(Duration::ZERO);
+        immediate.trigger(at(0));
+        assert!(immediate.take_ready(at(0)));
+    }
+}